@@ -23,7 +23,7 @@ fn main() {
     // create a transfomation matrix that maps from -1..1 to the window size
     let window_size = PhysicalSize::new(2200, 2200);
 
-    let transform = -nalgebra::Matrix3::from([
+    let _transform = -nalgebra::Matrix3::from([
         [2.0 / window_size.width as f32, 0.0, 0.0],
         [0.0, 2.0 / window_size.height as f32, 0.0],
         [-1.0, -1.0, 1.0],
@@ -40,7 +40,7 @@ fn main() {
     );
 
     // create a blue circle
-    let g1 = Geom::new(
+    let _g1 = Geom::new(
         Primitive::Circle {
             center: Point2D::new(0.0, 0.0),
             radius: 100.0,
@@ -48,13 +48,14 @@ fn main() {
         Material::Colour(Colour::new(0.0, 1.0, 0.0, 1.0)),
         None,
         vec![],
-        TessellationOptions::Fill,
+        TessellationOptions::simple_fill(),
     );
 
-    let g2 = Geom::new(
+    let _g2 = Geom::new(
         Primitive::Rectangle {
             a: Point2D::new(-100.0, -100.0),
             b: Point2D::new(800.0, 700.0),
+            rotation: 0.0,
         },
         Material::Texture(TextureMaterial {
             texture: texture1,
@@ -66,24 +67,26 @@ fn main() {
         }),
         None,
         vec![],
-        TessellationOptions::Fill,
+        TessellationOptions::simple_fill(),
     );
 
     let g2a = Geom::new(
         Primitive::Rectangle {
             a: Point2D::new(-300.0, -300.0),
             b: Point2D::new(300.0, 300.0),
+            rotation: 0.0,
         },
         Material::Colour(Colour::LIGHTGREY),
         None,
         vec![],
-        TessellationOptions::Fill,
+        TessellationOptions::simple_fill(),
     );
 
     let g2b = Geom::new(
         Primitive::Rectangle {
             a: Point2D::new(-300.0, -300.0),
             b: Point2D::new(300.0, 300.0),
+            rotation: 0.0,
         },
         Material::Colour(Colour::RED),
         None,
@@ -95,6 +98,7 @@ fn main() {
         Primitive::Ellipse {
             center: Point2D::new(-400.0, -200.0),
             radii: Vector2::new(300.0, 800.0),
+            rotation: 0.0,
         },
         Material::Texture(TextureMaterial {
             texture: texture2,
@@ -106,7 +110,7 @@ fn main() {
         }),
         None,
         vec![],
-        TessellationOptions::Fill,
+        TessellationOptions::simple_fill(),
     );
 
     let g4 = Geom::new(
@@ -193,9 +197,9 @@ impl ApplicationHandler for App {
                         ..
                     },
                 ..
-            } => match key_code {
-                _key => {}
-            },
+            } => {
+                let _key = key_code;
+            }
             _evt => {}
         };
 
@@ -252,7 +256,9 @@ impl GPUState {
                 .request_device(
                     &wgpu::DeviceDescriptor {
                         label: None,
-                        required_features: wgpu::Features::default(),
+                        // textures that do not repeat need border colours
+                        required_features: adapter.features()
+                            & wgpu::Features::ADDRESS_MODE_CLAMP_TO_BORDER,
                         required_limits: wgpu::Limits::default(),
                     },
                     None,
@@ -296,7 +302,7 @@ impl GPUState {
         };
 
         // change the colour of geom 2
-        if i % 2 == 0 {
+        if i.is_multiple_of(2) {
             geoms[2].material = Material::Colour(Colour::RED);
         } else {
             geoms[2].material = Material::Colour(Colour::BLUE);
@@ -314,13 +320,24 @@ impl GPUState {
 
         // draw the primitives
         let t0 = Instant::now();
-        let rd = self.renderer.prepare(
-            &mut self.device,
-            &mut self.queue,
-            &self.surface_desc,
-            &geoms,
-        );
+        let rd = match self
+            .renderer
+            .prepare(&self.device, &self.queue, &self.surface_desc, geoms)
+        {
+            Ok(rd) => rd,
+            Err(e) => {
+                println!("Failed to prepare frame: {e}");
+                return;
+            }
+        };
+        for (index, e) in &rd.errors {
+            println!("Failed to prepare geom {index}: {e}");
+        }
         let t1 = Instant::now();
+        if let Err(e) = self.renderer.render_filter_layers(&mut encoder, &rd) {
+            println!("Failed to render filter layers: {e}");
+            return;
+        }
         {
             // A resolve target is only supported if the attachment actually uses anti-aliasing
             // So if sample_count == 1 then we must render directly to the surface's buffer
@@ -341,7 +358,9 @@ impl GPUState {
                 occlusion_query_set: None,
             });
 
-            self.renderer.render(&mut pass, &self.device, geoms, &rd);
+            if let Err(e) = self.renderer.render(&mut pass, &rd) {
+                println!("Failed to render frame: {e}");
+            }
         }
 
         // submit the commands
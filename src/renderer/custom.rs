@@ -0,0 +1,307 @@
+use std::any::{type_name, TypeId};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use super::error::RendererError;
+use super::helpers::{CacheEntry, Cacheable};
+use super::material::{TextureFilter, TextureRepeat};
+use super::pipeline::SamplerKey;
+use super::texture::Texture;
+
+/// The per-geom uniforms of a custom material with parameters.
+const UNIFORMS_WITH_PARAMS: &str = "
+struct Uniforms {
+    transform: mat4x4<f32>,
+    bbox: BBox,
+    filters: FilterRange,
+    params: Params,
+};
+
+// returns the parameters of the geom
+fn params(in: VertexOutput) -> Params {
+    return geoms[in.geom_index].params;
+}
+";
+
+/// The per-geom uniforms of a custom material without parameters.
+const UNIFORMS_WITHOUT_PARAMS: &str = "
+struct Uniforms {
+    transform: mat4x4<f32>,
+    bbox: BBox,
+    filters: FilterRange,
+};
+";
+
+/// The id of the next custom shader.
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A fragment shader written in WGSL, which can be used by custom materials. The source is
+/// combined with the shared declarations and the shared vertex shader, and must define
+///
+/// ```wgsl
+/// fn material(in: VertexOutput) -> vec4<f32>
+/// ```
+///
+/// which returns the (straight) colour of a fragment, before the tint and the pixel filters of the
+/// geom are applied. `in.position_org` is the position in the coordinates of the primitive, and
+/// `geoms[in.geom_index].bbox` is the bounding box of the primitive.
+///
+/// If the parameters of the material are not empty, the source must also declare a struct
+/// `Params` with the same layout as the parameters, which is returned by `params(in)`. The
+/// texture of the material (if any) is available as `texture` with the sampler `texture_sampler`.
+///
+/// Cloning a shader is cheap, and all clones share the same pipelines. The pipelines are removed
+/// from the renderer once the last clone is dropped.
+#[derive(Clone, Debug)]
+pub struct CustomShader(Arc<CustomShaderInner>);
+
+#[derive(Debug)]
+struct CustomShaderInner {
+    /// The id of the shader, which identifies its pipelines.
+    id: u64,
+    /// The cache entry of the shader, which tells the renderer when the shader is dropped.
+    cache_entry: CacheEntry,
+    /// The complete source of the shader module.
+    source: String,
+    /// The type of the parameters.
+    params_type: TypeId,
+    /// The size of the parameters in bytes.
+    params_size: usize,
+    /// Whether the shader samples the texture.
+    uses_texture: bool,
+}
+
+impl PartialEq for CustomShader {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.id == other.0.id
+    }
+}
+
+impl Cacheable for CustomShader {
+    fn cache_id(&self) -> CacheEntry {
+        self.0.cache_entry.clone()
+    }
+}
+
+impl CustomShader {
+    /// Creates a shader from the given WGSL source, whose materials have parameters of type `P`
+    /// (use `()` for materials without parameters). The shader is validated immediately, so that
+    /// errors in the source and parameters that do not match `Params` are reported here rather
+    /// than when rendering.
+    pub fn new<P: bytemuck::Pod>(source: &str) -> Result<Self, RendererError> {
+        let params_size = std::mem::size_of::<P>();
+        let uniforms = if params_size > 0 {
+            UNIFORMS_WITH_PARAMS
+        } else {
+            UNIFORMS_WITHOUT_PARAMS
+        };
+        let source = [
+            include_str!("shaders/common.wgsl"),
+            include_str!("shaders/vertex.wgsl"),
+            uniforms,
+            include_str!("shaders/custom.wgsl"),
+            source,
+        ]
+        .join("\n");
+
+        let module = naga::front::wgsl::parse_str(&source)
+            .map_err(|error| RendererError::InvalidShader(error.emit_to_string(&source)))?;
+        let info = naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::empty(),
+        )
+        .validate(&module)
+        .map_err(|error| RendererError::InvalidShader(error.emit_to_string(&source)))?;
+
+        // the parameters must have the same size as `Params` in WGSL, including the padding
+        if params_size > 0 {
+            let wgsl_size = module
+                .types
+                .iter()
+                .find(|(_, ty)| ty.name.as_deref() == Some("Params"))
+                .map_or(0, |(_, ty)| ty.inner.size(module.to_ctx()) as usize);
+            if wgsl_size != params_size {
+                return Err(RendererError::InvalidParams {
+                    expected: wgsl_size,
+                    actual: params_size,
+                });
+            }
+        }
+
+        // the texture is only bound if the fragment shader uses it
+        let fragment = module
+            .entry_points
+            .iter()
+            .position(|entry_point| entry_point.name == "fs_main")
+            .expect("Custom shader has no fragment entry point. This should not happen.");
+        let uses_texture = module
+            .global_variables
+            .iter()
+            .find(|(_, global)| global.name.as_deref() == Some("texture"))
+            .is_some_and(|(handle, _)| !info.get_entry_point(fragment)[handle].is_empty());
+
+        Ok(Self(Arc::new(CustomShaderInner {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            cache_entry: CacheEntry::new(),
+            source,
+            params_type: TypeId::of::<P>(),
+            params_size,
+            uses_texture,
+        })))
+    }
+
+    /// Returns the id of the shader, which identifies its pipelines.
+    pub fn id(&self) -> u64 {
+        self.0.id
+    }
+
+    /// Returns the complete source of the shader module.
+    pub fn source(&self) -> &str {
+        &self.0.source
+    }
+
+    /// Returns the size of the parameters of its materials in bytes.
+    pub fn params_size(&self) -> usize {
+        self.0.params_size
+    }
+
+    /// Returns true if the shader samples the texture of its materials.
+    pub fn uses_texture(&self) -> bool {
+        self.0.uses_texture
+    }
+}
+
+/// A material with a custom fragment shader.
+#[derive(Clone, Debug)]
+pub struct CustomMaterial {
+    /// The shader.
+    pub shader: CustomShader,
+    /// The texture that is bound to the shader. This is required if the shader samples it.
+    pub texture: Option<Texture>,
+    /// How the texture repeats.
+    pub repeat: TextureRepeat,
+    /// How the texture is filtered.
+    pub filter: TextureFilter,
+    /// The parameters, which match the `Params` struct of the shader.
+    params: Vec<u8>,
+}
+
+impl CustomMaterial {
+    /// Creates a material with the given shader and parameters. The parameters must have the type
+    /// that the shader was created with.
+    pub fn new<P: bytemuck::Pod>(shader: &CustomShader, params: &P) -> Result<Self, RendererError> {
+        let mut material = Self {
+            shader: shader.clone(),
+            texture: None,
+            repeat: TextureRepeat::Clamp,
+            filter: TextureFilter::Linear,
+            params: vec![],
+        };
+        material.set_params(params)?;
+        Ok(material)
+    }
+
+    /// Sets the texture that is bound to the shader.
+    pub fn with_texture(
+        self,
+        texture: Texture,
+        repeat: TextureRepeat,
+        filter: TextureFilter,
+    ) -> Self {
+        Self {
+            texture: Some(texture),
+            repeat,
+            filter,
+            ..self
+        }
+    }
+
+    /// Replaces the parameters. The parameters must have the type that the shader was created
+    /// with.
+    pub fn set_params<P: bytemuck::Pod>(&mut self, params: &P) -> Result<(), RendererError> {
+        if TypeId::of::<P>() != self.shader.0.params_type {
+            return Err(RendererError::InvalidParamsType(type_name::<P>()));
+        }
+        self.params = bytemuck::bytes_of(params).to_vec();
+        Ok(())
+    }
+
+    /// Returns the parameters as bytes.
+    pub fn params(&self) -> &[u8] {
+        &self.params
+    }
+
+    /// Returns the texture, if the shader samples it.
+    pub fn texture(&self) -> Option<&Texture> {
+        self.texture.as_ref().filter(|_| self.shader.uses_texture())
+    }
+
+    /// Returns the state of the sampler, if the shader samples the texture.
+    pub fn sampler_key(&self) -> Option<SamplerKey> {
+        self.shader.uses_texture().then(|| SamplerKey {
+            address_mode_u: self.repeat.address_mode(),
+            address_mode_v: self.repeat.address_mode(),
+            filter: self.filter.filter_mode(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shaders_are_validated() {
+        // errors in the source are reported when the shader is created
+        for source in [
+            "fn material(in: VertexOutput) -> vec4<f32> {",
+            "fn material(in: VertexOutput) -> vec4<f32> { return vec3<f32>(1.0); }",
+            "fn colour(in: VertexOutput) -> vec4<f32> { return vec4<f32>(1.0); }",
+        ] {
+            assert!(matches!(
+                CustomShader::new::<()>(source),
+                Err(RendererError::InvalidShader(_))
+            ));
+        }
+
+        // the parameters must match `Params` including its padding
+        let source = "
+            struct Params {
+                colour: vec4<f32>,
+                frequency: f32,
+            };
+
+            fn material(in: VertexOutput) -> vec4<f32> {
+                return params(in).colour * params(in).frequency;
+            }
+        ";
+        assert!(matches!(
+            CustomShader::new::<[f32; 5]>(source),
+            Err(RendererError::InvalidParams {
+                expected: 32,
+                actual: 20
+            })
+        ));
+        let shader = CustomShader::new::<[f32; 8]>(source).unwrap();
+        assert!(!shader.uses_texture());
+        assert_eq!(shader.params_size(), 32);
+
+        // materials must use the type the shader was created with
+        assert!(matches!(
+            CustomMaterial::new(&shader, &[0u32; 8]),
+            Err(RendererError::InvalidParamsType(_))
+        ));
+        let material = CustomMaterial::new(&shader, &[1.0f32; 8]).unwrap();
+        assert_eq!(material.params(), bytemuck::bytes_of(&[1.0f32; 8]));
+    }
+
+    #[test]
+    fn shaders_have_unique_ids() {
+        let source = "fn material(in: VertexOutput) -> vec4<f32> { return vec4<f32>(1.0); }";
+        let shader = CustomShader::new::<()>(source).unwrap();
+        let other = CustomShader::new::<()>(source).unwrap();
+        assert_ne!(shader.id(), other.id());
+        assert_ne!(shader, other);
+        assert_eq!(shader, shader.clone());
+    }
+}
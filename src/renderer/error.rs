@@ -0,0 +1,86 @@
+/// Errors that can occur while preparing geoms for rendering.
+#[derive(Debug, thiserror::Error)]
+pub enum RendererError {
+    /// Lyon failed to tessellate a primitive, e.g. because it contains NaN coordinates.
+    #[error("failed to tessellate primitive: {0}")]
+    Tessellation(#[from] lyon::tessellation::TessellationError),
+    /// A primitive contains NaN or infinite coordinates, or is a compound without contours.
+    #[error("primitive contains non-finite values")]
+    NonFinitePrimitive,
+    /// The tessellation options do not match the requested kind of tessellation.
+    #[error("invalid tessellation options: expected {expected} options")]
+    InvalidTessellationOptions { expected: &'static str },
+    /// The primitive cannot be tessellated with the requested kind of tessellation, e.g. because
+    /// a line has no interior to fill.
+    #[error("{primitive} primitives cannot be tessellated with {kind} options")]
+    UnsupportedTessellation {
+        primitive: &'static str,
+        kind: &'static str,
+    },
+    /// A dash pattern is too fine to be tessellated.
+    #[error("invalid dash pattern: {reason}")]
+    InvalidDashPattern { reason: &'static str },
+
+    /// SVG path data could not be parsed.
+    #[error("invalid path data: {0}")]
+    InvalidPath(String),
+    /// A texture has a width or height of zero.
+    #[error("texture has an invalid size of {width}x{height}")]
+    InvalidTextureSize { width: u32, height: u32 },
+    /// The data of a texture does not match its size and format.
+    #[error("texture data has {actual} bytes, but {expected} bytes were expected")]
+    InvalidTextureData { expected: usize, actual: usize },
+    /// An image with a different size was passed to `Texture::update_image`.
+    #[error("image size {actual:?} does not match texture size {expected:?}")]
+    TextureSizeMismatch {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    /// `Texture::update_image` was called on a texture that is not backed by an 8-bit image.
+    #[error("texture is not backed by an 8-bit image")]
+    NotAnImageTexture,
+    /// A texture was used before it was added to the renderer.
+    #[error("texture has not been added to the renderer")]
+    MissingTexture,
+    /// A texture was used with a material that does not have a texture binding.
+    #[error("material does not have a texture binding")]
+    MissingTextureBinding,
+    /// The kernel of a convolution filter does not match its size.
+    #[error("convolution kernel has {actual} values, but {expected} values were expected")]
+    InvalidKernel { expected: usize, actual: usize },
+    /// The standard deviation of a blur is not finite or exceeds `MAX_BLUR_SIGMA`.
+    #[error("blur has an invalid standard deviation of {sigma}")]
+    InvalidBlur { sigma: f32 },
+    /// The source of a custom shader failed to parse or to validate.
+    #[error("invalid shader:\n{0}")]
+    InvalidShader(String),
+    /// The parameters of a custom shader do not have the size of its `Params` struct.
+    #[error("shader parameters have {actual} bytes, but `Params` has {expected} bytes")]
+    InvalidParams { expected: usize, actual: usize },
+    /// The parameters of a custom material do not have the type its shader was created with.
+    #[error("shader parameters of type {0} do not match the type of the shader")]
+    InvalidParamsType(&'static str),
+    /// A custom shader samples a texture, but its material does not have one.
+    #[error("shader samples a texture, but the material does not have one")]
+    MissingShaderTexture,
+    /// The device does not support features that are needed to render a geom, e.g.
+    /// `TextureRepeat::None`.
+    #[error("device does not support the features {0:?}")]
+    UnsupportedFeatures(wgpu::Features),
+    /// A draw call refers to a pipeline that the renderer does not have, e.g. because the
+    /// render data was prepared by a different renderer.
+    #[error("pipeline has not been added to the renderer")]
+    MissingPipeline,
+    /// A geom could not be prepared for rendering.
+    #[error("geom {index} could not be prepared: {source}")]
+    InvalidGeom {
+        index: usize,
+        source: Box<RendererError>,
+    },
+    /// The GPU reported an error, e.g. while validating a shader or a pipeline.
+    #[error("GPU error: {0}")]
+    Gpu(String),
+    /// Writing an image to disk failed.
+    #[error("failed to write image: {0}")]
+    Image(#[from] image::ImageError),
+}
@@ -0,0 +1,762 @@
+use super::error::RendererError;
+use super::geometry::{BBox, PixelFilter, Transformation, MAX_BLUR_SIGMA};
+use super::pipeline::{FilterPassKind, FilterPipelineKey};
+use super::uniform_structs::{FilterPassUniforms, FilterRange, FilterUniforms};
+use super::{DrawCall, RenderData, Renderer, SAMPLE_COUNT};
+
+/// The format of the intermediate textures of geoms with spatial filters.
+pub const LAYER_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// The width and height of layer textures are multiples of this size, so that a texture can be
+/// reused while its geom grows a little.
+const LAYER_SIZE_STEP: u32 = 16;
+
+/// The initial size of the buffers with the uniforms and the kernels of the filter passes.
+pub const INITIAL_FILTER_PASS_BUFFER_SIZE: u64 = 1 << 16;
+
+/// The pipeline of all convolution passes, which overwrite their target.
+const CONVOLVE_PIPELINE: FilterPipelineKey = FilterPipelineKey {
+    pass: FilterPassKind::Convolve,
+    blend: wgpu::BlendState::REPLACE,
+    target_format: LAYER_FORMAT,
+    sample_count: 1,
+};
+
+/// A geom with spatial filters. The geom is rendered into its own (layer) texture with
+/// premultiplied alpha, which is filtered by one or more passes and finally blended with the
+/// render target.
+pub struct FilterLayer {
+    /// The draw call that renders the geom into the layer texture.
+    pub draw: DrawCall,
+    /// The offset of the uniforms of the geom as it is drawn on the render target, which are
+    /// used by the filter passes (the draw call uses a copy with the transform of the layer).
+    pub uniform_offset: u32,
+    /// The index of the layer texture in the texture pool.
+    pub texture: usize,
+    /// The filter passes, in order.
+    pub passes: Vec<FilterPass>,
+    /// The bind group of the composite pass.
+    pub composite_bind_group: wgpu::BindGroup,
+    /// The pipeline of the composite pass.
+    pub composite_pipeline: FilterPipelineKey,
+}
+
+/// A pass that convolves a texture of the pool and writes the result into another one.
+pub struct FilterPass {
+    /// The bind group with the source texture, the uniforms and the kernel of the pass.
+    pub bind_group: wgpu::BindGroup,
+    /// The index of the target texture in the texture pool.
+    pub target: usize,
+}
+
+/// A convolution pass of a spatial filter, followed by the pixel filters that come after the
+/// spatial filter.
+pub struct SpatialPass<'a> {
+    pub kernel: Vec<f32>,
+    pub width: u32,
+    pub height: u32,
+    pub filters: Vec<&'a PixelFilter>,
+}
+
+/// The pixel filters and the spatial passes of a geom, see `split_filters`.
+pub type SplitFilters<'a> = (Vec<&'a PixelFilter>, Vec<SpatialPass<'a>>);
+
+/// Splits the filters of a geom into the pixel filters that are applied while the geom is
+/// rendered (all filters before the first spatial filter) and the passes of the spatial filters.
+/// Pixel filters that follow a spatial filter are applied by its last pass.
+pub fn split_filters(filters: &[PixelFilter]) -> Result<SplitFilters<'_>, RendererError> {
+    let mut pixel_filters = vec![];
+    let mut passes: Vec<SpatialPass> = vec![];
+
+    for filter in filters {
+        if filter.is_spatial() {
+            // large blurs would allocate huge kernels, and are far too slow to render
+            if let PixelFilter::Blur { sigma } = filter {
+                if !sigma.is_finite() || *sigma > MAX_BLUR_SIGMA {
+                    return Err(RendererError::InvalidBlur { sigma: *sigma });
+                }
+            }
+
+            for (kernel, width, height) in filter.kernels() {
+                let expected = width as usize * height as usize;
+                if kernel.len() != expected || expected == 0 {
+                    return Err(RendererError::InvalidKernel {
+                        expected,
+                        actual: kernel.len(),
+                    });
+                }
+                passes.push(SpatialPass {
+                    kernel,
+                    width,
+                    height,
+                    filters: vec![],
+                });
+            }
+        } else if let Some(pass) = passes.last_mut() {
+            pass.filters.push(filter);
+        } else {
+            pixel_filters.push(filter);
+        }
+    }
+
+    Ok((pixel_filters, passes))
+}
+
+/// The part of the render target that the layer of a geom covers, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LayerWindow {
+    /// The top-left corner of the layer on the render target.
+    pub origin: [i32; 2],
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Returns the window of a layer, given the bounds of its geom in clip space (see
+/// `screen_bounds`): the bounds plus the distance the passes can spread the geom, limited to the
+/// render target. Pixels outside of the window are transparent, unless a pixel filter makes them
+/// visible, in which case the layer covers the whole target.
+pub fn layer_window(bounds: &BBox, passes: &[SpatialPass], width: u32, height: u32) -> LayerWindow {
+    let shows_transparent = |filter: &&PixelFilter| matches!(filter, PixelFilter::ColourMatrix(matrix) if matrix[19] > 0.0);
+    if passes
+        .iter()
+        .flat_map(|pass| &pass.filters)
+        .any(shows_transparent)
+    {
+        return LayerWindow {
+            origin: [0, 0],
+            width,
+            height,
+        };
+    }
+
+    // each pass spreads the geom by half its kernel, and antialiasing by one more pixel
+    let (margin_x, margin_y) = passes.iter().fold((1, 1), |(x, y), pass| {
+        (x + pass.width / 2, y + pass.height / 2)
+    });
+    let (width, height) = (width as f32, height as f32);
+    let min_x = ((bounds.aa.x + 1.0) / 2.0 * width).floor() - margin_x as f32;
+    let max_x = ((bounds.bb.x + 1.0) / 2.0 * width).ceil() + margin_x as f32;
+    let min_y = ((1.0 - bounds.bb.y) / 2.0 * height).floor() - margin_y as f32;
+    let max_y = ((1.0 - bounds.aa.y) / 2.0 * height).ceil() + margin_y as f32;
+    let (min_x, max_x) = (min_x.clamp(0.0, width), max_x.clamp(0.0, width));
+    let (min_y, max_y) = (min_y.clamp(0.0, height), max_y.clamp(0.0, height));
+
+    // geoms that are not on the target still need a (tiny) texture
+    LayerWindow {
+        origin: [min_x as i32, min_y as i32],
+        width: ((max_x - min_x) as u32).max(1),
+        height: ((max_y - min_y) as u32).max(1),
+    }
+}
+
+/// Lays out the uniforms and the kernels of the filter passes of a frame in the filter pass
+/// buffers, at offsets that can be bound.
+pub struct FilterPassLayout {
+    uniform_alignment: u64,
+    kernel_alignment: u64,
+    /// The number of bytes of the uniform buffer that are used.
+    pub uniform_bytes: u64,
+    /// The number of bytes of the kernel buffer that are used.
+    pub kernel_bytes: u64,
+}
+
+impl FilterPassLayout {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let limits = device.limits();
+        Self {
+            uniform_alignment: limits.min_uniform_buffer_offset_alignment as u64,
+            kernel_alignment: limits.min_storage_buffer_offset_alignment as u64,
+            uniform_bytes: 0,
+            kernel_bytes: 0,
+        }
+    }
+
+    /// Allocates the passes of a layer, followed by its composite pass (which has a kernel with a
+    /// single value, as empty bindings are not allowed). Returns the offsets of the uniforms and
+    /// the kernel of each pass.
+    pub fn allocate_layer(&mut self, passes: &[SpatialPass]) -> Vec<(u64, u64)> {
+        let kernel_lengths = passes.iter().map(|pass| pass.kernel.len()).chain([1]);
+        kernel_lengths
+            .map(|kernel_length| {
+                let uniform_offset = self.uniform_bytes.next_multiple_of(self.uniform_alignment);
+                let kernel_offset = self.kernel_bytes.next_multiple_of(self.kernel_alignment);
+                self.uniform_bytes =
+                    uniform_offset + std::mem::size_of::<FilterPassUniforms>() as u64;
+                self.kernel_bytes =
+                    kernel_offset + (kernel_length * std::mem::size_of::<f32>()) as u64;
+                (uniform_offset, kernel_offset)
+            })
+            .collect()
+    }
+}
+
+pub fn create_filter_pass_uniform_buffer(device: &wgpu::Device, size: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Filter Pass Uniform Buffer"),
+        size,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+pub fn create_filter_kernel_buffer(device: &wgpu::Device, size: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Filter Pass Kernel Buffer"),
+        size,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// Creates the shader module of the filter passes.
+pub fn create_filter_shader(device: &wgpu::Device) -> wgpu::ShaderModule {
+    device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("filter_pass.wgsl"),
+        source: wgpu::ShaderSource::Wgsl(
+            concat!(
+                include_str!("shaders/common.wgsl"),
+                include_str!("shaders/filter_pass.wgsl")
+            )
+            .into(),
+        ),
+    })
+}
+
+/// Creates the layout of the bind group of a filter pass (group 1).
+pub fn create_filter_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Filter Pass Bind Group Layout"),
+        entries: &[
+            // the source texture, which is read with `textureLoad`
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                },
+                count: None,
+            },
+            // the uniforms of the pass
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            // the kernel of the pass
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+impl Renderer {
+    /// Adds the pipeline of a filter pass if it is not in the renderer yet.
+    fn add_filter_pipeline(
+        &mut self,
+        device: &wgpu::Device,
+        key: FilterPipelineKey,
+    ) -> Result<(), RendererError> {
+        if self.filter_pipelines.contains_key(&key) {
+            return Ok(());
+        }
+
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Filter Pipeline Layout"),
+            bind_group_layouts: &[&self.bind_group_layout, &self.filter_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Filter Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &self.filter_shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &self.filter_shader,
+                entry_point: key.pass.entry_point(),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: key.target_format,
+                    blend: Some(key.blend),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: key.sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        });
+
+        if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+            return Err(RendererError::Gpu(error.to_string()));
+        }
+
+        self.filter_pipelines.insert(key, pipeline);
+
+        Ok(())
+    }
+
+    /// Makes sure the texture pool contains a layer texture of at least each of the given sizes,
+    /// preceded by a scratch texture that is used by all layers, and is as large as the largest
+    /// layer texture. Textures are reused while they are large enough, but not much larger than
+    /// needed.
+    pub(crate) fn reserve_filter_textures(&mut self, device: &wgpu::Device, sizes: &[(u32, u32)]) {
+        if sizes.is_empty() {
+            self.filter_textures.clear();
+            return;
+        }
+
+        self.filter_textures.truncate(sizes.len() + 1);
+        for (index, &size) in sizes.iter().enumerate() {
+            self.reserve_filter_texture(device, index + 1, size);
+        }
+
+        let scratch_size =
+            self.filter_textures[1..]
+                .iter()
+                .fold((1, 1), |(width, height), (texture, _)| {
+                    (width.max(texture.width()), height.max(texture.height()))
+                });
+        self.reserve_filter_texture(device, 0, scratch_size);
+    }
+
+    /// Makes sure the texture at `index` of the pool is at least of the given size, creating it
+    /// (or the pool up to it) if necessary.
+    fn reserve_filter_texture(
+        &mut self,
+        device: &wgpu::Device,
+        index: usize,
+        (width, height): (u32, u32),
+    ) {
+        let fits =
+            |extent: u32, size: u32| (size..=2 * size.max(LAYER_SIZE_STEP)).contains(&extent);
+        if let Some((texture, _)) = self.filter_textures.get(index) {
+            if fits(texture.width(), width) && fits(texture.height(), height) {
+                return;
+            }
+        }
+
+        let create_texture = |width: u32, height: u32| {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Filter Texture"),
+                size: wgpu::Extent3d {
+                    width: width.next_multiple_of(LAYER_SIZE_STEP),
+                    height: height.next_multiple_of(LAYER_SIZE_STEP),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: LAYER_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC
+                    | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            (texture, view)
+        };
+
+        // the scratch texture is replaced after the layers, so it may not exist yet
+        while self.filter_textures.len() < index {
+            self.filter_textures.push(create_texture(1, 1));
+        }
+        if index < self.filter_textures.len() {
+            self.filter_textures[index] = create_texture(width, height);
+        } else {
+            self.filter_textures.push(create_texture(width, height));
+        }
+    }
+
+    /// Returns the transform that draws a geom into the given layer texture instead of the render
+    /// target, where the texture covers the target from the origin of `window`. This maps the
+    /// clip space of the target to the clip space of the texture.
+    pub(crate) fn layer_transform(
+        &self,
+        transform: &Transformation,
+        texture: usize,
+        window: &LayerWindow,
+        width: u32,
+        height: u32,
+    ) -> Transformation {
+        let texture = &self.filter_textures[texture].0;
+        let (texture_width, texture_height) = (texture.width() as f32, texture.height() as f32);
+        let (width, height) = (width as f32, height as f32);
+        let (x, y) = (window.origin[0] as f32, window.origin[1] as f32);
+
+        // the y-axis of clip space points up, the one of the textures down
+        let (scale_x, scale_y) = (width / texture_width, height / texture_height);
+        let offset_x = (width - 2.0 * x) / texture_width - 1.0;
+        let offset_y = (2.0 * y - height) / texture_height + 1.0;
+
+        let mut layer_transform = *transform;
+        layer_transform.a = scale_x * transform.a;
+        layer_transform.d = scale_x * transform.d;
+        layer_transform.g = scale_x * transform.g + offset_x;
+        layer_transform.b = scale_y * transform.b;
+        layer_transform.e = scale_y * transform.e;
+        layer_transform.h = scale_y * transform.h + offset_y;
+        layer_transform
+    }
+
+    /// Makes sure the filter pass buffers can hold the passes of the given layers, reallocating
+    /// them like the global buffers if necessary. Returns an empty layout for the frame.
+    pub(crate) fn reserve_filter_pass_buffers<'a>(
+        &mut self,
+        device: &wgpu::Device,
+        layers: impl Iterator<Item = &'a [SpatialPass<'a>]>,
+    ) -> FilterPassLayout {
+        let mut layout = FilterPassLayout::new(device);
+        for passes in layers.filter(|passes| !passes.is_empty()) {
+            layout.allocate_layer(passes);
+        }
+
+        if self.filter_pass_uniform_buffer.size() < layout.uniform_bytes {
+            self.filter_pass_uniform_buffer =
+                create_filter_pass_uniform_buffer(device, layout.uniform_bytes.next_power_of_two());
+        }
+        if self.filter_kernel_buffer.size() < layout.kernel_bytes {
+            self.filter_kernel_buffer =
+                create_filter_kernel_buffer(device, layout.kernel_bytes.next_power_of_two());
+        }
+        self.used_filter_pass_sizes = (layout.uniform_bytes, layout.kernel_bytes);
+
+        FilterPassLayout::new(device)
+    }
+
+    /// Shrinks the filter pass buffers to what the last frame needed (see `shrink_buffers`).
+    pub(crate) fn shrink_filter_pass_buffers(&mut self, device: &wgpu::Device) {
+        let (uniform_bytes, kernel_bytes) = self.used_filter_pass_sizes;
+
+        let uniform_size = uniform_bytes
+            .next_power_of_two()
+            .max(INITIAL_FILTER_PASS_BUFFER_SIZE);
+        if self.filter_pass_uniform_buffer.size() > uniform_size {
+            self.filter_pass_uniform_buffer =
+                create_filter_pass_uniform_buffer(device, uniform_size);
+        }
+
+        let kernel_size = kernel_bytes
+            .next_power_of_two()
+            .max(INITIAL_FILTER_PASS_BUFFER_SIZE);
+        if self.filter_kernel_buffer.size() > kernel_size {
+            self.filter_kernel_buffer = create_filter_kernel_buffer(device, kernel_size);
+        }
+    }
+
+    /// Writes the uniforms and the kernel of a filter pass at the given offsets of the filter
+    /// pass buffers, and creates its bind group, which reads from the given texture of the pool.
+    fn create_filter_bind_group(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        source: usize,
+        (uniform_offset, kernel_offset): (u64, u64),
+        uniforms: &FilterPassUniforms,
+        kernel: &[f32],
+    ) -> wgpu::BindGroup {
+        queue.write_buffer(
+            &self.filter_pass_uniform_buffer,
+            uniform_offset,
+            bytemuck::bytes_of(uniforms),
+        );
+        queue.write_buffer(
+            &self.filter_kernel_buffer,
+            kernel_offset,
+            bytemuck::cast_slice(kernel),
+        );
+
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Filter Pass Bind Group"),
+            layout: &self.filter_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&self.filter_textures[source].1),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &self.filter_pass_uniform_buffer,
+                        offset: uniform_offset,
+                        size: wgpu::BufferSize::new(std::mem::size_of_val(uniforms) as u64),
+                    }),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &self.filter_kernel_buffer,
+                        offset: kernel_offset,
+                        size: wgpu::BufferSize::new(std::mem::size_of_val(kernel) as u64),
+                    }),
+                },
+            ],
+        })
+    }
+
+    /// Adds the pipelines of the filter passes of a layer whose composite pass blends the result
+    /// with a target of the given format, and returns the key of the composite pipeline.
+    pub(crate) fn add_filter_layer_pipelines(
+        &mut self,
+        device: &wgpu::Device,
+        blend: wgpu::BlendState,
+        target_format: wgpu::TextureFormat,
+    ) -> Result<FilterPipelineKey, RendererError> {
+        let composite_pipeline = FilterPipelineKey {
+            pass: FilterPassKind::Composite,
+            blend,
+            target_format,
+            sample_count: SAMPLE_COUNT,
+        };
+        self.add_filter_pipeline(device, CONVOLVE_PIPELINE)?;
+        self.add_filter_pipeline(device, composite_pipeline)?;
+        Ok(composite_pipeline)
+    }
+
+    /// Prepares the passes of a geom with spatial filters. `draw` renders the geom into the layer
+    /// texture `texture`, which covers `window`, and the passes use the uniforms of the geom at
+    /// `uniform_offset`. The pixel filters of the passes are appended to `filters`. The pipelines
+    /// must have been added with `add_filter_layer_pipelines`.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn prepare_filter_layer(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &mut FilterPassLayout,
+        draw: DrawCall,
+        uniform_offset: u32,
+        texture: usize,
+        window: &LayerWindow,
+        spatial_passes: Vec<SpatialPass>,
+        composite_pipeline: FilterPipelineKey,
+        filters: &mut Vec<FilterUniforms>,
+    ) -> FilterLayer {
+        let mut offsets = layout.allocate_layer(&spatial_passes).into_iter();
+
+        // the passes alternate between the layer texture and the scratch texture
+        let mut source = texture;
+        let mut passes = Vec::with_capacity(spatial_passes.len());
+        for pass in spatial_passes {
+            let uniforms = FilterPassUniforms {
+                filters: FilterRange {
+                    start: filters.len() as u32,
+                    count: pass.filters.len() as u32,
+                    _padding: [0; 2],
+                },
+                kernel_width: pass.width,
+                kernel_height: pass.height,
+                origin: window.origin,
+            };
+            filters.extend(pass.filters.iter().map(|filter| filter.uniforms()));
+
+            let target = if source == texture { 0 } else { texture };
+            passes.push(FilterPass {
+                bind_group: self.create_filter_bind_group(
+                    device,
+                    queue,
+                    source,
+                    offsets
+                        .next()
+                        .expect("Missing filter pass offsets. This should not happen."),
+                    &uniforms,
+                    &pass.kernel,
+                ),
+                target,
+            });
+            source = target;
+        }
+
+        // the result is copied back into the layer texture (see `render_filter_layers`), as the
+        // scratch texture is reused by the next layer
+        let composite_uniforms = FilterPassUniforms {
+            filters: FilterRange {
+                start: 0,
+                count: 0,
+                _padding: [0; 2],
+            },
+            kernel_width: 0,
+            kernel_height: 0,
+            origin: window.origin,
+        };
+        let composite_bind_group = self.create_filter_bind_group(
+            device,
+            queue,
+            texture,
+            offsets
+                .next()
+                .expect("Missing filter pass offsets. This should not happen."),
+            &composite_uniforms,
+            &[0.0],
+        );
+
+        FilterLayer {
+            draw,
+            uniform_offset,
+            texture,
+            passes,
+            composite_bind_group,
+            composite_pipeline,
+        }
+    }
+
+    /// Renders and filters the geoms with spatial filters into their layer textures. This must be
+    /// recorded before the render pass that draws the frame (see `render`), which then blends the
+    /// layers with the render target.
+    pub fn render_filter_layers(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        rdata: &RenderData,
+    ) -> Result<(), RendererError> {
+        for layer in &rdata.layers {
+            let (layer_texture, layer_view) = &self.filter_textures[layer.texture];
+
+            // render the geom with premultiplied alpha
+            {
+                let mut rpass = begin_filter_pass(encoder, layer_view, "Filter Layer Pass");
+                rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+                rpass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+                rpass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                rpass.set_pipeline(
+                    self.pipelines
+                        .get(&layer.draw.pipeline)
+                        .ok_or(RendererError::MissingPipeline)?,
+                );
+                rpass.set_bind_group(0, &self.bind_group, &[layer.draw.uniform_offset]);
+                if let Some(texture_bind_group) = &layer.draw.texture_bind_group {
+                    rpass.set_bind_group(1, texture_bind_group, &[]);
+                }
+                rpass.draw_indexed(layer.draw.indices.clone(), 0, layer.draw.instances.clone());
+            }
+
+            // apply the convolutions
+            let pipeline = self
+                .filter_pipelines
+                .get(&CONVOLVE_PIPELINE)
+                .ok_or(RendererError::MissingPipeline)?;
+            for pass in &layer.passes {
+                let target_view = &self.filter_textures[pass.target].1;
+                let mut rpass = begin_filter_pass(encoder, target_view, "Filter Pass");
+                rpass.set_pipeline(pipeline);
+                rpass.set_bind_group(0, &self.bind_group, &[layer.uniform_offset]);
+                rpass.set_bind_group(1, &pass.bind_group, &[]);
+                rpass.draw(0..3, 0..1);
+            }
+
+            // after an odd number of passes, the result is in the scratch texture
+            if layer.passes.len() % 2 == 1 {
+                let scratch_texture = &self.filter_textures[0].0;
+                encoder.copy_texture_to_texture(
+                    scratch_texture.as_image_copy(),
+                    layer_texture.as_image_copy(),
+                    layer_texture.size(),
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Begins a pass that clears the given filter texture to transparent.
+fn begin_filter_pass<'a>(
+    encoder: &'a mut wgpu::CommandEncoder,
+    view: &'a wgpu::TextureView,
+    label: &str,
+) -> wgpu::RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                store: wgpu::StoreOp::Store,
+            },
+            resolve_target: None,
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blurs_are_bounded() {
+        for sigma in [f32::INFINITY, f32::NAN, 1e5] {
+            let filters = [PixelFilter::Blur { sigma }];
+            assert!(matches!(
+                split_filters(&filters),
+                Err(RendererError::InvalidBlur { .. })
+            ));
+        }
+
+        let filters = [PixelFilter::Blur {
+            sigma: MAX_BLUR_SIGMA,
+        }];
+        let (_, passes) = split_filters(&filters).unwrap();
+        assert_eq!(passes.len(), 2);
+        assert_eq!(passes[0].width, 601);
+    }
+
+    #[test]
+    fn layer_windows_are_bounded() {
+        use crate::renderer::geometry::Point2D;
+
+        // the right half of a 100x40 target, blurred by 3 pixels in both directions
+        let bounds = BBox {
+            aa: Point2D::new(0.0, -0.25),
+            bb: Point2D::new(2.0, 0.25),
+        };
+        let filters = [PixelFilter::Blur { sigma: 1.0 }];
+        let (_, passes) = split_filters(&filters).unwrap();
+        let window = layer_window(&bounds, &passes, 100, 40);
+        assert_eq!(window.origin, [46, 11]);
+        assert_eq!((window.width, window.height), (54, 18));
+
+        // a colour matrix with an alpha offset makes the whole target visible
+        let mut matrix = [0.0; 20];
+        matrix[19] = 0.5;
+        let filters = [filters[0].clone(), PixelFilter::ColourMatrix(matrix)];
+        let (_, passes) = split_filters(&filters).unwrap();
+        let window = layer_window(&bounds, &passes, 100, 40);
+        assert_eq!(window.origin, [0, 0]);
+        assert_eq!((window.width, window.height), (100, 40));
+
+        // geoms that are not on the target get a tiny window
+        let bounds = BBox {
+            aa: Point2D::new(3.0, 3.0),
+            bb: Point2D::new(4.0, 4.0),
+        };
+        let window = layer_window(&bounds, &[], 100, 40);
+        assert_eq!((window.width, window.height), (1, 1));
+    }
+}
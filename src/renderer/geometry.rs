@@ -2,14 +2,16 @@ use std::hash::Hash;
 use std::hash::Hasher;
 
 use super::helpers::CacheEntry;
+use super::helpers::Cacheable;
 use super::helpers::Fingerprint;
 use super::material::Colour;
 use super::material::Material;
+use super::path::Path;
+use super::uniform_structs::FilterUniforms;
 
 /// A geometry object defined by what to render (a primitive and tessellation) and how to render it (a material).
 pub struct Geom {
     /// Internal id for caching.
-    #[allow(dead_code)]
     id: CacheEntry,
     /// The primitive to render.
     pub primitive: Primitive,
@@ -21,6 +23,10 @@ pub struct Geom {
     pub filters: Vec<PixelFilter>,
     /// How to tesselate the primitive before rendering.
     pub options: TessellationOptions,
+    /// If set, the primitive is drawn once per instance instead of once.
+    pub instances: Option<Vec<Instance>>,
+    /// How the geom is combined with what has already been rendered.
+    pub blend_mode: BlendMode,
 }
 
 impl Geom {
@@ -39,8 +45,93 @@ impl Geom {
             transform,
             filters,
             options,
+            instances: None,
+            blend_mode: BlendMode::default(),
         }
     }
+
+    /// Set the blend mode of the geometry object.
+    pub fn with_blend_mode(self, blend_mode: BlendMode) -> Self {
+        Self { blend_mode, ..self }
+    }
+
+    /// Returns true if any of the filters of the geometry object is a spatial filter.
+    pub fn has_spatial_filters(&self) -> bool {
+        self.filters.iter().any(PixelFilter::is_spatial)
+    }
+
+    /// Create a geometry object that draws the primitive once for every instance. The primitive
+    /// is only tessellated once, so this is much cheaper than creating a geom per instance.
+    pub fn instanced(
+        primitive: Primitive,
+        material: Material,
+        transform: Option<Transformation>,
+        filters: Vec<PixelFilter>,
+        options: TessellationOptions,
+        instances: Vec<Instance>,
+    ) -> Self {
+        Geom {
+            instances: Some(instances),
+            ..Self::new(primitive, material, transform, filters, options)
+        }
+    }
+}
+
+/// A single copy of an instanced geom.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Instance {
+    /// The transform of the instance, applied to the primitive before the transform of the geom.
+    /// It is given in the same units as the primitive.
+    pub transform: Transformation,
+    /// The colour that the output of the material is multiplied with.
+    pub tint: Colour,
+    /// The opacity of the instance.
+    pub opacity: f32,
+}
+
+impl Instance {
+    /// Create an instance with the given transform, no tint and full opacity.
+    pub fn new(transform: Transformation) -> Self {
+        Self {
+            transform,
+            tint: Colour::WHITE,
+            opacity: 1.0,
+        }
+    }
+
+    /// Create an instance that is moved by the given offset.
+    pub fn at(x: f32, y: f32) -> Self {
+        Self::new(Transformation::translation(x, y))
+    }
+
+    /// Set the tint of the instance.
+    pub fn with_tint(self, tint: Colour) -> Self {
+        Self { tint, ..self }
+    }
+
+    /// Set the opacity of the instance.
+    pub fn with_opacity(self, opacity: f32) -> Self {
+        Self { opacity, ..self }
+    }
+}
+
+impl Default for Instance {
+    fn default() -> Self {
+        Self::new(Transformation::identity())
+    }
+}
+
+impl Cacheable for Geom {
+    fn cache_id(&self) -> CacheEntry {
+        self.id.clone()
+    }
+}
+
+// the fingerprint covers everything the tessellation depends on
+impl Fingerprint for Geom {
+    fn fingerprint(&self) -> u64 {
+        (&self.primitive, &self.options).fingerprint()
+    }
 }
 
 #[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, PartialEq)]
@@ -53,22 +144,70 @@ pub struct BBox {
 #[derive(Debug, Clone, PartialEq)]
 #[repr(C)]
 pub enum Primitive {
-    /// A Rectangle defined by two points.
-    Rectangle { a: Point2D, b: Point2D },
-    /// A rounded rectangle defined by two points and a radius.
-    RoundedRectangle { a: Point2D, b: Point2D, radius: f32 },
+    /// A Rectangle defined by two points and a rotation in degrees around its centre.
+    Rectangle {
+        a: Point2D,
+        b: Point2D,
+        rotation: f32,
+    },
+    /// A rounded rectangle defined by two points, a radius and a rotation in degrees around its
+    /// centre.
+    RoundedRectangle {
+        a: Point2D,
+        b: Point2D,
+        radius: f32,
+        rotation: f32,
+    },
     /// A Circle defined by a center point and a radius.
     Circle { center: Point2D, radius: f32 },
     /// A Triangle defined by three points.
     Triangle { a: Point2D, b: Point2D, c: Point2D },
     /// A polygon defined by a list of points.
     Polygon { points: Vec<Point2D> },
-    /// A line defined by two points.
+    /// A line defined by two points. Lines have no interior, so they can only be stroked, and
+    /// filling one fails with `RendererError::UnsupportedTessellation`.
     Line { a: Point2D, b: Point2D },
-    /// A path defined by a list of points.
-    Path { points: Vec<Point2D> },
-    /// An ellipse defined by a center point and two radii.
-    Ellipse { center: Point2D, radii: Vector2 },
+    /// A path made of lines, Bézier curves and arcs, possibly with multiple sub-paths.
+    Path { path: Path },
+    /// An ellipse defined by a center point, two radii and a rotation in degrees around its
+    /// center.
+    Ellipse {
+        center: Point2D,
+        radii: Vector2,
+        rotation: f32,
+    },
+    /// A circular arc defined by a center point, a radius and start and end angles in degrees.
+    /// When filled, the arc is closed by a straight line (a circular segment).
+    Arc {
+        center: Point2D,
+        radius: f32,
+        start_angle: f32,
+        end_angle: f32,
+    },
+    /// A circular sector (pie wedge) defined by a center point, a radius and start and end angles
+    /// in degrees.
+    Sector {
+        center: Point2D,
+        radius: f32,
+        start_angle: f32,
+        end_angle: f32,
+    },
+    /// An annulus (ring) segment defined by a center point, inner and outer radii and start and
+    /// end angles in degrees. A sweep of 360 degrees gives a full ring.
+    Annulus {
+        center: Point2D,
+        inner_radius: f32,
+        outer_radius: f32,
+        start_angle: f32,
+        end_angle: f32,
+    },
+    /// A shape made of the contours of several primitives, e.g. a frame made of two rectangles
+    /// or a letter with counters. Where the contours overlap, the fill rule decides what is
+    /// inside. Circles, ellipses and rounded rectangles have a positive winding, while polygons,
+    /// triangles, rectangles and paths follow the order of their points or corners, and annuli
+    /// contain both windings. With `FillRule::NonZero`, holes have to run in the opposite
+    /// direction of the contours around them; `FillRule::EvenOdd` does not depend on the winding.
+    Compound { contours: Vec<Primitive> },
 }
 
 // make Primitive Eq
@@ -78,18 +217,25 @@ impl Fingerprint for Primitive {
     fn fingerprint(&self) -> u64 {
         let mut state = std::collections::hash_map::DefaultHasher::new();
         match self {
-            Primitive::Rectangle { a, b } => {
+            Primitive::Rectangle { a, b, rotation } => {
                 a.x.to_bits().hash(&mut state);
                 a.y.to_bits().hash(&mut state);
                 b.x.to_bits().hash(&mut state);
                 b.y.to_bits().hash(&mut state);
+                rotation.to_bits().hash(&mut state);
             }
-            Primitive::RoundedRectangle { a, b, radius } => {
+            Primitive::RoundedRectangle {
+                a,
+                b,
+                radius,
+                rotation,
+            } => {
                 a.x.to_bits().hash(&mut state);
                 a.y.to_bits().hash(&mut state);
                 b.x.to_bits().hash(&mut state);
                 b.y.to_bits().hash(&mut state);
                 radius.to_bits().hash(&mut state);
+                rotation.to_bits().hash(&mut state);
             }
             Primitive::Circle { center, radius } => {
                 center.x.to_bits().hash(&mut state);
@@ -110,19 +256,63 @@ impl Fingerprint for Primitive {
                     point.y.to_bits().hash(&mut state);
                 }
             }
+            Primitive::Path { path } => {
+                path.hash(&mut state);
+            }
+            Primitive::Compound { contours } => {
+                for contour in contours {
+                    contour.fingerprint().hash(&mut state);
+                }
+            }
+            Primitive::Arc {
+                center,
+                radius,
+                start_angle,
+                end_angle,
+            }
+            | Primitive::Sector {
+                center,
+                radius,
+                start_angle,
+                end_angle,
+            } => {
+                center.x.to_bits().hash(&mut state);
+                center.y.to_bits().hash(&mut state);
+                radius.to_bits().hash(&mut state);
+                start_angle.to_bits().hash(&mut state);
+                end_angle.to_bits().hash(&mut state);
+            }
+            Primitive::Annulus {
+                center,
+                inner_radius,
+                outer_radius,
+                start_angle,
+                end_angle,
+            } => {
+                center.x.to_bits().hash(&mut state);
+                center.y.to_bits().hash(&mut state);
+                inner_radius.to_bits().hash(&mut state);
+                outer_radius.to_bits().hash(&mut state);
+                start_angle.to_bits().hash(&mut state);
+                end_angle.to_bits().hash(&mut state);
+            }
             Primitive::Line { a, b } => {
                 a.x.to_bits().hash(&mut state);
                 a.y.to_bits().hash(&mut state);
                 b.x.to_bits().hash(&mut state);
                 b.y.to_bits().hash(&mut state);
             }
-            Primitive::Ellipse { center, radii } => {
+            Primitive::Ellipse {
+                center,
+                radii,
+                rotation,
+            } => {
                 center.x.to_bits().hash(&mut state);
                 center.y.to_bits().hash(&mut state);
                 radii.x.to_bits().hash(&mut state);
                 radii.y.to_bits().hash(&mut state);
+                rotation.to_bits().hash(&mut state);
             }
-            _ => todo!(),
         }
 
         // add the variant to the hash
@@ -136,25 +326,23 @@ impl Primitive {
     /// Get bounding box of the primitive.
     pub fn bbox(&self) -> BBox {
         match self {
-            Primitive::Rectangle { a, b } => {
+            Primitive::Rectangle { a, b, rotation }
+            | Primitive::RoundedRectangle { a, b, rotation, .. } => {
+                // the rounded corners are always inside the rotated rectangle
+                let corners = rectangle_corners(a, b, *rotation);
                 let aa = Point2D {
-                    x: a.x.min(b.x),
-                    y: a.y.min(b.y),
+                    x: corners.iter().map(|p| p.x).fold(f32::INFINITY, f32::min),
+                    y: corners.iter().map(|p| p.y).fold(f32::INFINITY, f32::min),
                 };
                 let bb = Point2D {
-                    x: a.x.max(b.x),
-                    y: a.y.max(b.y),
-                };
-                BBox { aa, bb }
-            }
-            Primitive::RoundedRectangle { a, b, .. } => {
-                let aa = Point2D {
-                    x: a.x.min(b.x),
-                    y: a.y.min(b.y),
-                };
-                let bb = Point2D {
-                    x: a.x.max(b.x),
-                    y: a.y.max(b.y),
+                    x: corners
+                        .iter()
+                        .map(|p| p.x)
+                        .fold(f32::NEG_INFINITY, f32::max),
+                    y: corners
+                        .iter()
+                        .map(|p| p.y)
+                        .fold(f32::NEG_INFINITY, f32::max),
                 };
                 BBox { aa, bb }
             }
@@ -208,23 +396,161 @@ impl Primitive {
                 };
                 BBox { aa, bb }
             }
-            Primitive::Ellipse { center, radii } => {
-                // calculate the bounding box of the ellipse
+            Primitive::Ellipse {
+                center,
+                radii,
+                rotation,
+            } => {
+                // calculate the bounding box of the (rotated) ellipse
+                let (sin, cos) = rotation.to_radians().sin_cos();
+                let half_width = ((radii.x * cos).powi(2) + (radii.y * sin).powi(2)).sqrt();
+                let half_height = ((radii.x * sin).powi(2) + (radii.y * cos).powi(2)).sqrt();
                 let aa = Point2D {
-                    x: center.x - radii.x,
-                    y: center.y - radii.y,
+                    x: center.x - half_width,
+                    y: center.y - half_height,
                 };
                 let bb = Point2D {
-                    x: center.x + radii.x,
-                    y: center.y + radii.y,
+                    x: center.x + half_width,
+                    y: center.y + half_height,
+                };
+                BBox { aa, bb }
+            }
+            Primitive::Path { path } => path.bbox(),
+            Primitive::Compound { contours } => {
+                let mut aa = Point2D {
+                    x: f32::INFINITY,
+                    y: f32::INFINITY,
                 };
+                let mut bb = Point2D {
+                    x: f32::NEG_INFINITY,
+                    y: f32::NEG_INFINITY,
+                };
+                for bbox in contours.iter().map(|c| c.bbox()) {
+                    aa.x = aa.x.min(bbox.aa.x);
+                    aa.y = aa.y.min(bbox.aa.y);
+                    bb.x = bb.x.max(bbox.bb.x);
+                    bb.y = bb.y.max(bbox.bb.y);
+                }
                 BBox { aa, bb }
             }
-            _ => todo!(),
+            Primitive::Arc { .. } | Primitive::Sector { .. } | Primitive::Annulus { .. } => self
+                .outline()
+                .expect("Arc-based primitives always have an outline.")
+                .bbox(),
+        }
+    }
+
+    /// Returns true if all coordinates, radii and angles of the primitive are finite. Primitives
+    /// with NaN or infinite values cannot be tessellated. A compound without contours has no
+    /// finite bounding box, so it is not finite either.
+    pub fn is_finite(&self) -> bool {
+        match self {
+            Primitive::Rectangle { a, b, rotation } => {
+                a.is_finite() && b.is_finite() && rotation.is_finite()
+            }
+            Primitive::RoundedRectangle {
+                a,
+                b,
+                radius,
+                rotation,
+            } => a.is_finite() && b.is_finite() && radius.is_finite() && rotation.is_finite(),
+            Primitive::Circle { center, radius } => center.is_finite() && radius.is_finite(),
+            Primitive::Triangle { a, b, c } => a.is_finite() && b.is_finite() && c.is_finite(),
+            Primitive::Polygon { points } => points.iter().all(Point2D::is_finite),
+            Primitive::Line { a, b } => a.is_finite() && b.is_finite(),
+            Primitive::Path { path } => path.is_finite(),
+            Primitive::Ellipse {
+                center,
+                radii,
+                rotation,
+            } => {
+                center.is_finite()
+                    && radii.x.is_finite()
+                    && radii.y.is_finite()
+                    && rotation.is_finite()
+            }
+            Primitive::Arc {
+                center,
+                radius,
+                start_angle,
+                end_angle,
+            }
+            | Primitive::Sector {
+                center,
+                radius,
+                start_angle,
+                end_angle,
+            } => {
+                center.is_finite()
+                    && radius.is_finite()
+                    && start_angle.is_finite()
+                    && end_angle.is_finite()
+            }
+            Primitive::Annulus {
+                center,
+                inner_radius,
+                outer_radius,
+                start_angle,
+                end_angle,
+            } => {
+                center.is_finite()
+                    && inner_radius.is_finite()
+                    && outer_radius.is_finite()
+                    && start_angle.is_finite()
+                    && end_angle.is_finite()
+            }
+            Primitive::Compound { contours } => {
+                !contours.is_empty() && contours.iter().all(Primitive::is_finite)
+            }
+        }
+    }
+
+    /// Returns the outline of arc-based primitives (`Arc`, `Sector` and `Annulus`) as a path.
+    pub fn outline(&self) -> Option<Path> {
+        match self {
+            Primitive::Arc {
+                center,
+                radius,
+                start_angle,
+                end_angle,
+            } => Some(Path::arc(*center, *radius, *start_angle, *end_angle)),
+            Primitive::Sector {
+                center,
+                radius,
+                start_angle,
+                end_angle,
+            } => Some(Path::sector(*center, *radius, *start_angle, *end_angle)),
+            Primitive::Annulus {
+                center,
+                inner_radius,
+                outer_radius,
+                start_angle,
+                end_angle,
+            } => Some(Path::annulus(
+                *center,
+                *inner_radius,
+                *outer_radius,
+                *start_angle,
+                *end_angle,
+            )),
+            _ => None,
         }
     }
 }
 
+/// Returns the corners of the rectangle spanned by `a` and `b`, rotated by `rotation` degrees
+/// around its centre.
+pub fn rectangle_corners(a: &Point2D, b: &Point2D, rotation: f32) -> [Point2D; 4] {
+    let center = Point2D::new((a.x + b.x) / 2.0, (a.y + b.y) / 2.0);
+    [
+        Point2D::new(a.x, a.y),
+        Point2D::new(b.x, a.y),
+        Point2D::new(b.x, b.y),
+        Point2D::new(a.x, b.y),
+    ]
+    .map(|p| p.rotated(&center, rotation))
+}
+
 impl Fingerprint for &Primitive {
     fn fingerprint(&self) -> u64 {
         (*self).fingerprint()
@@ -232,6 +558,7 @@ impl Fingerprint for &Primitive {
 }
 
 /// The type of line cap.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum LineCap {
     Butt,
     Square,
@@ -239,6 +566,7 @@ pub enum LineCap {
 }
 
 /// The type of line join.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum LineJoin {
     Miter,
     MiterClip,
@@ -246,14 +574,67 @@ pub enum LineJoin {
     Bevel,
 }
 
+/// The rule that decides which parts of a shape with overlapping contours are inside.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FillRule {
+    /// A point is inside if a ray from it crosses the contours an odd number of times.
+    #[default]
+    EvenOdd,
+    /// A point is inside if the contours wind around it a non-zero number of times.
+    NonZero,
+}
+
+/// The maximum distance between a curve and the line segments that approximate it.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Tolerance {
+    /// A fixed tolerance in the units of the primitive.
+    Fixed(f32),
+    /// A tolerance of `AUTO_TOLERANCE` pixels on screen, derived from the geom's transform and
+    /// the size of the surface. Large shapes get more segments and small shapes fewer.
+    #[default]
+    Auto,
+}
+
+/// The tolerance of `Tolerance::Auto` in pixels.
+pub const AUTO_TOLERANCE: f32 = 0.05;
+
+/// The tolerance used if the on-screen scale of a geom cannot be determined.
+const FALLBACK_TOLERANCE: f32 = 0.01;
+
+impl Tolerance {
+    /// Returns the tolerance in the units of the primitive, given the number of pixels per unit
+    /// (see `Transformation::pixel_scale`).
+    pub fn resolve(&self, pixel_scale: f32) -> f32 {
+        match self {
+            Tolerance::Fixed(tolerance) => *tolerance,
+            Tolerance::Auto if pixel_scale.is_finite() && pixel_scale > 0.0 => {
+                AUTO_TOLERANCE / pixel_scale
+            }
+            Tolerance::Auto => FALLBACK_TOLERANCE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum TessellationOptions {
-    Fill,
+    Fill {
+        fill_rule: FillRule,
+        tolerance: Tolerance,
+    },
     Stroke {
         start_cap: LineCap,
         end_cap: LineCap,
         line_join: LineJoin,
         line_width: f32,
         miter_limit: f32,
+        /// Alternating lengths of dashes and gaps. An empty pattern draws a solid line. A pattern
+        /// with an odd number of entries is repeated to make it even. Caps are applied to every
+        /// dash, so zero-length dashes with round caps give dotted lines. Negative or non-finite
+        /// entries are rejected with `RendererError::InvalidDashPattern`.
+        dash_pattern: Vec<f32>,
+        /// The distance into the dash pattern at which the stroke starts.
+        dash_offset: f32,
+        tolerance: Tolerance,
     },
 }
 
@@ -261,8 +642,16 @@ impl Fingerprint for TessellationOptions {
     fn fingerprint(&self) -> u64 {
         let mut state = std::collections::hash_map::DefaultHasher::new();
         match self {
-            TessellationOptions::Fill => {
+            TessellationOptions::Fill {
+                fill_rule,
+                tolerance,
+            } => {
                 "fill".hash(&mut state);
+                match fill_rule {
+                    FillRule::EvenOdd => "even_odd".hash(&mut state),
+                    FillRule::NonZero => "non_zero".hash(&mut state),
+                }
+                hash_tolerance(tolerance, &mut state);
             }
             TessellationOptions::Stroke {
                 start_cap,
@@ -270,6 +659,9 @@ impl Fingerprint for TessellationOptions {
                 line_join,
                 line_width,
                 miter_limit,
+                dash_pattern,
+                dash_offset,
+                tolerance,
             } => {
                 match start_cap {
                     LineCap::Butt => "butt".hash(&mut state),
@@ -289,6 +681,11 @@ impl Fingerprint for TessellationOptions {
                 }
                 line_width.to_bits().hash(&mut state);
                 miter_limit.to_bits().hash(&mut state);
+                for length in dash_pattern {
+                    length.to_bits().hash(&mut state);
+                }
+                dash_offset.to_bits().hash(&mut state);
+                hash_tolerance(tolerance, &mut state);
             }
         }
 
@@ -299,6 +696,16 @@ impl Fingerprint for TessellationOptions {
     }
 }
 
+fn hash_tolerance<H: Hasher>(tolerance: &Tolerance, state: &mut H) {
+    match tolerance {
+        Tolerance::Fixed(tolerance) => {
+            "fixed".hash(state);
+            tolerance.to_bits().hash(state);
+        }
+        Tolerance::Auto => "auto".hash(state),
+    }
+}
+
 impl Fingerprint for &TessellationOptions {
     fn fingerprint(&self) -> u64 {
         (*self).fingerprint()
@@ -306,6 +713,21 @@ impl Fingerprint for &TessellationOptions {
 }
 
 impl TessellationOptions {
+    /// Returns the tolerance of the options.
+    pub fn tolerance(&self) -> Tolerance {
+        match self {
+            TessellationOptions::Fill { tolerance, .. }
+            | TessellationOptions::Stroke { tolerance, .. } => *tolerance,
+        }
+    }
+
+    pub fn simple_fill() -> Self {
+        TessellationOptions::Fill {
+            fill_rule: FillRule::default(),
+            tolerance: Tolerance::default(),
+        }
+    }
+
     pub fn simple_line(width: f32) -> Self {
         TessellationOptions::Stroke {
             start_cap: LineCap::Butt,
@@ -313,6 +735,22 @@ impl TessellationOptions {
             line_join: LineJoin::Miter,
             line_width: width,
             miter_limit: 4.0,
+            dash_pattern: vec![],
+            dash_offset: 0.0,
+            tolerance: Tolerance::default(),
+        }
+    }
+
+    pub fn dashed_line(width: f32, dash_pattern: Vec<f32>) -> Self {
+        TessellationOptions::Stroke {
+            start_cap: LineCap::Butt,
+            end_cap: LineCap::Butt,
+            line_join: LineJoin::Miter,
+            line_width: width,
+            miter_limit: 4.0,
+            dash_pattern,
+            dash_offset: 0.0,
+            tolerance: Tolerance::default(),
         }
     }
 }
@@ -328,6 +766,22 @@ impl Point2D {
     pub fn new(x: f32, y: f32) -> Self {
         Self { x, y }
     }
+
+    /// Returns true if both coordinates are finite.
+    pub fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite()
+    }
+
+    /// Rotates the point by `degrees` around `center`.
+    pub fn rotated(&self, center: &Point2D, degrees: f32) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let dx = self.x - center.x;
+        let dy = self.y - center.y;
+        Self {
+            x: center.x + dx * cos - dy * sin,
+            y: center.y + dx * sin + dy * cos,
+        }
+    }
 }
 
 #[repr(C)]
@@ -344,7 +798,7 @@ impl Vector2 {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
 /// A 3x3 transformation matrix.
 pub struct Transformation {
     pub a: f32,
@@ -362,22 +816,76 @@ pub struct Transformation {
 }
 
 impl Transformation {
+    /// The transform that does not change anything.
+    pub const IDENTITY: Self = Self {
+        a: 1.0,
+        b: 0.0,
+        c: 0.0,
+        _pad1: 0.0,
+        d: 0.0,
+        e: 1.0,
+        f: 0.0,
+        _pad2: 0.0,
+        g: 0.0,
+        h: 0.0,
+        i: 1.0,
+        _pad3: [0.0; 5],
+    };
+
     pub fn identity() -> Self {
+        Self::IDENTITY
+    }
+
+    /// Create a transform that moves points by the given offset.
+    pub fn translation(x: f32, y: f32) -> Self {
         Self {
-            a: 1.0,
-            b: 0.0,
-            c: 0.0,
-            _pad1: 0.0,
-            d: 0.0,
-            e: 1.0,
-            f: 0.0,
-            _pad2: 0.0,
-            g: 0.0,
-            h: 0.0,
-            i: 1.0,
-            _pad3: [0.0; 5],
+            g: x,
+            h: y,
+            ..Self::identity()
         }
     }
+
+    /// Applies the transform to a point.
+    pub fn transform_point(&self, point: Point2D) -> Point2D {
+        Point2D::new(
+            self.a * point.x + self.d * point.y + self.g,
+            self.b * point.x + self.e * point.y + self.h,
+        )
+    }
+
+    /// Returns the largest factor by which the transform scales lengths.
+    pub fn scale(&self) -> f32 {
+        largest_singular_value(self.a, self.d, self.b, self.e)
+    }
+
+    /// Returns the largest factor by which the transform scales lengths, in pixels per unit on a
+    /// surface of the given size. This accounts for the mapping from pixels to clip space in the
+    /// vertex shader.
+    pub fn pixel_scale(&self, width: u32, height: u32) -> f32 {
+        let (width, height) = (width as f32, height as f32);
+
+        // the jacobian of the mapping from primitive units to pixels, i.e. pixels -> clip space
+        // (scaled by -2 / size), the transform, and clip space -> pixels (scaled by size / 2)
+        let m00 = self.a;
+        let m01 = self.d * width / height;
+        let m10 = self.b * height / width;
+        let m11 = self.e;
+
+        largest_singular_value(m00, m01, m10, m11)
+    }
+
+    /// Returns the geometric mean of the factors by which the transform scales lengths, in
+    /// pixels per unit. This is the inverse of `pixel_size` in the shaders.
+    pub fn mean_pixel_scale(&self) -> f32 {
+        (self.a * self.e - self.b * self.d).abs().sqrt()
+    }
+}
+
+/// Returns the largest singular value of the 2x2 matrix [[m00, m01], [m10, m11]].
+fn largest_singular_value(m00: f32, m01: f32, m10: f32, m11: f32) -> f32 {
+    let sum = m00 * m00 + m01 * m01 + m10 * m10 + m11 * m11;
+    let det = m00 * m11 - m01 * m10;
+    ((sum + (sum * sum - 4.0 * det * det).max(0.0).sqrt()) / 2.0).sqrt()
 }
 
 impl From<nalgebra::Matrix3<f32>> for Transformation {
@@ -399,23 +907,166 @@ impl From<nalgebra::Matrix3<f32>> for Transformation {
     }
 }
 
-/// A filter that is applied at the pixel level.
+/// How the colour of a geom (the source) is combined with the colour that has already been
+/// rendered (the destination). Unless stated otherwise, the source colour is weighted by its
+/// alpha, and the alpha of the target is composited as with `Alpha`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BlendMode {
+    /// Regular alpha compositing: `src * src_alpha + dst * (1 - src_alpha)`.
+    #[default]
+    Alpha,
+    /// Alpha compositing for colours that are already multiplied by their alpha:
+    /// `src + dst * (1 - src_alpha)`.
+    PremultipliedAlpha,
+    /// Adds the source to the destination: `dst + src * src_alpha`.
+    Additive,
+    /// Subtracts the source from the destination: `dst - src * src_alpha`.
+    Subtractive,
+    /// Multiplies the destination with the source: `dst * src`. The alpha of the source is
+    /// ignored.
+    Multiply,
+    /// The component-wise minimum of source and destination. The alpha of the source is ignored.
+    Min,
+    /// The component-wise maximum of source and destination. The alpha of the source is ignored.
+    Max,
+    /// Replaces the destination, including its alpha, with the exact value of the source.
+    Replace,
+}
+
+/// The largest standard deviation of a blur in pixels. The kernel of a blur spans three standard
+/// deviations on either side, and is evaluated for every pixel of the geom in each pass.
+pub const MAX_BLUR_SIGMA: f32 = 100.0;
+
+/// A filter that is applied at the pixel level. The filters of a geom are applied to the output
+/// of its material, in order.
+///
+/// Most filters only depend on the colour of a single pixel and are applied while the geom is
+/// rendered. Spatial filters (`Blur` and `Convolution`) also depend on the neighbouring pixels,
+/// so a geom with spatial filters is first rendered into an intermediate texture, filtered in
+/// one or more additional passes, and then blended with the render target.
+#[derive(Debug, Clone, PartialEq)]
 pub enum PixelFilter {
     /// A simple grayscale filter that averages the RGB values.
     Grayscale,
     /// Invert filter that inverts the RGB values.
     Invert,
-    /// A per-channel threshold filter.
+    /// A per-channel threshold filter. Each RGB channel is set to 1 if it is at least the
+    /// threshold, and to 0 otherwise. The alpha channel is not changed.
     Threshold { threshold: Colour },
-    /// A Gaussian envelope filter.
+    /// A Gaussian envelope filter, which multiplies the alpha channel by a Gaussian.
     GaussianEnvelope {
-        /// The center of the envelope, relative to geometry.
+        /// The center of the envelope, relative to the centre of the geometry's bounding box.
         center: Point2D,
         /// The standard deviation of the envelope in x and y.
         sigma: Vector2,
         /// The rotation of the envelope in degrees.
         rotation: f32,
     },
+    /// Adjusts the RGB values: `((c - 0.5) * contrast + 0.5 + brightness) ^ (1 / gamma)`.
+    /// Negative values are clamped to zero before the gamma is applied.
+    Adjust {
+        /// The value added to each channel. Neutral is 0.
+        brightness: f32,
+        /// The factor by which the channels are scaled around 0.5. Neutral is 1.
+        contrast: f32,
+        /// The gamma. Neutral is 1.
+        gamma: f32,
+    },
+    /// An arbitrary colour transform, given as a row-major 4x5 matrix. Each output channel
+    /// (RGBA) is `m[0] * r + m[1] * g + m[2] * b + m[3] * a + m[4]`, using its row of the matrix.
+    ColourMatrix([f32; 20]),
+    /// A Gaussian blur with the given standard deviation in pixels, which must be finite and at
+    /// most `MAX_BLUR_SIGMA`.
+    Blur { sigma: f32 },
+    /// Convolves the geom with a kernel of the given size, given row by row. The kernel is
+    /// centred on each pixel and is not flipped.
+    Convolution {
+        kernel: Vec<f32>,
+        width: u32,
+        height: u32,
+    },
+}
+
+impl PixelFilter {
+    /// Returns true if the filter depends on neighbouring pixels and thus needs its own pass.
+    pub fn is_spatial(&self) -> bool {
+        matches!(
+            self,
+            PixelFilter::Blur { .. } | PixelFilter::Convolution { .. }
+        )
+    }
+
+    /// Returns the kernels of the passes of a spatial filter as `(kernel, width, height)`. A blur
+    /// is split into a horizontal and a vertical pass.
+    pub fn kernels(&self) -> Vec<(Vec<f32>, u32, u32)> {
+        match self {
+            PixelFilter::Blur { sigma } if *sigma > 0.0 => {
+                let radius = (3.0 * sigma).ceil() as i32;
+                let mut kernel: Vec<f32> = (-radius..=radius)
+                    .map(|x| (-0.5 * (x * x) as f32 / (sigma * sigma)).exp())
+                    .collect();
+                let sum: f32 = kernel.iter().sum();
+                kernel.iter_mut().for_each(|w| *w /= sum);
+
+                let size = kernel.len() as u32;
+                vec![(kernel.clone(), size, 1), (kernel, 1, size)]
+            }
+            PixelFilter::Blur { .. } => vec![(vec![1.0], 1, 1)],
+            PixelFilter::Convolution {
+                kernel,
+                width,
+                height,
+            } => vec![(kernel.clone(), *width, *height)],
+            _ => vec![],
+        }
+    }
+
+    /// Returns the uniforms of the filter. The kind of filter must match `apply_filter_range` in
+    /// `common.wgsl`. Spatial filters are applied in their own passes and have no uniforms.
+    pub fn uniforms(&self) -> FilterUniforms {
+        let mut params = [[0.0; 4]; 5];
+        let kind = match self {
+            PixelFilter::Grayscale => 0,
+            PixelFilter::Invert => 1,
+            PixelFilter::Threshold { threshold } => {
+                params[0] = [threshold.r, threshold.g, threshold.b, threshold.a];
+                2
+            }
+            PixelFilter::GaussianEnvelope {
+                center,
+                sigma,
+                rotation,
+            } => {
+                params[0] = [center.x, center.y, sigma.x, sigma.y];
+                params[1] = [rotation.to_radians(), 0.0, 0.0, 0.0];
+                3
+            }
+            PixelFilter::ColourMatrix(matrix) => {
+                // the shader expects the columns of the matrix
+                for (column, param) in params.iter_mut().enumerate() {
+                    for (row, value) in param.iter_mut().enumerate() {
+                        *value = matrix[row * 5 + column];
+                    }
+                }
+                4
+            }
+            PixelFilter::Adjust {
+                brightness,
+                contrast,
+                gamma,
+            } => {
+                params[0] = [*brightness, *contrast, *gamma, 0.0];
+                5
+            }
+            PixelFilter::Blur { .. } | PixelFilter::Convolution { .. } => u32::MAX,
+        };
+
+        FilterUniforms {
+            kind,
+            _padding: [0; 3],
+            params,
+        }
+    }
 }
 
 /// A hashable f32 that de-references to a f32.
@@ -0,0 +1,384 @@
+// Golden tests of blend modes and pixel and spatial filters.
+
+use super::*;
+
+#[test]
+fn blend_modes() {
+    let mut harness = Harness::new();
+
+    let dst = [0.6, 0.4, 0.8];
+    let src = [0.5, 0.5, 0.25];
+    let src_alpha = 0.5;
+
+    let blended = |colour: Colour, blend_mode: BlendMode| {
+        square(16.0, 16.0, 32.0, Material::Colour(colour)).with_blend_mode(blend_mode)
+    };
+
+    let expected = |blend_mode: BlendMode, d: f32, s: f32| match blend_mode {
+        BlendMode::Alpha => s * src_alpha + d * (1.0 - src_alpha),
+        BlendMode::PremultipliedAlpha => s + d * (1.0 - src_alpha),
+        BlendMode::Additive => d + s * src_alpha,
+        BlendMode::Subtractive => d - s * src_alpha,
+        BlendMode::Multiply => d * s,
+        BlendMode::Min => d.min(s),
+        BlendMode::Max => d.max(s),
+        BlendMode::Replace => s,
+    };
+
+    for blend_mode in [
+        BlendMode::Alpha,
+        BlendMode::PremultipliedAlpha,
+        BlendMode::Additive,
+        BlendMode::Subtractive,
+        BlendMode::Multiply,
+        BlendMode::Min,
+        BlendMode::Max,
+        BlendMode::Replace,
+    ] {
+        let image = harness.render(&[
+            blended(Colour::new(dst[0], dst[1], dst[2], 1.0), BlendMode::Replace),
+            blended(Colour::new(src[0], src[1], src[2], src_alpha), blend_mode),
+        ]);
+
+        let pixel = image.get_pixel(32, 32);
+        for c in 0..3 {
+            let expected = expected(blend_mode, dst[c], src[c]);
+            assert!(
+                (pixel[c] - expected).abs() < 1e-3,
+                "{blend_mode:?}: channel {c} is {}, expected {expected}",
+                pixel[c]
+            );
+        }
+
+        // only `Replace` changes the alpha of an opaque target
+        let expected_alpha = if blend_mode == BlendMode::Replace {
+            src_alpha
+        } else {
+            1.0
+        };
+        assert!((pixel[3] - expected_alpha).abs() < 1e-3, "{blend_mode:?}");
+    }
+}
+
+#[test]
+fn pixel_filters() {
+    let mut harness = Harness::new();
+
+    let filtered = |material: Material, filters: Vec<PixelFilter>| {
+        let mut geom = square(16.0, 16.0, 32.0, material);
+        geom.filters = filters;
+        geom
+    };
+
+    let colour = Colour::new(0.2, 0.5, 0.8, 1.0);
+    let threshold = PixelFilter::Threshold {
+        threshold: Colour::new(0.5, 0.5, 0.5, 0.5),
+    };
+
+    // filters are applied in order
+    for (filters, expected) in [
+        (vec![], [0.2, 0.5, 0.8]),
+        (vec![PixelFilter::Grayscale], [0.5, 0.5, 0.5]),
+        (vec![PixelFilter::Invert], [0.8, 0.5, 0.2]),
+        (vec![threshold.clone()], [0.0, 1.0, 1.0]),
+        (
+            vec![PixelFilter::Invert, threshold.clone()],
+            [1.0, 1.0, 0.0],
+        ),
+        (
+            vec![threshold.clone(), PixelFilter::Invert],
+            [1.0, 0.0, 0.0],
+        ),
+    ] {
+        let image = harness.render(&[filtered(Material::Colour(colour), filters.clone())]);
+
+        let pixel = image.get_pixel(32, 32);
+        for c in 0..3 {
+            assert!(
+                (pixel[c] - expected[c]).abs() < 1e-3,
+                "{filters:?}: channel {c} is {}, expected {}",
+                pixel[c],
+                expected[c]
+            );
+        }
+    }
+
+    // the envelope fades out the alpha around the centre of the geom
+    let sigma = 8.0;
+    let envelope = PixelFilter::GaussianEnvelope {
+        center: Point2D::new(0.0, 0.0),
+        sigma: Vector2::new(sigma, sigma),
+        rotation: 0.0,
+    };
+    let image = harness.render(&[filtered(
+        Material::Colour(Colour::WHITE),
+        vec![envelope.clone()],
+    )]);
+    for x in [32, 36, 40, 46] {
+        // the pixel centre is half a pixel away from the centre of the square in each direction
+        let (dx, dy) = (x as f32 + 0.5 - 32.0, 0.5);
+        let expected = (-0.5 * (dx * dx + dy * dy) / (sigma * sigma)).exp();
+        let pixel = image.get_pixel(x, 32);
+        assert!(
+            (pixel[0] - expected).abs() < 2e-3,
+            "x = {x}: {} != {expected}",
+            pixel[0]
+        );
+    }
+
+    // filters work with every material
+    let textured = Material::Texture(TextureMaterial {
+        texture: test_texture(),
+        size_x: TextureSize::Original,
+        size_y: TextureSize::Original,
+        repeat_x: TextureRepeat::Clamp,
+        repeat_y: TextureRepeat::Clamp,
+        filter: TextureFilter::Nearest,
+    });
+    let image = harness.render_fresh(&[filtered(textured, vec![envelope, PixelFilter::Grayscale])]);
+    assert_golden("pixel_filters_texture", &image);
+}
+
+#[test]
+fn colour_filters() {
+    let mut harness = Harness::new();
+
+    #[rustfmt::skip]
+    let swap_red_blue = PixelFilter::ColourMatrix([
+        0.0, 0.0, 1.0, 0.0, 0.1,
+        0.0, 1.0, 0.0, 0.0, 0.0,
+        1.0, 0.0, 0.0, 0.0, 0.0,
+        0.0, 0.0, 0.0, 1.0, 0.0,
+    ]);
+    let adjust = |brightness, contrast, gamma| PixelFilter::Adjust {
+        brightness,
+        contrast,
+        gamma,
+    };
+
+    for (filter, expected) in [
+        (swap_red_blue, [0.9, 0.5, 0.2]),
+        (adjust(0.0, 1.0, 1.0), [0.2, 0.5, 0.8]),
+        (adjust(0.1, 1.0, 1.0), [0.3, 0.6, 0.9]),
+        (adjust(0.0, 2.0, 1.0), [0.0, 0.5, 1.1]),
+        (
+            adjust(0.0, 1.0, 2.0),
+            [0.2f32.sqrt(), 0.5f32.sqrt(), 0.8f32.sqrt()],
+        ),
+    ] {
+        let mut geom = square(
+            16.0,
+            16.0,
+            32.0,
+            Material::Colour(Colour::new(0.2, 0.5, 0.8, 1.0)),
+        );
+        geom.filters = vec![filter.clone()];
+        let image = harness.render(&[geom]);
+
+        let pixel = image.get_pixel(32, 32);
+        for c in 0..3 {
+            assert!(
+                (pixel[c] - expected[c]).abs() < 2e-3,
+                "{filter:?}: channel {c} is {}, expected {}",
+                pixel[c],
+                expected[c]
+            );
+        }
+    }
+}
+
+#[test]
+fn spatial_filters() {
+    let mut harness = Harness::new();
+
+    let filtered = |colour: Colour, filters: Vec<PixelFilter>| {
+        let mut geom = square(16.0, 16.0, 32.0, Material::Colour(colour));
+        geom.filters = filters;
+        geom
+    };
+    let mut render_square =
+        |colour: Colour, filters: Vec<PixelFilter>| harness.render(&[filtered(colour, filters)]);
+
+    // identity kernels do not change the result
+    let unfiltered = render_square(Colour::RED, vec![]);
+    for (kernel, width, height) in [
+        (vec![1.0], 1, 1),
+        (vec![0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0], 3, 3),
+    ] {
+        let convolution = PixelFilter::Convolution {
+            kernel,
+            width,
+            height,
+        };
+        let image = render_square(Colour::RED, vec![convolution]);
+        for (a, b) in image.pixels().zip(unfiltered.pixels()) {
+            assert!(a.0.iter().zip(b.0).all(|(a, b)| (a - b).abs() < 1e-3));
+        }
+    }
+
+    // the kernel is not flipped, so a weight on the left moves the geom to the right
+    let shift = PixelFilter::Convolution {
+        kernel: vec![1.0, 0.0, 0.0],
+        width: 3,
+        height: 1,
+    };
+    let image = render_square(Colour::WHITE, vec![shift]);
+    assert_eq!(image.get_pixel(16, 32)[0], 0.0);
+    assert_eq!(image.get_pixel(48, 32)[0], 1.0);
+
+    // a blur softens the edges symmetrically and keeps the centre
+    let image = render_square(Colour::WHITE, vec![PixelFilter::Blur { sigma: 2.0 }]);
+    assert!((image.get_pixel(32, 32)[0] - 1.0).abs() < 1e-3);
+    let edge = image.get_pixel(16, 32)[0];
+    assert!(0.3 < edge && edge < 0.7, "edge is {edge}");
+    for k in 0..8 {
+        let left = image.get_pixel(12 + k, 32)[0];
+        let right = image.get_pixel(51 - k, 32)[0];
+        let top = image.get_pixel(32, 12 + k)[0];
+        assert!((left - right).abs() < 2e-3, "{left} != {right}");
+        assert!((left - top).abs() < 2e-3, "{left} != {top}");
+    }
+
+    // pixel filters after a spatial filter are applied to its result
+    let image = render_square(
+        Colour::new(0.2, 0.5, 0.8, 1.0),
+        vec![PixelFilter::Blur { sigma: 1.0 }, PixelFilter::Invert],
+    );
+    let pixel = image.get_pixel(32, 32);
+    for (c, expected) in [0.8, 0.5, 0.2].into_iter().enumerate() {
+        assert!((pixel[c] - expected).abs() < 2e-3);
+    }
+
+    // layers only cover the part of the target that the filters can spread the geom over
+    let corner = |filters: Vec<PixelFilter>| {
+        let mut geom = square(40.0, 40.0, 8.0, Material::Colour(Colour::WHITE));
+        geom.filters = filters;
+        geom
+    };
+    let image = harness.render(&[corner(vec![PixelFilter::Blur { sigma: 1.0 }])]);
+    let layer = &harness.renderer.filter_textures[1].0;
+    assert!(layer.width() < SIZE && layer.height() < SIZE);
+    for k in 0..4 {
+        let left = image.get_pixel(39 - k, 44)[0];
+        let right = image.get_pixel(48 + k, 44)[0];
+        assert!((left - right).abs() < 2e-3, "{left} != {right}");
+    }
+
+    // unless a pixel filter makes transparent pixels visible
+    let mut matrix = [0.0; 20];
+    for (row, offset) in [(0, 1.0), (3, 0.5)] {
+        matrix[row * 5 + row] = 1.0;
+        matrix[row * 5 + 4] = offset;
+    }
+    let image = harness.render(&[corner(vec![
+        PixelFilter::Blur { sigma: 1.0 },
+        PixelFilter::ColourMatrix(matrix),
+    ])]);
+    let layer = &harness.renderer.filter_textures[1].0;
+    assert_eq!((layer.width(), layer.height()), (SIZE, SIZE));
+    assert!((image.get_pixel(2, 2)[0] - 0.5).abs() < 2e-3);
+
+    // invalid kernels are rejected
+    let invalid = PixelFilter::Convolution {
+        kernel: vec![1.0; 4],
+        width: 3,
+        height: 3,
+    };
+    assert!(matches!(
+        harness.try_render(&[filtered(Colour::WHITE, vec![invalid])]),
+        Err(RendererError::InvalidGeom { index: 0, source }) if matches!(
+            *source,
+            RendererError::InvalidKernel {
+                expected: 9,
+                actual: 4
+            }
+        )
+    ));
+
+    // filtered geoms keep their place when batching reorders the draws
+    let geoms = || {
+        let dot = |x: f32, y: f32, colour: Colour, filters: Vec<PixelFilter>| {
+            Geom::new(
+                Primitive::Circle {
+                    center: Point2D::new(x, y),
+                    radius: 10.0,
+                },
+                Material::Colour(colour),
+                Some(pixel_space()),
+                filters,
+                TessellationOptions::simple_fill(),
+            )
+        };
+        vec![
+            dot(20.0, 20.0, Colour::RED, vec![]),
+            dot(
+                32.0,
+                32.0,
+                Colour::GREEN,
+                vec![PixelFilter::Blur { sigma: 3.0 }],
+            ),
+            dot(44.0, 44.0, Colour::RED, vec![]),
+            dot(
+                20.0,
+                44.0,
+                Colour::BLUE,
+                vec![PixelFilter::Blur { sigma: 1.0 }],
+            ),
+        ]
+    };
+    let expected = harness.render_fresh(&geoms());
+    harness.renderer.set_batching(Batching::Reorder);
+    let image = harness
+        .renderer
+        .render_to_rgba8_image(
+            &harness.device,
+            &harness.queue,
+            SIZE,
+            SIZE,
+            &geoms(),
+            Colour::BLACK,
+        )
+        .unwrap();
+    assert_eq!(diff(&image, &expected, 0).0, 0);
+
+    // spatial filters work with every material
+    let textured = Material::Texture(TextureMaterial {
+        texture: test_texture(),
+        size_x: TextureSize::Original,
+        size_y: TextureSize::Original,
+        repeat_x: TextureRepeat::Repeat,
+        repeat_y: TextureRepeat::Repeat,
+        filter: TextureFilter::Nearest,
+    });
+    let edges = PixelFilter::Convolution {
+        kernel: vec![0.0, -1.0, 0.0, -1.0, 4.0, -1.0, 0.0, -1.0, 0.0],
+        width: 3,
+        height: 3,
+    };
+    let geoms = [
+        Geom::new(
+            Primitive::Rectangle {
+                a: Point2D::new(4.0, 4.0),
+                b: Point2D::new(28.0, 60.0),
+                rotation: 0.0,
+            },
+            textured.clone(),
+            Some(pixel_space()),
+            vec![PixelFilter::Blur { sigma: 1.5 }],
+            TessellationOptions::simple_fill(),
+        ),
+        Geom::new(
+            Primitive::Rectangle {
+                a: Point2D::new(36.0, 4.0),
+                b: Point2D::new(60.0, 60.0),
+                rotation: 0.0,
+            },
+            textured,
+            Some(pixel_space()),
+            vec![PixelFilter::Grayscale, edges, PixelFilter::Invert],
+            TessellationOptions::simple_fill(),
+        ),
+    ];
+    let image = harness.render_fresh(&geoms);
+    assert_golden("spatial_filters", &image);
+}
@@ -0,0 +1,318 @@
+// Tests of the state the renderer keeps between frames: the caches, the buffers, batching,
+// instancing and recovering from errors.
+
+use super::*;
+
+#[test]
+fn tessellation_cache_tracks_changes() {
+    let (device, queue) = device();
+    let mut renderer = Renderer::new(&device);
+
+    let mut geoms = vec![Geom::new(
+        Primitive::Circle {
+            center: Point2D::new(32.0, 32.0),
+            radius: 20.0,
+        },
+        Material::Colour(Colour::WHITE),
+        Some(pixel_space()),
+        vec![],
+        TessellationOptions::simple_fill(),
+    )];
+
+    let frame = |renderer: &mut Renderer, geoms: &[Geom]| {
+        let image = renderer
+            .render_to_rgba8_image(&device, &queue, SIZE, SIZE, geoms, Colour::BLACK)
+            .unwrap();
+        let key = TessellationKey {
+            geom: &geoms[0],
+            tolerance: geoms[0].options.tolerance().resolve(1.0),
+        };
+        let (vertices, _) = renderer
+            .tesselation_cache
+            .get(&key)
+            .expect("tessellation is cached");
+        (image, vertices.as_ptr())
+    };
+
+    // an unchanged geom reuses its tessellation
+    let (first, first_vertices) = frame(&mut renderer, &geoms);
+    let (second, second_vertices) = frame(&mut renderer, &geoms);
+    assert_eq!(first, second);
+    assert_eq!(first_vertices, second_vertices);
+
+    // a changed primitive is tessellated again
+    geoms[0].primitive = Primitive::Circle {
+        center: Point2D::new(32.0, 32.0),
+        radius: 10.0,
+    };
+    let (third, _) = frame(&mut renderer, &geoms);
+    assert_ne!(first, third);
+    assert_eq!(third, render(&device, &queue, &geoms));
+}
+
+#[test]
+fn buffers_grow_and_shrink() {
+    let (device, queue) = device();
+    let mut renderer = Renderer::new(&device);
+
+    // one textured dot per pixel, far more than fits into the initial buffers
+    let dots: Vec<Geom> = (0..SIZE * SIZE)
+        .map(|i| {
+            Geom::new(
+                Primitive::Circle {
+                    center: Point2D::new((i % SIZE) as f32 + 0.5, (i / SIZE) as f32 + 0.5),
+                    radius: 0.4,
+                },
+                Material::Texture(TextureMaterial {
+                    texture: test_texture(),
+                    size_x: TextureSize::Original,
+                    size_y: TextureSize::Original,
+                    repeat_x: TextureRepeat::Clamp,
+                    repeat_y: TextureRepeat::Clamp,
+                    filter: TextureFilter::Nearest,
+                }),
+                Some(pixel_space()),
+                vec![],
+                TessellationOptions::simple_fill(),
+            )
+        })
+        .collect();
+
+    let image = renderer
+        .render_to_rgba8_image(&device, &queue, SIZE, SIZE, &dots, Colour::BLACK)
+        .unwrap();
+    assert!(image.pixels().all(|p| *p != Rgba([0, 0, 0, 255])));
+
+    // shrinking after a small frame must not break the next frame
+    let geoms = vec![square(10.0, 10.0, 10.0, Material::Colour(Colour::WHITE))];
+    let expected = render(&device, &queue, &geoms);
+    renderer
+        .render_to_rgba8_image(&device, &queue, SIZE, SIZE, &geoms, Colour::BLACK)
+        .unwrap();
+    renderer.shrink_buffers(&device);
+    let actual = renderer
+        .render_to_rgba8_image(&device, &queue, SIZE, SIZE, &geoms, Colour::BLACK)
+        .unwrap();
+    assert_eq!(actual, expected);
+
+    // the global bind group is kept while its buffers and the binding size stay the same
+    let bind_group = renderer.bind_group.global_id();
+    renderer
+        .render_to_rgba8_image(&device, &queue, SIZE, SIZE, &geoms, Colour::BLACK)
+        .unwrap();
+    assert_eq!(renderer.bind_group.global_id(), bind_group);
+}
+
+#[test]
+fn batching_preserves_the_result() {
+    let (device, queue) = device();
+
+    // all textured squares share the same texture, so they can be drawn together
+    let texture = test_texture();
+    let textured = || {
+        Material::Texture(TextureMaterial {
+            texture: texture.clone(),
+            size_x: TextureSize::Original,
+            size_y: TextureSize::Original,
+            repeat_x: TextureRepeat::Clamp,
+            repeat_y: TextureRepeat::Clamp,
+            filter: TextureFilter::Nearest,
+        })
+    };
+    // a row of separate squares with alternating materials, followed by a stack of overlapping
+    // squares whose order must be kept
+    let mut geoms: Vec<Geom> = (0..8)
+        .map(|i| {
+            let material = if i % 2 == 0 {
+                Material::Colour(Colour::new(i as f32 / 8.0, 0.5, 1.0, 1.0))
+            } else {
+                textured()
+            };
+            square(i as f32 * 8.0 + 1.0, 2.0, 6.0, material)
+        })
+        .collect();
+    geoms.push(square(10.0, 20.0, 30.0, Material::Colour(Colour::RED)));
+    geoms.push(square(20.0, 28.0, 30.0, textured()));
+    geoms.push(square(30.0, 36.0, 26.0, Material::Colour(Colour::GREEN)));
+
+    let surface_desc = surface_desc();
+
+    let expected = render(&device, &queue, &geoms);
+    for (batching, draws) in [
+        (Batching::None, 11),
+        (Batching::Consecutive, 11),
+        (Batching::Reorder, 3),
+    ] {
+        let mut renderer = Renderer::new(&device);
+        renderer.set_batching(batching);
+
+        let rdata = renderer
+            .prepare(&device, &queue, &surface_desc, &geoms)
+            .unwrap();
+        assert_eq!(rdata.draws.len(), draws, "{batching:?}");
+
+        let actual = renderer
+            .render_to_rgba8_image(&device, &queue, SIZE, SIZE, &geoms, Colour::BLACK)
+            .unwrap();
+        assert_eq!(actual, expected, "{batching:?}");
+    }
+
+    // consecutive geoms with the same material are merged
+    geoms.sort_by_key(|geom| geom.material.has_texture());
+    let mut renderer = Renderer::new(&device);
+    renderer.set_batching(Batching::Consecutive);
+    let rdata = renderer
+        .prepare(&device, &queue, &surface_desc, &geoms)
+        .unwrap();
+    assert_eq!(rdata.draws.len(), 2);
+}
+
+#[test]
+fn instances_match_separate_geoms() {
+    let (device, queue) = device();
+
+    let circle = Primitive::Circle {
+        center: Point2D::new(0.0, 0.0),
+        radius: 3.0,
+    };
+    let colour = Colour::new(1.0, 0.8, 0.6, 1.0);
+
+    // a grid of dots with varying tint, opacity and size
+    let instances: Vec<Instance> = (0..64)
+        .map(|i| {
+            let (x, y) = ((i % 8) as f32 * 8.0 + 4.0, (i / 8) as f32 * 8.0 + 4.0);
+            let mut transform = Transformation::translation(x, y);
+            let scale = 0.5 + (i % 3) as f32 * 0.25;
+            transform.a = scale;
+            transform.e = scale;
+            Instance::new(transform)
+                .with_tint(Colour::new(
+                    (i % 8) as f32 / 7.0,
+                    (i / 8) as f32 / 7.0,
+                    1.0,
+                    1.0,
+                ))
+                .with_opacity(1.0 - (i % 5) as f32 * 0.2)
+        })
+        .collect();
+
+    let instanced = Geom::instanced(
+        circle.clone(),
+        Material::Colour(colour),
+        Some(pixel_space()),
+        vec![],
+        TessellationOptions::simple_fill(),
+        instances.clone(),
+    );
+    let actual = render(&device, &queue, &[instanced]);
+
+    let separate: Vec<Geom> = instances
+        .iter()
+        .map(|instance| {
+            let transform = instance.transform;
+            let tint = instance.tint;
+            Geom::new(
+                Primitive::Circle {
+                    center: Point2D::new(transform.g, transform.h),
+                    radius: 3.0 * transform.a,
+                },
+                Material::Colour(Colour::new(
+                    colour.r * tint.r,
+                    colour.g * tint.g,
+                    colour.b * tint.b,
+                    colour.a * tint.a * instance.opacity,
+                )),
+                Some(pixel_space()),
+                vec![],
+                TessellationOptions::simple_fill(),
+            )
+        })
+        .collect();
+    let expected = render(&device, &queue, &separate);
+
+    let (mismatches, _) = diff(&actual, &expected, tolerance());
+    assert_eq!(mismatches, 0);
+
+    assert_golden("instanced_dots", &actual);
+}
+
+#[test]
+fn errors_do_not_poison_the_renderer() {
+    let (device, queue) = device();
+    let mut renderer = Renderer::new(&device);
+
+    let surface_desc = surface_desc();
+
+    let good_geom = square(10.0, 10.0, 10.0, Material::Colour(Colour::WHITE));
+
+    // a texture whose data does not match its size
+    let broken_texture = Texture::from_raw(vec![0; 10], 8, 8, TextureFormat::Rgba8U);
+    let texture_geom = square(
+        10.0,
+        10.0,
+        10.0,
+        Material::Texture(TextureMaterial {
+            texture: broken_texture,
+            size_x: TextureSize::Original,
+            size_y: TextureSize::Original,
+            repeat_x: TextureRepeat::Clamp,
+            repeat_y: TextureRepeat::Clamp,
+            filter: TextureFilter::Nearest,
+        }),
+    );
+
+    // primitives with NaN coordinates cannot be tessellated
+    let nan_geom = Geom::new(
+        Primitive::Circle {
+            center: Point2D::new(f32::NAN, 32.0),
+            radius: 10.0,
+        },
+        Material::Colour(Colour::WHITE),
+        Some(pixel_space()),
+        vec![],
+        TessellationOptions::simple_line(2.0),
+    );
+
+    // the bad geoms are skipped and reported, and the rest of the frame is still drawn
+    let mut geoms = vec![texture_geom, good_geom, nan_geom];
+    let rdata = renderer
+        .prepare(&device, &queue, &surface_desc, &geoms)
+        .unwrap();
+    assert_eq!(rdata.draws.len(), 1);
+    assert!(matches!(
+        rdata.errors.as_slice(),
+        [
+            (
+                0,
+                RendererError::InvalidTextureData {
+                    expected: 256,
+                    actual: 10
+                }
+            ),
+            (2, RendererError::NonFinitePrimitive)
+        ]
+    ));
+
+    // offscreen rendering reports the first bad geom
+    assert!(matches!(
+        renderer.render_to_rgba8_image(&device, &queue, SIZE, SIZE, &geoms, Colour::BLACK),
+        Err(RendererError::InvalidGeom { index: 0, .. })
+    ));
+
+    // images of a different size cannot replace the image of a texture
+    let mut texture = test_texture();
+    assert!(matches!(
+        texture.update_image(RgbaImage::new(4, 4).into()),
+        Err(RendererError::TextureSizeMismatch {
+            expected: (8, 8),
+            actual: (4, 4)
+        })
+    ));
+
+    // the renderer can still be used afterwards
+    let geoms = vec![geoms.remove(1)];
+    let actual = renderer
+        .render_to_rgba8_image(&device, &queue, SIZE, SIZE, &geoms, Colour::BLACK)
+        .unwrap();
+    assert_eq!(actual, render(&device, &queue, &geoms));
+}
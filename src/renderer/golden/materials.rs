@@ -0,0 +1,448 @@
+// Golden tests of textures, gradients and custom materials.
+
+use super::*;
+
+#[test]
+fn sampler_settings_are_per_geom() {
+    let (device, queue) = device();
+    let texture = test_texture();
+
+    let textured = |x: f32, filter: TextureFilter, repeat: TextureRepeat| {
+        Geom::new(
+            Primitive::Rectangle {
+                a: Point2D::new(x, 16.0),
+                b: Point2D::new(x + 24.0, 48.0),
+                rotation: 0.0,
+            },
+            Material::Texture(TextureMaterial {
+                texture: texture.clone(),
+                size_x: TextureSize::Relative(0.5),
+                size_y: TextureSize::Relative(0.5),
+                repeat_x: repeat,
+                repeat_y: repeat,
+                filter,
+            }),
+            Some(pixel_space()),
+            vec![],
+            TessellationOptions::simple_fill(),
+        )
+    };
+
+    let linear_clamp = || textured(4.0, TextureFilter::Linear, TextureRepeat::Clamp);
+    let nearest_repeat = || textured(36.0, TextureFilter::Nearest, TextureRepeat::Repeat);
+
+    // the sampler of the first geom must not be used for the second one, whatever the order
+    let forward = render(&device, &queue, &[linear_clamp(), nearest_repeat()]);
+    let backward = render(&device, &queue, &[nearest_repeat(), linear_clamp()]);
+    assert_eq!(forward, backward);
+
+    assert_golden("sampler_settings", &forward);
+
+    // textures that do not repeat need border colours, which are reported if the device does
+    // not support them
+    let geoms = [textured(4.0, TextureFilter::Nearest, TextureRepeat::None)];
+    let result =
+        Renderer::new(&device).render_to_image(&device, &queue, SIZE, SIZE, &geoms, Colour::BLACK);
+    if device
+        .features()
+        .contains(wgpu::Features::ADDRESS_MODE_CLAMP_TO_BORDER)
+    {
+        assert!(result.is_ok());
+    } else {
+        assert!(matches!(
+            result,
+            Err(RendererError::InvalidGeom { index: 0, source })
+                if matches!(*source, RendererError::UnsupportedFeatures(_))
+        ));
+    }
+}
+
+#[test]
+fn gradients() {
+    let mut harness = Harness::new();
+
+    // a ramp of four colours whose index is encoded in the red and green channels, so that it can
+    // be read back
+    let ramp = RgbaImage::from_fn(4, 1, |x, _| {
+        Rgba([(x & 1) as u8 * 255, (x >> 1) as u8 * 255, 0, 255])
+    });
+    let ramp = Texture::from_image(ramp.into(), TextureFormat::Rgba8U);
+
+    let gradient = |gradient_type, extent, repeat, rotation| {
+        Material::Gradient(GradientMaterial {
+            gradient_type,
+            extent,
+            repeat,
+            centre: Point2D::new(0.0, 0.0),
+            ramp_texture: ramp.clone(),
+            rotation,
+        })
+    };
+    let mut render_square = |material: Material| {
+        let image = harness.render(&[square(16.0, 16.0, 32.0, material)]);
+        move |x: u32, y: u32| {
+            let pixel = image.get_pixel(x, y);
+            (pixel[0] > 0.5) as u32 + 2 * (pixel[1] > 0.5) as u32
+        }
+    };
+
+    use GradientExtent::*;
+    use GradientRepeatMode::*;
+    use GradientType::*;
+
+    // a linear gradient that fills the square, centred on the square
+    let level = render_square(gradient(Linear, Fill, Clamp, 0.0));
+    assert_eq!([17, 30, 34, 46].map(|x| level(x, 32)), [0, 1, 2, 3]);
+    assert_eq!([17, 30, 34, 46].map(|y| level(31, y)), [1, 1, 1, 1]);
+
+    // the rotation is the direction of the gradient
+    let level = render_square(gradient(Linear, Fill, Clamp, 90.0));
+    assert_eq!([17, 30, 34, 46].map(|y| level(32, y)), [0, 1, 2, 3]);
+    let level = render_square(gradient(Linear, Fill, Clamp, 180.0));
+    assert_eq!([17, 30, 34, 46].map(|x| level(x, 32)), [3, 2, 1, 0]);
+
+    // a relative extent is a fraction of the fill extent, and the last colour is repeated
+    let level = render_square(gradient(Linear, Relative(0.5), Clamp, 0.0));
+    assert_eq!(
+        [17, 25, 29, 33, 37, 46].map(|x| level(x, 32)),
+        [0, 0, 1, 2, 3, 3]
+    );
+
+    // an absolute extent is in pixels, and the gradient can repeat
+    let level = render_square(gradient(Linear, Absolute(8.0), Repeat, 0.0));
+    assert_eq!(
+        [28, 30, 32, 34, 36, 38].map(|x| level(x, 32)),
+        [0, 1, 2, 3, 0, 1]
+    );
+
+    // radial gradients start at the centre
+    let level = render_square(gradient(Radial, Absolute(8.0), Clamp, 0.0));
+    assert_eq!(
+        [32, 34, 36, 38, 40, 46].map(|x| level(x, 32)),
+        [0, 1, 2, 3, 3, 3]
+    );
+    assert_eq!(level(32, 38), level(38, 32));
+    let level = render_square(gradient(Radial, Absolute(8.0), Repeat, 0.0));
+    assert_eq!([36, 38, 40, 42].map(|x| level(x, 32)), [2, 3, 0, 1]);
+
+    // conic gradients revolve around the centre, starting at the rotation
+    let level = render_square(gradient(Conic, Fill, Clamp, 0.0));
+    assert_eq!(
+        [(44, 33), (31, 44), (20, 31), (32, 20)].map(|(x, y)| level(x, y)),
+        [0, 1, 2, 3]
+    );
+    let level = render_square(gradient(Conic, Fill, Clamp, 90.0));
+    assert_eq!(
+        [(31, 44), (20, 31), (32, 20), (44, 33)].map(|(x, y)| level(x, y)),
+        [0, 1, 2, 3]
+    );
+    let level = render_square(gradient(Conic, Absolute(180.0), Repeat, 0.0));
+    assert_eq!(
+        [(44, 33), (31, 44), (20, 31), (32, 20)].map(|(x, y)| level(x, y)),
+        [0, 2, 0, 2]
+    );
+
+    // absolute extents stay in pixels when the transform scales the primitive by one half
+    #[rustfmt::skip]
+    let scaled = Transformation::from(nalgebra::Matrix3::new(
+        -0.5, 0.0, 0.0,
+        0.0, 0.5, 0.0,
+        -1.0, 1.0, 1.0,
+    ));
+    let mut render_scaled = |material: Material| {
+        let mut geom = square(32.0, 32.0, 64.0, material);
+        geom.transform = Some(scaled);
+        let image = harness.render(&[geom]);
+        move |x: u32, y: u32| {
+            let pixel = image.get_pixel(x, y);
+            (pixel[0] > 0.5) as u32 + 2 * (pixel[1] > 0.5) as u32
+        }
+    };
+    let level = render_scaled(gradient(Linear, Absolute(8.0), Repeat, 0.0));
+    assert_eq!(
+        [28, 30, 32, 34, 36, 38].map(|x| level(x, 32)),
+        [0, 1, 2, 3, 0, 1]
+    );
+    let level = render_scaled(gradient(Radial, Absolute(8.0), Clamp, 0.0));
+    assert_eq!(
+        [32, 34, 36, 38, 40, 46].map(|x| level(x, 32)),
+        [0, 1, 2, 3, 3, 3]
+    );
+
+    // smooth gradients on different shapes and with an offset centre
+    let smooth = RgbaImage::from_fn(64, 1, |x, _| {
+        Rgba([(x * 4) as u8, 64, 255 - (x * 4) as u8, 255])
+    });
+    let smooth = Texture::from_image(smooth.into(), TextureFormat::Rgba8U);
+    let shape = |primitive: Primitive, gradient_type, extent, repeat, rotation| {
+        Geom::new(
+            primitive,
+            Material::Gradient(GradientMaterial {
+                gradient_type,
+                extent,
+                repeat,
+                centre: Point2D::new(3.0, -2.0),
+                ramp_texture: smooth.clone(),
+                rotation,
+            }),
+            Some(pixel_space()),
+            vec![],
+            TessellationOptions::simple_fill(),
+        )
+    };
+    let geoms = [
+        shape(
+            Primitive::Rectangle {
+                a: Point2D::new(2.0, 2.0),
+                b: Point2D::new(30.0, 30.0),
+                rotation: 0.0,
+            },
+            Linear,
+            Fill,
+            Clamp,
+            30.0,
+        ),
+        shape(
+            Primitive::Circle {
+                center: Point2D::new(48.0, 16.0),
+                radius: 14.0,
+            },
+            Radial,
+            Relative(0.5),
+            Repeat,
+            0.0,
+        ),
+        shape(
+            Primitive::Ellipse {
+                center: Point2D::new(16.0, 48.0),
+                radii: Vector2::new(14.0, 10.0),
+                rotation: 0.0,
+            },
+            Conic,
+            Fill,
+            Clamp,
+            -45.0,
+        ),
+        shape(
+            Primitive::Rectangle {
+                a: Point2D::new(34.0, 34.0),
+                b: Point2D::new(62.0, 62.0),
+                rotation: 0.0,
+            },
+            Linear,
+            Absolute(10.0),
+            Repeat,
+            45.0,
+        ),
+    ];
+    let image = harness.render_fresh(&geoms);
+    assert_golden("gradients", &image);
+}
+
+#[test]
+fn gradient_stops() {
+    let mut harness = Harness::new();
+
+    let bar = |y: f32, material: GradientMaterial| {
+        Geom::new(
+            Primitive::Rectangle {
+                a: Point2D::new(0.0, y),
+                b: Point2D::new(64.0, y + 16.0),
+                rotation: 0.0,
+            },
+            Material::Gradient(material),
+            Some(pixel_space()),
+            vec![],
+            TessellationOptions::simple_fill(),
+        )
+    };
+
+    // the midpoint of a gradient from black to white is grey in the interpolation space, i.e.
+    // has the given sRGB-encoded value
+    let black_to_white = [(0.0, Colour::BLACK), (1.0, Colour::WHITE)];
+    for (interpolation, expected) in [
+        (ColourInterpolation::Srgb, 0.5),
+        (ColourInterpolation::LinearRgb, 0.7354),
+        (ColourInterpolation::Lab, 0.4663),
+        (ColourInterpolation::OkLab, 0.3888),
+    ] {
+        let gradient =
+            GradientMaterial::from_stops(GradientType::Linear, &black_to_white, interpolation);
+        let image = harness.render(&[bar(0.0, gradient)]);
+
+        // the centre of the gradient lies between two pixels
+        let midpoint = (image.get_pixel(31, 8)[0] + image.get_pixel(32, 8)[0]) / 2.0;
+        assert!(
+            (midpoint - expected).abs() < 0.01,
+            "{interpolation:?}: {midpoint} != {expected}"
+        );
+
+        // the ends have the colours of the stops, and the gradient is smooth and monotonic
+        assert!(image.get_pixel(0, 8)[0] < 0.1);
+        assert!(image.get_pixel(63, 8)[0] > 0.98);
+        for x in 1..SIZE {
+            let step = image.get_pixel(x, 8)[0] - image.get_pixel(x - 1, 8)[0];
+            assert!(
+                step > 0.0 && step < 0.1,
+                "{interpolation:?}: step of {step}"
+            );
+        }
+    }
+
+    // stops are sorted, and the colours of the first and last stop extend to the ends
+    let gradient = GradientMaterial::from_stops(
+        GradientType::Linear,
+        &[(0.75, Colour::BLUE), (0.25, Colour::RED)],
+        ColourInterpolation::Srgb,
+    );
+    let image = harness.render(&[bar(0.0, gradient)]);
+    assert_eq!(image.get_pixel(8, 8).0, [1.0, 0.0, 0.0, 1.0]);
+    assert_eq!(image.get_pixel(56, 8).0, [0.0, 0.0, 1.0, 1.0]);
+
+    // the same stops interpolated in each colour space
+    let stops = [
+        (0.0, Colour::new(0.1, 0.3, 0.9, 1.0)),
+        (0.6, Colour::new(1.0, 0.9, 0.2, 1.0)),
+        (1.0, Colour::new(0.8, 0.1, 0.3, 0.0)),
+    ];
+    let geoms: Vec<Geom> = [
+        ColourInterpolation::Srgb,
+        ColourInterpolation::LinearRgb,
+        ColourInterpolation::Lab,
+        ColourInterpolation::OkLab,
+    ]
+    .into_iter()
+    .enumerate()
+    .map(|(i, interpolation)| {
+        let gradient = GradientMaterial::from_stops(GradientType::Linear, &stops, interpolation);
+        bar(16.0 * i as f32, gradient)
+    })
+    .collect();
+    let image = harness.render_fresh(&geoms);
+    assert_golden("gradient_stops", &image);
+}
+
+/// The parameters of the custom shader in `custom_materials`.
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct RingParams {
+    colour: [f32; 4],
+    frequency: f32,
+    _padding: [f32; 3],
+}
+
+const RING_SHADER: &str = "
+struct Params {
+    colour: vec4<f32>,
+    frequency: f32,
+};
+
+fn material(in: VertexOutput) -> vec4<f32> {
+    let params = params(in);
+    let bbox = geoms[in.geom_index].bbox;
+    let r = length(in.position_org - (bbox.min + bbox.max) / 2.0);
+    return vec4<f32>(params.colour.rgb * (0.5 + 0.5 * cos(r * params.frequency)), params.colour.a);
+}
+";
+
+#[test]
+fn custom_materials() {
+    let mut harness = Harness::new();
+
+    // a shader without parameters, which is tinted like any other material
+    let shader = CustomShader::new::<()>(
+        "fn material(in: VertexOutput) -> vec4<f32> {
+            return vec4<f32>(in.position_org.x / 64.0, in.position_org.y / 64.0, 0.5, 1.0);
+        }",
+    )
+    .unwrap();
+    let material = Material::Custom(CustomMaterial::new(&shader, &()).unwrap());
+    let mut geom = square(0.0, 0.0, 64.0, material);
+    geom.instances = Some(vec![
+        Instance::new(Transformation::identity()).with_tint(Colour::new(1.0, 1.0, 0.5, 1.0))
+    ]);
+    let image = harness.render(&[geom]);
+    for (x, y) in [(0, 0), (10, 50), (63, 31)] {
+        let pixel = image.get_pixel(x, y);
+        let expected = [(x as f32 + 0.5) / 64.0, (y as f32 + 0.5) / 64.0, 0.25];
+        for c in 0..3 {
+            assert!(
+                (pixel[c] - expected[c]).abs() < 1e-3,
+                "({x}, {y}): {pixel:?} != {expected:?}"
+            );
+        }
+    }
+
+    // a shader that samples a texture needs one, and then renders like the texture material
+    let shader = CustomShader::new::<()>(
+        "fn material(in: VertexOutput) -> vec4<f32> {
+            let bbox = geoms[in.geom_index].bbox;
+            let coords = (in.position_org - bbox.min) / (bbox.max - bbox.min);
+            return textureSample(texture, texture_sampler, coords);
+        }",
+    )
+    .unwrap();
+    assert!(shader.uses_texture());
+    let material = CustomMaterial::new(&shader, &()).unwrap();
+    assert!(matches!(
+        harness.try_render(&[square(0.0, 0.0, 64.0, Material::Custom(material.clone()))]),
+        Err(RendererError::InvalidGeom { index: 0, source })
+            if matches!(*source, RendererError::MissingShaderTexture)
+    ));
+    let material =
+        material.with_texture(test_texture(), TextureRepeat::Clamp, TextureFilter::Nearest);
+    let textured = Material::Texture(TextureMaterial {
+        texture: test_texture(),
+        size_x: TextureSize::Original,
+        size_y: TextureSize::Original,
+        repeat_x: TextureRepeat::Clamp,
+        repeat_y: TextureRepeat::Clamp,
+        filter: TextureFilter::Nearest,
+    });
+    let expected = harness.render_fresh(&[square(0.0, 0.0, 64.0, textured)]);
+    let image = harness.render_fresh(&[square(0.0, 0.0, 64.0, Material::Custom(material))]);
+    assert_eq!(diff(&image, &expected, 0).0, 0);
+
+    // geoms with the same shader but different parameters are drawn together
+    let shader = CustomShader::new::<RingParams>(RING_SHADER).unwrap();
+    let ring = |x: f32, y: f32, colour: Colour, frequency: f32| {
+        let params = RingParams {
+            colour: [colour.r, colour.g, colour.b, colour.a],
+            frequency,
+            _padding: [0.0; 3],
+        };
+        Geom::new(
+            Primitive::Circle {
+                center: Point2D::new(x, y),
+                radius: 15.0,
+            },
+            Material::Custom(CustomMaterial::new(&shader, &params).unwrap()),
+            Some(pixel_space()),
+            vec![],
+            TessellationOptions::simple_fill(),
+        )
+    };
+    let geoms = [
+        ring(16.0, 16.0, Colour::RED, 1.0),
+        ring(48.0, 16.0, Colour::GREEN, 0.5),
+        ring(16.0, 48.0, Colour::BLUE, 2.0),
+        ring(48.0, 48.0, Colour::WHITE, 0.8),
+    ];
+    let image = harness.render_fresh(&geoms);
+    assert_golden("custom_materials", &image);
+
+    // the pipelines of a shader are removed once the shader and its materials are dropped
+    let is_custom =
+        |material_type: &MaterialType| matches!(material_type, MaterialType::Custom { .. });
+    let mut harness = Harness::new();
+    harness.render(&geoms);
+    assert!(harness.renderer.shaders.keys().any(is_custom));
+    drop((geoms, shader));
+    harness.render(&[]);
+    assert!(!harness.renderer.shaders.keys().any(is_custom));
+    assert!(!harness
+        .renderer
+        .pipelines
+        .keys()
+        .any(|key| is_custom(&key.material_type)));
+}
@@ -0,0 +1,489 @@
+// Golden-image regression tests for the renderer.
+//
+// Every case is rendered offscreen and compared against a reference PNG in `tests/golden`.
+// Set `SHEBANG_GOLDEN_BLESS=1` to (re-)generate the reference images and `SHEBANG_GOLDEN_TOLERANCE`
+// to change the maximum allowed per-channel difference (in 8-bit units). If a case fails, the
+// rendered image and a diff image are written to `target/golden`.
+//
+// Blessing records whatever the renderer currently draws, so a regenerated reference is only as
+// good as the person who checked it. Look at every new or changed PNG before committing it, and
+// make sure it shows what the test describes; a reference blessed from a broken renderer makes
+// the test pass forever.
+//
+// The tests are grouped by feature in the submodules, which share the harness and the fixtures
+// below.
+
+use std::path::PathBuf;
+
+use image::{Rgba, Rgba32FImage, RgbaImage};
+
+use super::custom::{CustomMaterial, CustomShader};
+use super::error::RendererError;
+use super::geometry::{
+    BlendMode, FillRule, Geom, Instance, LineCap, LineJoin, PixelFilter, Point2D, Primitive,
+    TessellationOptions, Tolerance, Transformation, Vector2,
+};
+use super::material::{
+    CheckerboardMaterial, Colour, ColourInterpolation, GaborMaterial, GradientExtent,
+    GradientMaterial, GradientRepeatMode, GradientType, GratingMaterial, HatchMaterial, Material,
+    MaterialType, NoiseMaterial, PlaidMaterial, RadialCheckerboardMaterial, RingSpacing,
+    SpatialFrequency, TextureFilter, TextureMaterial, TextureRepeat, TextureSize, Waveform,
+};
+use super::noise::{NoiseColour, NoiseGenerator, NoiseKind};
+use super::offscreen::headless_device;
+use super::path::Path;
+use super::texture::{Texture, TextureFormat};
+use super::{Batching, Renderer, TessellationKey, TARGET_FORMAT};
+
+mod filters;
+mod frames;
+mod materials;
+mod primitives;
+mod stimuli;
+
+/// Size of the rendered test images in pixels.
+const SIZE: u32 = 64;
+
+/// Default maximum per-channel difference between the rendered and the reference image.
+const DEFAULT_TOLERANCE: u8 = 2;
+
+/// A transformation that maps primitive coordinates to pixels, with the origin in the top-left
+/// corner of the target and the y-axis pointing down.
+fn pixel_space() -> Transformation {
+    #[rustfmt::skip]
+    let matrix = nalgebra::Matrix3::new(
+        -1.0, 0.0, 0.0,
+        0.0, 1.0, 0.0,
+        -1.0, 1.0, 1.0,
+    );
+    Transformation::from(matrix)
+}
+
+fn device() -> (wgpu::Device, wgpu::Queue) {
+    headless_device(false)
+        .or_else(|| headless_device(true))
+        .expect("No adapter available to run the golden-image tests.")
+}
+
+fn tolerance() -> u8 {
+    std::env::var("SHEBANG_GOLDEN_TOLERANCE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_TOLERANCE)
+}
+
+fn bless() -> bool {
+    std::env::var("SHEBANG_GOLDEN_BLESS").is_ok_and(|v| v != "0")
+}
+
+fn reference_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn output_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/golden")
+}
+
+fn render(device: &wgpu::Device, queue: &wgpu::Queue, geoms: &[Geom]) -> RgbaImage {
+    let mut renderer = Renderer::new(device);
+    renderer
+        .render_to_rgba8_image(device, queue, SIZE, SIZE, geoms, Colour::BLACK)
+        .unwrap()
+}
+
+/// A device with a renderer that is kept between frames, like in an experiment, so that the
+/// caches of the renderer are exercised as well.
+struct Harness {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    renderer: Renderer,
+}
+
+impl Harness {
+    fn new() -> Self {
+        let (device, queue) = device();
+        let renderer = Renderer::new(&device);
+        Self {
+            device,
+            queue,
+            renderer,
+        }
+    }
+
+    /// Renders the geoms on black with the kept renderer, and returns the values of the target.
+    fn try_render(&mut self, geoms: &[Geom]) -> Result<Rgba32FImage, RendererError> {
+        self.renderer
+            .render_to_image(&self.device, &self.queue, SIZE, SIZE, geoms, Colour::BLACK)
+    }
+
+    fn render(&mut self, geoms: &[Geom]) -> Rgba32FImage {
+        self.try_render(geoms).unwrap()
+    }
+
+    /// Renders the geoms with a new renderer, like the reference images.
+    fn render_fresh(&self, geoms: &[Geom]) -> RgbaImage {
+        render(&self.device, &self.queue, geoms)
+    }
+}
+
+/// The configuration of the target, for tests that prepare the frame themselves.
+fn surface_desc() -> wgpu::SurfaceConfiguration {
+    wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        format: TARGET_FORMAT,
+        width: SIZE,
+        height: SIZE,
+        present_mode: wgpu::PresentMode::Fifo,
+        desired_maximum_frame_latency: 1,
+        alpha_mode: wgpu::CompositeAlphaMode::Auto,
+        view_formats: vec![],
+    }
+}
+
+/// A filled square in pixel space with its top-left corner at `(x, y)`.
+fn square(x: f32, y: f32, size: f32, material: Material) -> Geom {
+    Geom::new(
+        Primitive::Rectangle {
+            a: Point2D::new(x, y),
+            b: Point2D::new(x + size, y + size),
+            rotation: 0.0,
+        },
+        material,
+        Some(pixel_space()),
+        vec![],
+        TessellationOptions::simple_fill(),
+    )
+}
+
+/// Compares two images and returns the number of pixels that differ by more than `tolerance` in
+/// any channel, together with an image highlighting those pixels in red.
+fn diff(actual: &RgbaImage, expected: &RgbaImage, tolerance: u8) -> (usize, RgbaImage) {
+    let mut mismatches = 0;
+    let diff = RgbaImage::from_fn(expected.width(), expected.height(), |x, y| {
+        let a = actual.get_pixel(x, y);
+        let e = expected.get_pixel(x, y);
+        let max_delta =
+            a.0.iter()
+                .zip(e.0)
+                .map(|(a, e)| a.abs_diff(e))
+                .max()
+                .unwrap();
+        if max_delta > tolerance {
+            mismatches += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            // dimmed luminance of the reference so the failure can be located
+            let l = ((e[0] as u32 + e[1] as u32 + e[2] as u32) / 12) as u8;
+            Rgba([l, l, l, 255])
+        }
+    });
+    (mismatches, diff)
+}
+
+/// Checks a rendered image against its reference. Returns a description of the failure, if any.
+fn check(name: &str, actual: &RgbaImage) -> Option<String> {
+    let reference = reference_dir().join(format!("{name}.png"));
+
+    if bless() {
+        std::fs::create_dir_all(reference_dir()).unwrap();
+        actual.save(&reference).unwrap();
+        return None;
+    }
+
+    let expected = match image::open(&reference) {
+        Ok(image) => image.into_rgba8(),
+        Err(e) => return Some(format!("{name}: cannot load {}: {e}", reference.display())),
+    };
+
+    let failure = if expected.dimensions() != actual.dimensions() {
+        format!(
+            "{name}: size {:?} does not match reference size {:?}",
+            actual.dimensions(),
+            expected.dimensions()
+        )
+    } else {
+        let (mismatches, diff_image) = diff(actual, &expected, tolerance());
+        if mismatches == 0 {
+            return None;
+        }
+        std::fs::create_dir_all(output_dir()).unwrap();
+        diff_image
+            .save(output_dir().join(format!("{name}.diff.png")))
+            .unwrap();
+        format!("{name}: {mismatches} pixels differ from the reference")
+    };
+
+    std::fs::create_dir_all(output_dir()).unwrap();
+    actual
+        .save(output_dir().join(format!("{name}.actual.png")))
+        .unwrap();
+
+    Some(failure)
+}
+
+/// Panics if a rendered image does not match its reference, see `check`.
+fn assert_golden(name: &str, actual: &RgbaImage) {
+    if let Some(failure) = check(name, actual) {
+        panic!("{failure}");
+    }
+}
+
+/// A small checkerboard texture with distinct colours in each quadrant.
+fn test_texture() -> Texture {
+    let image = RgbaImage::from_fn(8, 8, |x, y| match (x < 4, y < 4) {
+        (true, true) => Rgba([255, 0, 0, 255]),
+        (false, true) => Rgba([0, 255, 0, 255]),
+        (true, false) => Rgba([0, 0, 255, 255]),
+        (false, false) => Rgba([255, 255, 255, 255]),
+    });
+    Texture::from_image(image.into(), TextureFormat::Rgba8U)
+}
+
+fn primitives() -> Vec<(&'static str, Primitive)> {
+    vec![
+        (
+            "rectangle",
+            Primitive::Rectangle {
+                a: Point2D::new(12.0, 16.0),
+                b: Point2D::new(52.0, 48.0),
+                rotation: 0.0,
+            },
+        ),
+        (
+            "circle",
+            Primitive::Circle {
+                center: Point2D::new(32.0, 32.0),
+                radius: 20.0,
+            },
+        ),
+        (
+            "ellipse",
+            Primitive::Ellipse {
+                center: Point2D::new(32.0, 32.0),
+                radii: Vector2::new(24.0, 12.0),
+                rotation: 0.0,
+            },
+        ),
+        (
+            "rotated_rectangle",
+            Primitive::Rectangle {
+                a: Point2D::new(12.0, 24.0),
+                b: Point2D::new(52.0, 40.0),
+                rotation: 30.0,
+            },
+        ),
+        (
+            "rotated_rounded_rectangle",
+            Primitive::RoundedRectangle {
+                a: Point2D::new(12.0, 22.0),
+                b: Point2D::new(52.0, 42.0),
+                radius: 6.0,
+                rotation: -45.0,
+            },
+        ),
+        (
+            "rotated_ellipse",
+            Primitive::Ellipse {
+                center: Point2D::new(32.0, 32.0),
+                radii: Vector2::new(26.0, 10.0),
+                rotation: 60.0,
+            },
+        ),
+        (
+            "line",
+            Primitive::Line {
+                a: Point2D::new(10.0, 12.0),
+                b: Point2D::new(54.0, 50.0),
+            },
+        ),
+        (
+            "rounded_rectangle",
+            Primitive::RoundedRectangle {
+                a: Point2D::new(10.0, 14.0),
+                b: Point2D::new(54.0, 50.0),
+                radius: 8.0,
+                rotation: 0.0,
+            },
+        ),
+        (
+            "triangle",
+            Primitive::Triangle {
+                a: Point2D::new(32.0, 8.0),
+                b: Point2D::new(56.0, 54.0),
+                c: Point2D::new(8.0, 54.0),
+            },
+        ),
+        (
+            "polygon",
+            Primitive::Polygon {
+                points: vec![
+                    Point2D::new(32.0, 6.0),
+                    Point2D::new(38.0, 26.0),
+                    Point2D::new(58.0, 26.0),
+                    Point2D::new(42.0, 38.0),
+                    Point2D::new(48.0, 58.0),
+                    Point2D::new(32.0, 46.0),
+                    Point2D::new(16.0, 58.0),
+                    Point2D::new(22.0, 38.0),
+                    Point2D::new(6.0, 26.0),
+                    Point2D::new(26.0, 26.0),
+                ],
+            },
+        ),
+        (
+            "path",
+            Primitive::Path {
+                path: Path::polyline(
+                    &[
+                        Point2D::new(8.0, 52.0),
+                        Point2D::new(20.0, 12.0),
+                        Point2D::new(32.0, 44.0),
+                        Point2D::new(44.0, 12.0),
+                        Point2D::new(56.0, 52.0),
+                    ],
+                    false,
+                ),
+            },
+        ),
+        (
+            "curved_path",
+            Primitive::Path {
+                path: curved_path(),
+            },
+        ),
+        (
+            "arc",
+            Primitive::Arc {
+                center: Point2D::new(32.0, 32.0),
+                radius: 22.0,
+                start_angle: -30.0,
+                end_angle: 210.0,
+            },
+        ),
+        (
+            "sector",
+            Primitive::Sector {
+                center: Point2D::new(32.0, 32.0),
+                radius: 24.0,
+                start_angle: 30.0,
+                end_angle: 330.0,
+            },
+        ),
+        (
+            "annulus",
+            Primitive::Annulus {
+                center: Point2D::new(32.0, 32.0),
+                inner_radius: 12.0,
+                outer_radius: 24.0,
+                start_angle: 0.0,
+                end_angle: 360.0,
+            },
+        ),
+        (
+            "annulus_segment",
+            Primitive::Annulus {
+                center: Point2D::new(32.0, 32.0),
+                inner_radius: 12.0,
+                outer_radius: 24.0,
+                start_angle: -45.0,
+                end_angle: 90.0,
+            },
+        ),
+        (
+            "compound",
+            Primitive::Compound {
+                contours: vec![
+                    Primitive::Rectangle {
+                        a: Point2D::new(10.0, 10.0),
+                        b: Point2D::new(54.0, 54.0),
+                        rotation: 0.0,
+                    },
+                    Primitive::Rectangle {
+                        a: Point2D::new(20.0, 20.0),
+                        b: Point2D::new(44.0, 44.0),
+                        rotation: 0.0,
+                    },
+                    Primitive::Circle {
+                        center: Point2D::new(32.0, 32.0),
+                        radius: 6.0,
+                    },
+                ],
+            },
+        ),
+    ]
+}
+
+/// A path with two sub-paths using every kind of segment.
+fn curved_path() -> Path {
+    let mut path = Path::new();
+    path.move_to(Point2D::new(8.0, 32.0));
+    path.quadratic_bezier_to(Point2D::new(20.0, 4.0), Point2D::new(32.0, 20.0));
+    path.cubic_bezier_to(
+        Point2D::new(40.0, 4.0),
+        Point2D::new(60.0, 16.0),
+        Point2D::new(56.0, 32.0),
+    );
+    path.arc_to(
+        Vector2::new(24.0, 24.0),
+        0.0,
+        false,
+        true,
+        Point2D::new(8.0, 32.0),
+    );
+    path.close();
+    path.move_to(Point2D::new(24.0, 40.0));
+    path.line_to(Point2D::new(40.0, 40.0));
+    path.line_to(Point2D::new(32.0, 52.0));
+    path.close();
+    path
+}
+
+fn materials() -> Vec<(&'static str, Material)> {
+    vec![
+        ("colour", Material::Colour(Colour::new(0.2, 0.6, 1.0, 1.0))),
+        (
+            "texture",
+            Material::Texture(TextureMaterial {
+                texture: test_texture(),
+                size_x: TextureSize::Original,
+                size_y: TextureSize::Original,
+                repeat_x: TextureRepeat::Clamp,
+                repeat_y: TextureRepeat::Clamp,
+                filter: TextureFilter::Nearest,
+            }),
+        ),
+    ]
+}
+
+fn options() -> Vec<(&'static str, TessellationOptions)> {
+    vec![
+        ("fill", TessellationOptions::simple_fill()),
+        ("stroke", TessellationOptions::simple_line(3.0)),
+        (
+            "stroke_round",
+            TessellationOptions::Stroke {
+                start_cap: LineCap::Round,
+                end_cap: LineCap::Round,
+                line_join: LineJoin::Round,
+                line_width: 5.0,
+                miter_limit: 4.0,
+                dash_pattern: vec![],
+                dash_offset: 0.0,
+                tolerance: Tolerance::Auto,
+            },
+        ),
+        (
+            "stroke_miter_clip",
+            TessellationOptions::Stroke {
+                start_cap: LineCap::Square,
+                end_cap: LineCap::Square,
+                line_join: LineJoin::MiterClip,
+                line_width: 4.0,
+                miter_limit: 1.5,
+                dash_pattern: vec![],
+                dash_offset: 0.0,
+                tolerance: Tolerance::Auto,
+            },
+        ),
+    ]
+}
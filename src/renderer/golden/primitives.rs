@@ -0,0 +1,261 @@
+// Golden tests of the primitives and their tessellation: fills, strokes, dashes, fill rules
+// and tolerances.
+
+use super::*;
+
+#[test]
+fn primitive_material_options_matrix() {
+    let (device, queue) = device();
+
+    let mut failures = vec![];
+
+    for (primitive_name, primitive) in primitives() {
+        for (material_name, material) in materials() {
+            for (options_name, options) in options() {
+                let name = format!("{primitive_name}_{material_name}_{options_name}");
+                let fill = matches!(options, TessellationOptions::Fill { .. });
+                let geom = Geom::new(
+                    primitive.clone(),
+                    material.clone(),
+                    Some(pixel_space()),
+                    vec![],
+                    options,
+                );
+
+                // lines have no interior, so they cannot be filled
+                if fill && matches!(primitive, Primitive::Line { .. }) {
+                    let result = Renderer::new(&device).render_to_rgba8_image(
+                        &device,
+                        &queue,
+                        SIZE,
+                        SIZE,
+                        &[geom],
+                        Colour::BLACK,
+                    );
+                    assert!(matches!(
+                        result,
+                        Err(RendererError::InvalidGeom { index: 0, source })
+                            if matches!(*source, RendererError::UnsupportedTessellation { .. })
+                    ));
+                    continue;
+                }
+
+                let actual = render(&device, &queue, &[geom]);
+                failures.extend(check(&name, &actual));
+            }
+        }
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+fn dash_options() -> Vec<(&'static str, TessellationOptions)> {
+    vec![
+        (
+            "dashed",
+            TessellationOptions::dashed_line(3.0, vec![6.0, 3.0]),
+        ),
+        (
+            "dotted",
+            TessellationOptions::Stroke {
+                start_cap: LineCap::Round,
+                end_cap: LineCap::Round,
+                line_join: LineJoin::Round,
+                line_width: 4.0,
+                miter_limit: 4.0,
+                dash_pattern: vec![0.0, 7.0],
+                dash_offset: 2.0,
+                tolerance: Tolerance::Auto,
+            },
+        ),
+    ]
+}
+
+#[test]
+fn dashed_strokes() {
+    let (device, queue) = device();
+
+    let mut failures = vec![];
+
+    for (primitive_name, primitive) in primitives() {
+        for (options_name, options) in dash_options() {
+            let name = format!("{primitive_name}_colour_{options_name}");
+            let geom = Geom::new(
+                primitive.clone(),
+                Material::Colour(Colour::WHITE),
+                Some(pixel_space()),
+                vec![],
+                options,
+            );
+
+            let actual = render(&device, &queue, &[geom]);
+            failures.extend(check(&name, &actual));
+        }
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+fn fill_rule_shapes() -> Vec<(&'static str, Primitive)> {
+    vec![
+        (
+            "pentagram",
+            Primitive::Polygon {
+                points: vec![
+                    Point2D::new(32.0, 6.0),
+                    Point2D::new(47.0, 54.0),
+                    Point2D::new(7.0, 24.0),
+                    Point2D::new(57.0, 24.0),
+                    Point2D::new(17.0, 54.0),
+                ],
+            },
+        ),
+        (
+            "donut",
+            Primitive::Compound {
+                contours: vec![
+                    Primitive::Circle {
+                        center: Point2D::new(32.0, 32.0),
+                        radius: 24.0,
+                    },
+                    Primitive::Circle {
+                        center: Point2D::new(32.0, 32.0),
+                        radius: 12.0,
+                    },
+                ],
+            },
+        ),
+        (
+            // the counter winds in the opposite direction, so it is a hole with both rules
+            "letter",
+            Primitive::Path {
+                path: Path::from_svg("M 16 8 h 20 a 12 12 0 0 1 0 24 h -10 v 24 h -10 z m 10 8 v 8 h 10 a 4 4 0 0 0 0 -8 z")
+                    .expect("valid SVG path data"),
+            },
+        ),
+    ]
+}
+
+#[test]
+fn fill_rules() {
+    let (device, queue) = device();
+
+    let mut failures = vec![];
+
+    for (shape_name, primitive) in fill_rule_shapes() {
+        for (rule_name, fill_rule) in [
+            ("even_odd", FillRule::EvenOdd),
+            ("non_zero", FillRule::NonZero),
+        ] {
+            let name = format!("{shape_name}_{rule_name}");
+            let geom = Geom::new(
+                primitive.clone(),
+                Material::Colour(Colour::WHITE),
+                Some(pixel_space()),
+                vec![],
+                TessellationOptions::Fill {
+                    fill_rule,
+                    tolerance: Tolerance::Auto,
+                },
+            );
+
+            let actual = render(&device, &queue, &[geom]);
+            failures.extend(check(&name, &actual));
+        }
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn auto_tolerance_follows_transform() {
+    let (device, queue) = device();
+
+    // pixel space, scaled up by a factor of 10
+    #[rustfmt::skip]
+    let scaled = Transformation::from(nalgebra::Matrix3::new(
+        -10.0, 0.0, 0.0,
+        0.0, 10.0, 0.0,
+        -1.0, 1.0, 1.0,
+    ));
+
+    assert_eq!(pixel_space().pixel_scale(SIZE, SIZE), 1.0);
+    assert_eq!(scaled.pixel_scale(SIZE, SIZE), 10.0);
+    assert_eq!(scaled.pixel_scale(2 * SIZE, SIZE), 10.0);
+
+    let render_circle = |center: Point2D, radius: f32, transform: Transformation| {
+        let geom = Geom::new(
+            Primitive::Circle { center, radius },
+            Material::Colour(Colour::WHITE),
+            Some(transform),
+            vec![],
+            TessellationOptions::simple_fill(),
+        );
+        render(&device, &queue, &[geom])
+    };
+
+    // a small circle that is scaled up must be as smooth as a large one
+    let large = render_circle(Point2D::new(32.0, 32.0), 24.0, pixel_space());
+    let small = render_circle(Point2D::new(3.2, 3.2), 2.4, scaled);
+
+    let (mismatches, _) = diff(&small, &large, tolerance());
+    assert_eq!(mismatches, 0);
+}
+
+#[test]
+fn rectangle_lights_exact_pixels() {
+    let (device, queue) = device();
+
+    let geom = square(10.0, 10.0, 10.0, Material::Colour(Colour::WHITE));
+    let actual = render(&device, &queue, &[geom]);
+
+    for (x, y, pixel) in actual.enumerate_pixels() {
+        let inside = (10..20).contains(&x) && (10..20).contains(&y);
+        let expected = if inside {
+            Rgba([255, 255, 255, 255])
+        } else {
+            Rgba([0, 0, 0, 255])
+        };
+        assert_eq!(*pixel, expected, "unexpected value at pixel ({x}, {y})");
+    }
+}
+
+#[test]
+fn svg_path_matches_built_path() {
+    let (device, queue) = device();
+
+    let svg =
+        Path::from_svg("M 8 32 Q 20 4 32 20 C 40 4 60 16 56 32 L 8 32 Z m 16 8 h 16 l -8 12 z")
+            .expect("valid SVG path data");
+
+    let mut built = Path::new();
+    built.move_to(Point2D::new(8.0, 32.0));
+    built.quadratic_bezier_to(Point2D::new(20.0, 4.0), Point2D::new(32.0, 20.0));
+    built.cubic_bezier_to(
+        Point2D::new(40.0, 4.0),
+        Point2D::new(60.0, 16.0),
+        Point2D::new(56.0, 32.0),
+    );
+    built.line_to(Point2D::new(8.0, 32.0));
+    built.close();
+    built.move_to(Point2D::new(24.0, 40.0));
+    built.line_to(Point2D::new(40.0, 40.0));
+    built.line_to(Point2D::new(32.0, 52.0));
+    built.close();
+
+    assert_eq!(svg, built);
+
+    let render_path = |path: Path| {
+        let geom = Geom::new(
+            Primitive::Path { path },
+            Material::Colour(Colour::WHITE),
+            Some(pixel_space()),
+            vec![],
+            TessellationOptions::simple_fill(),
+        );
+        render(&device, &queue, &[geom])
+    };
+
+    assert_eq!(render_path(svg), render_path(built));
+    assert!(Path::from_svg("M 0 0 L 10 x").is_err());
+}
@@ -0,0 +1,479 @@
+// Golden tests of the stimulus materials: gratings, Gabor patches, noise and patterns.
+
+use super::*;
+
+#[test]
+fn gratings() {
+    let mut harness = Harness::new();
+
+    let grating = |frequency, phase, orientation, waveform| {
+        Material::Grating(GratingMaterial {
+            frequency,
+            phase,
+            orientation,
+            contrast: 0.8,
+            mean: Colour::new(0.5, 0.25, 0.5, 1.0),
+            waveform,
+        })
+    };
+    let mut render_square = |material: Material, transform: Transformation| {
+        let mut geom = square(16.0, 16.0, 32.0, material);
+        geom.transform = Some(transform);
+        harness.render(&[geom])
+    };
+
+    // the waveforms, relative to the centre of the square
+    let sine = |t: f32| (2.0 * std::f32::consts::PI * t).sin();
+    let waveforms: [(Waveform, &dyn Fn(f32) -> f32); 4] = [
+        (Waveform::Sine, &sine),
+        (Waveform::Square, &|t: f32| sine(t).signum()),
+        (Waveform::Sawtooth, &|t: f32| {
+            2.0 * (t + 0.5).rem_euclid(1.0) - 1.0
+        }),
+        (Waveform::Triangle, &|t: f32| {
+            4.0 * ((t - 0.25).rem_euclid(1.0) - 0.5).abs() - 1.0
+        }),
+    ];
+    let frequency = 1.0 / 16.0;
+    for (waveform, f) in waveforms {
+        for (phase, orientation) in [(0.0, 0.0), (90.0, 0.0), (0.0, 90.0), (-45.0, 180.0)] {
+            let image = render_square(
+                grating(
+                    SpatialFrequency::CyclesPerPixel(frequency),
+                    phase,
+                    orientation,
+                    waveform,
+                ),
+                pixel_space(),
+            );
+            for i in 16..48 {
+                // the pixel centre relative to the centre of the square along the orientation
+                let x = (i as f32 + 0.5 - 32.0) * if orientation == 180.0 { -1.0 } else { 1.0 };
+                let pixel = if orientation == 90.0 {
+                    image.get_pixel(20, i)
+                } else {
+                    image.get_pixel(i, 20)
+                };
+                let value = f(x * frequency + phase / 360.0);
+                assert!(
+                    (pixel[0] - 0.5 * (1.0 + 0.8 * value)).abs() < 2e-3
+                        && (pixel[1] - 0.25 * (1.0 + 0.8 * value)).abs() < 2e-3,
+                    "{waveform:?}, phase {phase}, orientation {orientation}, pixel {i}: {pixel:?}"
+                );
+            }
+        }
+    }
+
+    // cycles per pixel do not depend on the transform, cycles per unit do
+    let scaled = || {
+        #[rustfmt::skip]
+        let matrix = nalgebra::Matrix3::new(
+            -0.5, 0.0, 0.0,
+            0.0, 0.5, 0.0,
+            -1.0, 1.0, 1.0,
+        );
+        Transformation::from(matrix)
+    };
+    for (frequency, period) in [
+        (SpatialFrequency::CyclesPerPixel(1.0 / 8.0), 8),
+        (SpatialFrequency::CyclesPerUnit(1.0 / 8.0), 4),
+    ] {
+        let image = render_square(grating(frequency, 0.0, 0.0, Waveform::Sine), scaled());
+        for x in 8..16 {
+            let (a, b) = (
+                image.get_pixel(x, 10)[0],
+                image.get_pixel(x + period, 10)[0],
+            );
+            assert!((a - b).abs() < 2e-3, "{frequency:?}: {a} != {b}");
+        }
+        let (a, b) = (
+            image.get_pixel(8, 10)[0],
+            image.get_pixel(8 + period / 2, 10)[0],
+        );
+        assert!((a - b).abs() > 0.1, "{frequency:?}: {a} == {b}");
+    }
+
+    let geoms = [
+        square(
+            16.0,
+            16.0,
+            32.0,
+            grating(
+                SpatialFrequency::CyclesPerPixel(0.1),
+                30.0,
+                30.0,
+                Waveform::Sine,
+            ),
+        ),
+        Geom::new(
+            Primitive::Circle {
+                center: Point2D::new(32.0, 32.0),
+                radius: 12.0,
+            },
+            grating(
+                SpatialFrequency::CyclesPerUnit(0.2),
+                0.0,
+                -60.0,
+                Waveform::Square,
+            ),
+            Some(pixel_space()),
+            vec![],
+            TessellationOptions::simple_fill(),
+        ),
+    ];
+    let image = harness.render_fresh(&geoms);
+    assert_golden("gratings", &image);
+}
+
+#[test]
+fn gabor_patches() {
+    let mut harness = Harness::new();
+
+    let gabor = |phase: f32, sigma: Vector2, envelope_orientation: f32, normalise: bool| {
+        Material::Gabor(GaborMaterial {
+            carrier: GratingMaterial {
+                frequency: SpatialFrequency::CyclesPerUnit(1.0 / 16.0),
+                phase,
+                orientation: 0.0,
+                contrast: 0.8,
+                mean: Colour::new(0.5, 0.5, 0.5, 1.0),
+                waveform: Waveform::Sine,
+            },
+            sigma,
+            envelope_orientation,
+            normalise,
+        })
+    };
+    let mut render_square =
+        |material: Material| harness.render(&[square(0.0, 0.0, 64.0, material)]);
+    let expected = |phase: f32, sigma: Vector2, x: f32, y: f32| {
+        let envelope = (-0.5 * ((x / sigma.x).powi(2) + (y / sigma.y).powi(2))).exp();
+        let carrier = (2.0 * std::f32::consts::PI * x / 16.0 + phase.to_radians()).sin();
+        0.5 * (1.0 + 0.8 * envelope * carrier)
+    };
+
+    // the envelope is centred on the bounding box, and the envelope can be rotated independently
+    // of the carrier
+    for (sigma, envelope_orientation) in [
+        (Vector2::new(8.0, 8.0), 0.0),
+        (Vector2::new(4.0, 12.0), 0.0),
+        (Vector2::new(12.0, 4.0), 90.0),
+    ] {
+        let image = render_square(gabor(90.0, sigma, envelope_orientation, false));
+        let sigma = if envelope_orientation == 90.0 {
+            Vector2::new(sigma.y, sigma.x)
+        } else {
+            sigma
+        };
+        for (x, y) in [(32, 32), (36, 32), (40, 32), (44, 30), (32, 40), (20, 26)] {
+            let value = image.get_pixel(x, y)[0];
+            let expected = expected(90.0, sigma, x as f32 - 31.5, y as f32 - 31.5);
+            assert!(
+                (value - expected).abs() < 2e-3,
+                "{sigma:?} at ({x}, {y}): {value} != {expected}"
+            );
+        }
+        // far from the centre, the patch has the mean colour
+        assert!((image.get_pixel(1, 1)[0] - 0.5).abs() < 2e-3);
+    }
+
+    // with normalisation, the peak amplitude is 1 independent of the phase
+    let sigma = Vector2::new(4.0, 4.0);
+    let amplitude = |x: f32, y: f32| (expected(0.0, sigma, x, y) / 0.5 - 1.0) / 0.8;
+    let peak = (-800..800)
+        .map(|i| amplitude(i as f32 / 100.0, 0.0).abs())
+        .fold(0.0, f32::max);
+    assert!(peak < 0.7);
+    for normalise in [false, true] {
+        let image = render_square(gabor(0.0, sigma, 0.0, normalise));
+        let scale = if normalise { 1.0 / peak } else { 1.0 };
+        for x in 24..40 {
+            let value = image.get_pixel(x, 31)[0];
+            let expected = 0.5 * (1.0 + 0.8 * scale * amplitude(x as f32 - 31.5, -0.5));
+            assert!(
+                (value - expected).abs() < 2e-3,
+                "normalise = {normalise}, x = {x}: {value} != {expected}"
+            );
+        }
+    }
+
+    // Gabor patches on a rectangle and on a circle
+    let patch = |primitive: Primitive, orientation: f32, frequency: SpatialFrequency| {
+        Geom::new(
+            primitive,
+            Material::Gabor(GaborMaterial {
+                carrier: GratingMaterial {
+                    frequency,
+                    phase: 45.0,
+                    orientation,
+                    contrast: 1.0,
+                    mean: Colour::new(0.4, 0.5, 0.6, 1.0),
+                    waveform: Waveform::Sine,
+                },
+                sigma: Vector2::new(8.0, 4.0),
+                envelope_orientation: 30.0,
+                normalise: true,
+            }),
+            Some(pixel_space()),
+            vec![],
+            TessellationOptions::simple_fill(),
+        )
+    };
+    let geoms = [
+        patch(
+            Primitive::Rectangle {
+                a: Point2D::new(0.0, 0.0),
+                b: Point2D::new(32.0, 64.0),
+                rotation: 0.0,
+            },
+            45.0,
+            SpatialFrequency::CyclesPerPixel(0.125),
+        ),
+        patch(
+            Primitive::Circle {
+                center: Point2D::new(48.0, 32.0),
+                radius: 15.0,
+            },
+            -30.0,
+            SpatialFrequency::CyclesPerUnit(0.1),
+        ),
+    ];
+    let image = harness.render_fresh(&geoms);
+    assert_golden("gabor_patches", &image);
+}
+
+#[test]
+fn noise() {
+    let mut harness = Harness::new();
+
+    let mut render_noise = |generator: &NoiseGenerator, grain_size: f32| {
+        let material =
+            NoiseMaterial::new(generator, grain_size, Colour::new(0.5, 0.5, 0.5, 1.0), 0.25);
+        harness.render(&[square(0.0, 0.0, 64.0, Material::Noise(material))])
+    };
+
+    // the same seed always results in the same pixels, a different seed does not
+    let binary = NoiseGenerator::new(NoiseKind::Binary, 16, 42);
+    let image = render_noise(&binary, 4.0);
+    assert_eq!(image, render_noise(&binary, 4.0));
+    assert_ne!(
+        image,
+        render_noise(&NoiseGenerator::new(NoiseKind::Binary, 16, 43), 4.0)
+    );
+
+    // every grain is 4 by 4 pixels and has one of the two values, and the noise repeats after 16
+    // grains
+    let noise = binary.image();
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            let value = image.get_pixel(x, y)[0];
+            let expected = 0.5 * (1.0 + 0.25 * noise.get_pixel((x / 4) % 16, (y / 4) % 16)[0]);
+            assert!(
+                (value - expected).abs() < 1e-3,
+                "({x}, {y}): {value} != {expected}"
+            );
+        }
+    }
+
+    // four kinds of noise side by side
+    let noise_square = |x: f32, y: f32, generator: NoiseGenerator, grain_size: f32| {
+        let material =
+            NoiseMaterial::new(&generator, grain_size, Colour::new(0.4, 0.5, 0.6, 1.0), 0.3);
+        square(x, y, 32.0, Material::Noise(material))
+    };
+    let coloured = NoiseGenerator {
+        colour: NoiseColour::Colour,
+        ..NoiseGenerator::new(NoiseKind::Gaussian, 32, 3)
+    };
+    let geoms = [
+        noise_square(0.0, 0.0, coloured, 2.0),
+        noise_square(32.0, 0.0, NoiseGenerator::new(NoiseKind::Binary, 8, 4), 4.0),
+        noise_square(
+            0.0,
+            32.0,
+            NoiseGenerator::new(NoiseKind::Spectral { alpha: 1.0 }, 32, 5),
+            1.0,
+        ),
+        noise_square(
+            32.0,
+            32.0,
+            NoiseGenerator::new(NoiseKind::Perlin { cell_size: 8 }, 32, 6),
+            1.0,
+        ),
+    ];
+    let image = harness.render_fresh(&geoms);
+    assert_golden("noise", &image);
+}
+
+#[test]
+fn patterns() {
+    let mut harness = Harness::new();
+
+    let mut render_square =
+        |material: Material| harness.render(&[square(0.0, 0.0, 64.0, material)]);
+    let (dark, light) = (Colour::DARKGREY, Colour::LIGHTGREY);
+    // the position of the centre of a pixel relative to the centre of the square
+    let position = |x: u32, y: u32| (x as f32 - 31.5, y as f32 - 31.5);
+    let check_pixel = |image: &image::Rgba32FImage, x: u32, y: u32, expected: f32| {
+        let value = image.get_pixel(x, y)[0];
+        assert!(
+            (value - expected).abs() < 2e-3,
+            "({x}, {y}): {value} != {expected}"
+        );
+    };
+
+    // four checks meet at the centre, and a phase of 180 degrees swaps the colours
+    for phase in [0.0, 180.0] {
+        let image = render_square(Material::Checkerboard(CheckerboardMaterial {
+            check_size: Vector2::new(8.0, 4.0),
+            phase: Vector2::new(phase, 0.0),
+            rotation: 0.0,
+            colours: [dark, light],
+        }));
+        for (x, y) in image.enumerate_pixels().map(|(x, y, _)| (x, y)) {
+            let (px, py) = position(x, y);
+            let parity = ((px / 8.0 + phase / 180.0).floor() + (py / 4.0).floor()) as i32 & 1;
+            check_pixel(&image, x, y, if parity == 0 { 0.2 } else { 0.8 });
+        }
+    }
+
+    // a dartboard with linearly and logarithmically spaced rings, which is only drawn between the
+    // inner and the outer radius
+    for ring_spacing in [RingSpacing::Linear, RingSpacing::Logarithmic] {
+        let image = render_square(Material::RadialCheckerboard(RadialCheckerboardMaterial {
+            inner_radius: 4.0,
+            outer_radius: 30.0,
+            rings: 4,
+            wedges: 8,
+            ring_spacing,
+            ring_phase: 0.0,
+            wedge_phase: 90.0,
+            colours: [dark, light],
+        }));
+        for (x, y) in image.enumerate_pixels().map(|(x, y, _)| (x, y)) {
+            let (px, py) = position(x, y);
+            let r = (px * px + py * py).sqrt();
+            let ring = match ring_spacing {
+                RingSpacing::Linear => (r - 4.0) / 26.0 * 4.0,
+                RingSpacing::Logarithmic => (r / 4.0).ln() / (30.0f32 / 4.0).ln() * 4.0,
+            };
+            let angle = py.atan2(px).rem_euclid(std::f32::consts::TAU);
+            let wedge = angle / std::f32::consts::TAU * 8.0 + 0.5;
+            // pixels close to the edges of the checks are not compared
+            let near = |t: f32| (t - t.round()).abs() < 0.05;
+            if near(ring) || near(wedge) || (r - 4.0).abs() < 0.1 || (r - 30.0).abs() < 0.1 {
+                continue;
+            }
+            let expected = if !(4.0..=30.0).contains(&r) {
+                0.0
+            } else if (ring.floor() + wedge.floor()) as i32 & 1 == 0 {
+                0.2
+            } else {
+                0.8
+            };
+            check_pixel(&image, x, y, expected);
+        }
+    }
+
+    // a plaid is the sum of its gratings around their mean
+    let grating = |frequency: f32, orientation: f32, contrast: f32| GratingMaterial {
+        frequency: SpatialFrequency::CyclesPerUnit(frequency),
+        phase: 0.0,
+        orientation,
+        contrast,
+        mean: Colour::new(0.5, 0.5, 0.5, 1.0),
+        waveform: Waveform::Sine,
+    };
+    let image = render_square(Material::Plaid(PlaidMaterial {
+        components: [grating(1.0 / 16.0, 0.0, 0.3), grating(1.0 / 8.0, 90.0, 0.2)],
+    }));
+    for (x, y) in [(32, 32), (36, 30), (40, 44), (13, 57), (60, 3)] {
+        let (px, py) = position(x, y);
+        let wave = |t: f32| (2.0 * std::f32::consts::PI * t).sin();
+        check_pixel(
+            &image,
+            x,
+            y,
+            0.5 * (1.0 + 0.3 * wave(px / 16.0) + 0.2 * wave(py / 8.0)),
+        );
+    }
+
+    // horizontal lines, one of which passes through the centre
+    let image = render_square(Material::Hatch(HatchMaterial {
+        spacing: 8.0,
+        width: 2.0,
+        angle: 0.0,
+        crossed: false,
+        colour: light,
+        background: dark,
+    }));
+    for (x, y) in image.enumerate_pixels().map(|(x, y, _)| (x, y)) {
+        let on_line = (y % 8 == 7) || (y % 8 == 0);
+        check_pixel(&image, x, y, if on_line { 0.8 } else { 0.2 });
+    }
+
+    // all patterns side by side, rotated where possible
+    let red = Colour::new(0.9, 0.1, 0.1, 1.0);
+    let blue = Colour::new(0.1, 0.2, 0.9, 1.0);
+    let geoms = [
+        square(
+            0.0,
+            0.0,
+            32.0,
+            Material::Checkerboard(CheckerboardMaterial {
+                check_size: Vector2::new(6.0, 6.0),
+                phase: Vector2::new(90.0, 0.0),
+                rotation: 30.0,
+                colours: [red, blue],
+            }),
+        ),
+        square(
+            32.0,
+            0.0,
+            32.0,
+            Material::RadialCheckerboard(RadialCheckerboardMaterial {
+                inner_radius: 2.0,
+                outer_radius: 15.0,
+                rings: 5,
+                wedges: 12,
+                ring_spacing: RingSpacing::Logarithmic,
+                ring_phase: 0.0,
+                wedge_phase: 0.0,
+                colours: [dark, light],
+            }),
+        ),
+        square(
+            0.0,
+            32.0,
+            32.0,
+            Material::Plaid(PlaidMaterial {
+                components: [
+                    GratingMaterial {
+                        mean: Colour::new(0.5, 0.3, 0.3, 1.0),
+                        ..grating(0.1, 45.0, 0.5)
+                    },
+                    GratingMaterial {
+                        mean: Colour::new(0.3, 0.3, 0.5, 1.0),
+                        waveform: Waveform::Square,
+                        ..grating(0.15, -45.0, 0.5)
+                    },
+                ],
+            }),
+        ),
+        square(
+            32.0,
+            32.0,
+            32.0,
+            Material::Hatch(HatchMaterial {
+                spacing: 6.0,
+                width: 1.5,
+                angle: 45.0,
+                crossed: true,
+                colour: Colour::WHITE,
+                background: blue,
+            }),
+        ),
+    ];
+    let image = harness.render_fresh(&geoms);
+    assert_golden("patterns", &image);
+}
@@ -49,6 +49,12 @@ impl PartialEq for CacheEntry {
     }
 }
 
+impl Default for CacheEntry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CacheEntry {
     pub fn new() -> Self {
         // create random u128
@@ -85,6 +91,12 @@ pub struct Cache<T> {
     data: HashMap<CacheEntry, (T, u64)>,
 }
 
+impl<T> Default for Cache<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T> Cache<T> {
     pub fn new() -> Self {
         Self {
@@ -1,4 +1,11 @@
-use super::{geometry::Point2D, texture::Texture, uniform_structs};
+use super::{
+    custom::CustomMaterial,
+    geometry::{Point2D, Vector2},
+    noise::NoiseGenerator,
+    pipeline::SamplerKey,
+    texture::{Texture, TextureFormat},
+    uniform_structs,
+};
 use std::hash::{Hash, Hasher};
 
 /// An RGBA colour in the current colour space.
@@ -68,16 +75,16 @@ pub enum GradientExtent {
     /// Relative extent. Interpretation depends on the gradient type. Use in combination with
     /// `GradientRepeatMode' to control the repeat behaviour.
     /// - For linear gradients, this is the total length of the gradient as a fraction of the shape's
-    /// bounding box.
+    ///   bounding box.
     /// - For radial gradients, this is the total radius of the gradient as a fraction of the shape's
-    /// bounding box.
+    ///   bounding box.
     /// - For conic gradients, this is the total angle as a fraction of full circle.
     Relative(f32),
     /// Choose the extent that fills the available space. Interpretation depends on the gradient type.
-    /// - For linear gradients, this will stretch the gradient to fill the shape (or the shape's bounding box).
-    /// - For radial gradients, this will stretch the gradient to fill the shape (or the shape's bounding box).
-    /// for a conic gradient, this is
-    /// identical to `Exact(360.0)`).
+    /// - For linear gradients, this will stretch the gradient to fill the shape's bounding box.
+    /// - For radial gradients, this will stretch the gradient to the corner of the shape's bounding
+    ///   box that is farthest from the centre of the gradient.
+    /// - For conic gradients, this is identical to `Absolute(360.0)`.
     Fill,
 }
 
@@ -91,6 +98,32 @@ pub enum GradientRepeatMode {
     Repeat,
 }
 
+/// The colour space in which the colours between the stops of a gradient are interpolated. The
+/// colours of the stops are taken to be sRGB-encoded.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum ColourInterpolation {
+    /// Interpolate the linear (physical) RGB values.
+    LinearRgb,
+    /// Interpolate the sRGB-encoded values, like most browsers do by default.
+    #[default]
+    Srgb,
+    /// Interpolate in CIELAB (D65), which is roughly perceptually uniform.
+    Lab,
+    /// Interpolate in OKLab, which is more perceptually uniform than CIELAB.
+    OkLab,
+}
+
+impl GradientExtent {
+    /// Returns the mode and the value of the extent, as expected by `gradient.wgsl`.
+    pub fn get(&self) -> (u32, f32) {
+        match self {
+            GradientExtent::Absolute(extent) => (0, *extent),
+            GradientExtent::Relative(extent) => (1, *extent),
+            GradientExtent::Fill => (2, 0.0),
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum TextureSize {
@@ -111,7 +144,9 @@ pub enum TextureRepeat {
     Repeat,
     /// Repeat the texture, mirroring every other repeat.
     Mirror,
-    /// Do not repeat the texture. This is not supported for all backends.
+    /// Do not repeat the texture, i.e. it is transparent outside of its bounds. This requires
+    /// `wgpu::Features::ADDRESS_MODE_CLAMP_TO_BORDER`, and geoms are reported with
+    /// `RendererError::UnsupportedFeatures` on devices without it.
     None,
 }
 
@@ -124,6 +159,28 @@ pub enum TextureFilter {
     Linear,
 }
 
+impl TextureRepeat {
+    /// Returns the corresponding sampler address mode.
+    pub fn address_mode(&self) -> wgpu::AddressMode {
+        match self {
+            TextureRepeat::Repeat => wgpu::AddressMode::Repeat,
+            TextureRepeat::Clamp => wgpu::AddressMode::ClampToEdge,
+            TextureRepeat::Mirror => wgpu::AddressMode::MirrorRepeat,
+            TextureRepeat::None => wgpu::AddressMode::ClampToBorder,
+        }
+    }
+}
+
+impl TextureFilter {
+    /// Returns the corresponding sampler filter mode.
+    pub fn filter_mode(&self) -> wgpu::FilterMode {
+        match self {
+            TextureFilter::Nearest => wgpu::FilterMode::Nearest,
+            TextureFilter::Linear => wgpu::FilterMode::Linear,
+        }
+    }
+}
+
 impl TextureSize {
     pub fn get(&self) -> (u32, f32) {
         match self {
@@ -144,6 +201,10 @@ pub struct TextureMaterial {
     pub filter: TextureFilter,
 }
 
+/// The number of colours in the ramp of a gradient that is created from stops. This is twice the
+/// number of levels of a 10-bit display, so that smooth gradients do not show steps.
+pub const GRADIENT_RAMP_SIZE: u32 = 2048;
+
 #[derive(Clone)]
 pub struct GradientMaterial {
     /// The type of gradient.
@@ -153,14 +214,535 @@ pub struct GradientMaterial {
     /// The repeat mode of the gradient.
     pub repeat: GradientRepeatMode,
     /// The position of the gradient centre, relative to the centre of the shape's bounding box.
+    /// Linear gradients run through the centre (which is halfway along the gradient), radial
+    /// gradients start at the centre, and conic gradients revolve around it.
     pub centre: Point2D,
-    /// The colours of the gradient. All colours are equally spaced, and no interpolation is done.
-    /// This means that you will need to pre-calculate the colours if you want a smooth gradient.
+    /// The colours of the gradient as a single row (or column) of pixels. All colours are equally
+    /// spaced, and no interpolation is done. Use `GradientMaterial::from_stops` (or
+    /// `gradient_ramp`) to create a smooth ramp from colour stops.
     pub ramp_texture: Texture,
-    /// The rotation of the gradient in degrees.
+    /// The rotation of the gradient in degrees. This is the direction of linear gradients and the
+    /// angle at which conic gradients start.
     pub rotation: f32,
 }
 
+impl GradientMaterial {
+    /// Creates a gradient from colour stops, given as `(offset, colour)` with offsets between 0
+    /// and 1. The colours between the stops are interpolated in the given colour space, with
+    /// premultiplied alpha. Before the first and after the last stop, the colour of that stop is
+    /// used. The gradient fills the shape, is centred on it and is not rotated.
+    pub fn from_stops(
+        gradient_type: GradientType,
+        stops: &[(f32, Colour)],
+        interpolation: ColourInterpolation,
+    ) -> Self {
+        Self {
+            gradient_type,
+            extent: GradientExtent::Fill,
+            repeat: GradientRepeatMode::Clamp,
+            centre: Point2D::new(0.0, 0.0),
+            ramp_texture: gradient_ramp(stops, interpolation),
+            rotation: 0.0,
+        }
+    }
+}
+
+/// Creates the ramp texture of a gradient from colour stops (see `GradientMaterial::from_stops`).
+/// The ramp is a single row of `GRADIENT_RAMP_SIZE` floating point colours.
+pub fn gradient_ramp(stops: &[(f32, Colour)], interpolation: ColourInterpolation) -> Texture {
+    let mut stops = stops.to_vec();
+    stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    // the stops in the interpolation space, with premultiplied alpha
+    let components: Vec<(f32, [f64; 4])> = stops
+        .iter()
+        .map(|(offset, colour)| {
+            let alpha = colour.a as f64;
+            let [x, y, z] = interpolation.components(colour);
+            (*offset, [x * alpha, y * alpha, z * alpha, alpha])
+        })
+        .collect();
+
+    let mut pixels = Vec::with_capacity(GRADIENT_RAMP_SIZE as usize * 4);
+    for i in 0..GRADIENT_RAMP_SIZE {
+        // the colour at the centre of the pixel
+        let t = (i as f32 + 0.5) / GRADIENT_RAMP_SIZE as f32;
+
+        let next = components.partition_point(|(offset, _)| *offset <= t);
+        let [x, y, z, a] = match (components.get(next.wrapping_sub(1)), components.get(next)) {
+            (Some((o0, c0)), Some((o1, c1))) => {
+                let f = ((t - o0) / (o1 - o0)) as f64;
+                std::array::from_fn(|j| c0[j] + (c1[j] - c0[j]) * f)
+            }
+            (Some((_, c)), None) | (None, Some((_, c))) => *c,
+            (None, None) => [0.0; 4],
+        };
+
+        let [r, g, b] = if a > 0.0 {
+            interpolation.srgb([x / a, y / a, z / a])
+        } else {
+            [0.0; 3]
+        };
+        pixels.extend([r, g, b, a].map(|value| value as f32));
+    }
+
+    let ramp = image::Rgba32FImage::from_raw(GRADIENT_RAMP_SIZE, 1, pixels)
+        .expect("Ramp size does not match its data. This should not happen.");
+    Texture::from_image(ramp.into(), TextureFormat::Rgba32F)
+}
+
+impl ColourInterpolation {
+    /// Converts an sRGB-encoded colour into the interpolation space.
+    fn components(self, colour: &Colour) -> [f64; 3] {
+        let srgb = [colour.r, colour.g, colour.b].map(f64::from);
+        match self {
+            Self::Srgb => srgb,
+            Self::LinearRgb => srgb.map(srgb_to_linear),
+            Self::Lab => {
+                let [x, y, z] = mat3_mul(&LINEAR_SRGB_TO_XYZ, srgb.map(srgb_to_linear));
+                let [fx, fy, fz] =
+                    [x / D65_WHITE[0], y / D65_WHITE[1], z / D65_WHITE[2]].map(|v| {
+                        if v > LAB_EPSILON {
+                            v.cbrt()
+                        } else {
+                            (LAB_KAPPA * v + 16.0) / 116.0
+                        }
+                    });
+                [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+            }
+            Self::OkLab => {
+                let lms = mat3_mul(&LINEAR_SRGB_TO_LMS, srgb.map(srgb_to_linear));
+                mat3_mul(&LMS_TO_OKLAB, lms.map(f64::cbrt))
+            }
+        }
+    }
+
+    /// Converts a colour in the interpolation space back into sRGB-encoded values.
+    fn srgb(self, components: [f64; 3]) -> [f64; 3] {
+        let linear = match self {
+            Self::Srgb => return components,
+            Self::LinearRgb => components,
+            Self::Lab => {
+                let [l, a, b] = components;
+                let fy = (l + 16.0) / 116.0;
+                let [fx, fz] = [fy + a / 500.0, fy - b / 200.0];
+                let xyz = [fx, fy, fz].map(|f| {
+                    if f.powi(3) > LAB_EPSILON {
+                        f.powi(3)
+                    } else {
+                        (116.0 * f - 16.0) / LAB_KAPPA
+                    }
+                });
+                let xyz = [0, 1, 2].map(|i| xyz[i] * D65_WHITE[i]);
+                mat3_mul(&XYZ_TO_LINEAR_SRGB, xyz)
+            }
+            Self::OkLab => {
+                let lms = mat3_mul(&OKLAB_TO_LMS, components);
+                mat3_mul(&LMS_TO_LINEAR_SRGB, lms.map(|v| v * v * v))
+            }
+        };
+        linear.map(linear_to_srgb)
+    }
+}
+
+fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f64) -> f64 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn mat3_mul(m: &[[f64; 3]; 3], v: [f64; 3]) -> [f64; 3] {
+    m.map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2])
+}
+
+// CIELAB constants (see http://www.brucelindbloom.com)
+const LAB_EPSILON: f64 = 216.0 / 24389.0;
+const LAB_KAPPA: f64 = 24389.0 / 27.0;
+const D65_WHITE: [f64; 3] = [0.95047, 1.0, 1.08883];
+
+#[rustfmt::skip]
+const LINEAR_SRGB_TO_XYZ: [[f64; 3]; 3] = [
+    [0.4124564, 0.3575761, 0.1804375],
+    [0.2126729, 0.7151522, 0.0721750],
+    [0.0193339, 0.1191920, 0.9503041],
+];
+
+#[rustfmt::skip]
+const XYZ_TO_LINEAR_SRGB: [[f64; 3]; 3] = [
+    [ 3.2404542, -1.5371385, -0.4985314],
+    [-0.9692660,  1.8760108,  0.0415560],
+    [ 0.0556434, -0.2040259,  1.0572252],
+];
+
+// OKLab matrices (see https://bottosson.github.io/posts/oklab)
+#[rustfmt::skip]
+const LINEAR_SRGB_TO_LMS: [[f64; 3]; 3] = [
+    [0.4122214708, 0.5363325363, 0.0514459929],
+    [0.2119034982, 0.6806995451, 0.1073969566],
+    [0.0883024619, 0.2817188376, 0.6299787005],
+];
+
+#[rustfmt::skip]
+const LMS_TO_OKLAB: [[f64; 3]; 3] = [
+    [0.2104542553,  0.7936177850, -0.0040720468],
+    [1.9779984951, -2.4285922050,  0.4505937099],
+    [0.0259040371,  0.7827717662, -0.8086757660],
+];
+
+#[rustfmt::skip]
+const OKLAB_TO_LMS: [[f64; 3]; 3] = [
+    [1.0,  0.3963377774,  0.2158037573],
+    [1.0, -0.1055613458, -0.0638541728],
+    [1.0, -0.0894841775, -1.2914855480],
+];
+
+#[rustfmt::skip]
+const LMS_TO_LINEAR_SRGB: [[f64; 3]; 3] = [
+    [ 4.0767416621, -3.3077115913,  0.2309699292],
+    [-1.2684380046,  2.6097574011, -0.3413193965],
+    [-0.0041960863, -0.7034186147,  1.7076147010],
+];
+
+/// The spatial frequency of a grating.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpatialFrequency {
+    /// Cycles per pixel of the render target, independent of the transform of the geom.
+    CyclesPerPixel(f32),
+    /// Cycles per unit of the coordinate system of the primitive.
+    CyclesPerUnit(f32),
+}
+
+impl SpatialFrequency {
+    /// Returns the mode and the value of the frequency, as expected by the shaders.
+    pub fn get(&self) -> (u32, f32) {
+        match self {
+            SpatialFrequency::CyclesPerPixel(frequency) => (0, *frequency),
+            SpatialFrequency::CyclesPerUnit(frequency) => (1, *frequency),
+        }
+    }
+}
+
+/// The waveform of a grating. All waveforms range from -1 to 1 and follow the sign of the sine:
+/// they are positive in the first half of each cycle and negative in the second half. All but the
+/// square wave start at 0 and rise.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum Waveform {
+    /// `sin(2 * pi * t)` at `t` cycles.
+    #[default]
+    Sine,
+    /// 1 in the first half of each cycle and -1 in the second half.
+    Square,
+    /// Rises linearly from -1 to 1 over each cycle (shifted by half a cycle to start at 0).
+    Sawtooth,
+    /// Rises linearly from 0 to 1 in the first quarter of each cycle, falls to -1 at three
+    /// quarters and rises back to 0, like the sine.
+    Triangle,
+}
+
+impl Waveform {
+    /// Evaluates the waveform at `t` cycles, like `wave` in `carrier.wgsl`.
+    fn value(&self, t: f32) -> f32 {
+        match self {
+            Waveform::Sine => (2.0 * std::f32::consts::PI * t).sin(),
+            Waveform::Square => {
+                if t.rem_euclid(1.0) < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Sawtooth => 2.0 * (t + 0.5).rem_euclid(1.0) - 1.0,
+            Waveform::Triangle => 4.0 * ((t - 0.25).rem_euclid(1.0) - 0.5).abs() - 1.0,
+        }
+    }
+}
+
+/// A periodic grating, evaluated analytically for every pixel. The colour of a pixel is
+/// `mean * (1 + contrast * waveform(x))` (except for the alpha of `mean`), where `x` is the
+/// position along the orientation of the grating, relative to the centre of the shape's
+/// bounding box.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GratingMaterial {
+    /// The spatial frequency.
+    pub frequency: SpatialFrequency,
+    /// The phase at the centre of the shape's bounding box in degrees.
+    pub phase: f32,
+    /// The direction in which the grating varies in degrees. At 0 degrees, the stripes are
+    /// vertical.
+    pub orientation: f32,
+    /// The Michelson contrast, usually between 0 and 1.
+    pub contrast: f32,
+    /// The mean colour.
+    pub mean: Colour,
+    /// The waveform.
+    pub waveform: Waveform,
+}
+
+impl GratingMaterial {
+    /// Returns the uniforms of the grating.
+    pub fn uniforms(&self) -> uniform_structs::GratingUniforms {
+        let (frequency_mode, frequency) = self.frequency.get();
+        uniform_structs::GratingUniforms {
+            mean: [self.mean.r, self.mean.g, self.mean.b, self.mean.a],
+            frequency,
+            frequency_mode,
+            phase: self.phase.to_radians(),
+            orientation: self.orientation.to_radians(),
+            contrast: self.contrast,
+            waveform: self.waveform as u32,
+            _padding: [0; 2],
+        }
+    }
+}
+
+/// A Gabor patch: a grating (the carrier) whose contrast is modulated by a Gaussian envelope
+/// centred on the shape's bounding box, i.e. `mean * (1 + contrast * envelope(x, y) * carrier(x))`.
+/// Far from the centre, the patch has the mean colour of the carrier.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GaborMaterial {
+    /// The carrier grating.
+    pub carrier: GratingMaterial,
+    /// The standard deviation of the envelope in x and y, in the units of the primitive.
+    pub sigma: Vector2,
+    /// The rotation of the envelope in degrees, independent of the orientation of the carrier.
+    pub envelope_orientation: f32,
+    /// If true, the amplitude is scaled so that the peak contrast of the patch is the contrast of
+    /// the carrier, independent of its phase and of the size of the envelope. For frequencies in
+    /// cycles per pixel, this uses the scale of the transform of the geom, but not of its
+    /// instances.
+    pub normalise: bool,
+}
+
+impl GaborMaterial {
+    /// The number of samples used to find the peak of the patch.
+    const PEAK_SAMPLES: u32 = 64;
+
+    /// Returns the uniforms of the Gabor patch. `pixel_scale` is the scale of the geom in pixels
+    /// per unit, which is needed to normalise carriers with a frequency in cycles per pixel.
+    pub fn uniforms(&self, pixel_scale: f32) -> uniform_structs::GaborUniforms {
+        let peak = if self.normalise {
+            self.peak(pixel_scale)
+        } else {
+            0.0
+        };
+        let amplitude_scale = if peak > 0.0 { 1.0 / peak } else { 1.0 };
+
+        uniform_structs::GaborUniforms {
+            carrier: self.carrier.uniforms(),
+            sigma: [self.sigma.x, self.sigma.y],
+            envelope_orientation: self.envelope_orientation.to_radians(),
+            amplitude_scale,
+        }
+    }
+
+    /// Returns the largest absolute value of `envelope * carrier`. The peak always lies within
+    /// half a period of the centre, as there is a peak of the carrier in that range.
+    fn peak(&self, pixel_scale: f32) -> f32 {
+        let frequency = match self.carrier.frequency {
+            SpatialFrequency::CyclesPerPixel(frequency) => frequency * pixel_scale,
+            SpatialFrequency::CyclesPerUnit(frequency) => frequency,
+        };
+        if frequency.is_nan() || frequency <= 0.0 {
+            return 0.0;
+        }
+
+        // the standard deviation of the envelope along the carrier
+        let orientation = self.carrier.orientation.to_radians();
+        let envelope_orientation = self.envelope_orientation.to_radians();
+        let angle = orientation - envelope_orientation;
+        let sigma = (angle.cos() * self.sigma.x).hypot(angle.sin() * self.sigma.y);
+
+        let phase = self.carrier.phase.to_radians() / (2.0 * std::f32::consts::PI);
+        (0..=Self::PEAK_SAMPLES)
+            .map(|i| {
+                let x = (i as f32 / Self::PEAK_SAMPLES as f32 - 0.5) / frequency;
+                let t = x * frequency + phase;
+                (-0.5 * x * x / (sigma * sigma)).exp() * self.carrier.waveform.value(t).abs()
+            })
+            .fold(0.0, f32::max)
+    }
+}
+
+/// A checkerboard, evaluated analytically for every pixel. Four checks meet at the centre of the
+/// shape's bounding box.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CheckerboardMaterial {
+    /// The width and height of a check in the units of the primitive.
+    pub check_size: Vector2,
+    /// The phase in x and y in degrees, where 360 degrees are two checks, i.e. a phase of 180
+    /// degrees in one direction swaps the colours.
+    pub phase: Vector2,
+    /// The rotation of the checkerboard in degrees.
+    pub rotation: f32,
+    /// The colours of the checks.
+    pub colours: [Colour; 2],
+}
+
+impl CheckerboardMaterial {
+    /// Returns the uniforms of the checkerboard.
+    pub fn uniforms(&self) -> uniform_structs::CheckerboardUniforms {
+        uniform_structs::CheckerboardUniforms {
+            colours: self.colours.map(|c| [c.r, c.g, c.b, c.a]),
+            check_size: [self.check_size.x, self.check_size.y],
+            phase: [self.phase.x / 180.0, self.phase.y / 180.0],
+            rotation: self.rotation.to_radians(),
+            _padding: [0; 3],
+        }
+    }
+}
+
+/// How the rings of a radial checkerboard are spaced.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum RingSpacing {
+    /// All rings have the same width.
+    #[default]
+    Linear,
+    /// The width of the rings grows with their radius, so that all checks have a similar aspect
+    /// ratio (which roughly compensates for the cortical magnification). The inner radius must be
+    /// larger than 0.
+    Logarithmic,
+}
+
+/// A radial checkerboard (dartboard) of rings and wedges around the centre of the shape's
+/// bounding box. Only the annulus between the inner and the outer radius is drawn.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RadialCheckerboardMaterial {
+    /// The inner radius in the units of the primitive.
+    pub inner_radius: f32,
+    /// The outer radius in the units of the primitive.
+    pub outer_radius: f32,
+    /// The number of rings between the inner and the outer radius.
+    pub rings: u32,
+    /// The number of wedges. This should be even, so that the checks alternate everywhere.
+    pub wedges: u32,
+    /// How the rings are spaced.
+    pub ring_spacing: RingSpacing,
+    /// The radial phase in degrees, where 360 degrees are two rings.
+    pub ring_phase: f32,
+    /// The angular phase in degrees, where 360 degrees are two wedges.
+    pub wedge_phase: f32,
+    /// The colours of the checks.
+    pub colours: [Colour; 2],
+}
+
+impl RadialCheckerboardMaterial {
+    /// Returns the uniforms of the radial checkerboard.
+    pub fn uniforms(&self) -> uniform_structs::RadialCheckerboardUniforms {
+        uniform_structs::RadialCheckerboardUniforms {
+            colours: self.colours.map(|c| [c.r, c.g, c.b, c.a]),
+            inner_radius: self.inner_radius,
+            outer_radius: self.outer_radius,
+            rings: self.rings,
+            wedges: self.wedges,
+            ring_phase: self.ring_phase / 180.0,
+            wedge_phase: self.wedge_phase / 180.0,
+            ring_spacing: self.ring_spacing as u32,
+            _padding: 0,
+        }
+    }
+}
+
+/// A plaid, i.e. the sum of two gratings. The colour of a pixel is
+/// `mean + mean_1 * contrast_1 * waveform_1(x) + mean_2 * contrast_2 * waveform_2(x)`, where `mean`
+/// is the average of the means of the gratings. For gratings with the same mean, this is
+/// `mean * (1 + contrast_1 * waveform_1(x) + contrast_2 * waveform_2(x))`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlaidMaterial {
+    /// The two gratings.
+    pub components: [GratingMaterial; 2],
+}
+
+impl PlaidMaterial {
+    /// Returns the uniforms of the plaid.
+    pub fn uniforms(&self) -> uniform_structs::PlaidUniforms {
+        uniform_structs::PlaidUniforms {
+            components: [self.components[0].uniforms(), self.components[1].uniforms()],
+        }
+    }
+}
+
+/// Parallel lines, or a grid of lines if the hatching is crossed. One line passes through the
+/// centre of the shape's bounding box.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HatchMaterial {
+    /// The distance between the centres of neighbouring lines in the units of the primitive.
+    pub spacing: f32,
+    /// The width of the lines in the units of the primitive.
+    pub width: f32,
+    /// The angle of the lines in degrees. At 0 degrees, the lines are horizontal.
+    pub angle: f32,
+    /// If true, a second set of lines is drawn perpendicular to the first.
+    pub crossed: bool,
+    /// The colour of the lines.
+    pub colour: Colour,
+    /// The colour between the lines.
+    pub background: Colour,
+}
+
+impl HatchMaterial {
+    /// Returns the uniforms of the hatching.
+    pub fn uniforms(&self) -> uniform_structs::HatchUniforms {
+        let colour = |c: &Colour| [c.r, c.g, c.b, c.a];
+        uniform_structs::HatchUniforms {
+            colour: colour(&self.colour),
+            background: colour(&self.background),
+            spacing: self.spacing,
+            width: self.width,
+            angle: self.angle.to_radians(),
+            crossed: self.crossed as u32,
+        }
+    }
+}
+
+/// Noise from a texture of a `NoiseGenerator`. The colour of a pixel is
+/// `mean * (1 + contrast * noise(x, y))` (except for the alpha of `mean`), where the noise has one
+/// value per grain. The noise starts at the corner of the shape's bounding box and repeats if the
+/// shape is larger than the noise.
+#[derive(Clone, Debug)]
+pub struct NoiseMaterial {
+    /// The noise, as generated by `NoiseGenerator::texture`.
+    pub texture: Texture,
+    /// The width and height of a grain in pixels of the render target.
+    pub grain_size: f32,
+    /// The mean colour.
+    pub mean: Colour,
+    /// The contrast, i.e. the scale of the noise relative to the mean.
+    pub contrast: f32,
+}
+
+impl NoiseMaterial {
+    /// Creates a noise material from the noise of the given generator.
+    pub fn new(generator: &NoiseGenerator, grain_size: f32, mean: Colour, contrast: f32) -> Self {
+        Self {
+            texture: generator.texture(),
+            grain_size,
+            mean,
+            contrast,
+        }
+    }
+
+    /// Returns the uniforms of the noise.
+    pub fn uniforms(&self) -> uniform_structs::NoiseUniforms {
+        uniform_structs::NoiseUniforms {
+            mean: [self.mean.r, self.mean.g, self.mean.b, self.mean.a],
+            grain_size: self.grain_size,
+            contrast: self.contrast,
+            _padding: [0; 2],
+        }
+    }
+}
+
 /// A material that defines how a shape should be rendered.
 #[derive(Clone)]
 pub enum Material {
@@ -170,6 +752,22 @@ pub enum Material {
     Texture(TextureMaterial),
     /// Apply the given gradient to the shape.
     Gradient(GradientMaterial),
+    /// A procedural grating.
+    Grating(GratingMaterial),
+    /// A Gabor patch, i.e. a grating in a Gaussian envelope.
+    Gabor(GaborMaterial),
+    /// Seeded noise.
+    Noise(NoiseMaterial),
+    /// A checkerboard.
+    Checkerboard(CheckerboardMaterial),
+    /// A radial checkerboard of rings and wedges.
+    RadialCheckerboard(RadialCheckerboardMaterial),
+    /// The sum of two gratings.
+    Plaid(PlaidMaterial),
+    /// Line hatching.
+    Hatch(HatchMaterial),
+    /// A material with a user-defined shader.
+    Custom(CustomMaterial),
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -177,27 +775,118 @@ pub enum MaterialType {
     Color,
     Texture,
     Gradient,
+    Grating,
+    Gabor,
+    Noise,
+    Checkerboard,
+    RadialCheckerboard,
+    Plaid,
+    Hatch,
+    /// A custom shader, identified by its id.
+    Custom {
+        id: u64,
+        params_size: usize,
+        uses_texture: bool,
+    },
 }
 
 impl Material {
-    /// Returns the vertex shader module for this material.
-    pub fn vertex_shader_module(&self, device: &wgpu::Device) -> wgpu::ShaderModule {
-        device.create_shader_module(wgpu::include_wgsl!("shaders/vertex.wgsl"))
-    }
+    /// Returns the shader module for this material. The fragment shader of each material is
+    /// combined with the shared declarations and the shared vertex shader, which reads the
+    /// per-geom uniforms declared by the material.
+    pub fn shader_module(&self, device: &wgpu::Device) -> wgpu::ShaderModule {
+        let (label, source) = match self {
+            Self::Colour(..) => (
+                "colour.wgsl",
+                concat!(
+                    include_str!("shaders/common.wgsl"),
+                    include_str!("shaders/vertex.wgsl"),
+                    include_str!("shaders/colour.wgsl")
+                ),
+            ),
+            Self::Texture(..) => (
+                "texture.wgsl",
+                concat!(
+                    include_str!("shaders/common.wgsl"),
+                    include_str!("shaders/vertex.wgsl"),
+                    include_str!("shaders/texture.wgsl")
+                ),
+            ),
+            Self::Gradient(..) => (
+                "gradient.wgsl",
+                concat!(
+                    include_str!("shaders/common.wgsl"),
+                    include_str!("shaders/vertex.wgsl"),
+                    include_str!("shaders/gradient.wgsl")
+                ),
+            ),
+            Self::Grating(..) => (
+                "grating.wgsl",
+                concat!(
+                    include_str!("shaders/common.wgsl"),
+                    include_str!("shaders/vertex.wgsl"),
+                    include_str!("shaders/carrier.wgsl"),
+                    include_str!("shaders/grating.wgsl")
+                ),
+            ),
+            Self::Gabor(..) => (
+                "gabor.wgsl",
+                concat!(
+                    include_str!("shaders/common.wgsl"),
+                    include_str!("shaders/vertex.wgsl"),
+                    include_str!("shaders/carrier.wgsl"),
+                    include_str!("shaders/gabor.wgsl")
+                ),
+            ),
+            Self::Noise(..) => (
+                "noise.wgsl",
+                concat!(
+                    include_str!("shaders/common.wgsl"),
+                    include_str!("shaders/vertex.wgsl"),
+                    include_str!("shaders/noise.wgsl")
+                ),
+            ),
+            Self::Checkerboard(..) => (
+                "checkerboard.wgsl",
+                concat!(
+                    include_str!("shaders/common.wgsl"),
+                    include_str!("shaders/vertex.wgsl"),
+                    include_str!("shaders/checkerboard.wgsl")
+                ),
+            ),
+            Self::RadialCheckerboard(..) => (
+                "radial_checkerboard.wgsl",
+                concat!(
+                    include_str!("shaders/common.wgsl"),
+                    include_str!("shaders/vertex.wgsl"),
+                    include_str!("shaders/radial_checkerboard.wgsl")
+                ),
+            ),
+            Self::Plaid(..) => (
+                "plaid.wgsl",
+                concat!(
+                    include_str!("shaders/common.wgsl"),
+                    include_str!("shaders/vertex.wgsl"),
+                    include_str!("shaders/carrier.wgsl"),
+                    include_str!("shaders/plaid.wgsl")
+                ),
+            ),
+            Self::Hatch(..) => (
+                "hatch.wgsl",
+                concat!(
+                    include_str!("shaders/common.wgsl"),
+                    include_str!("shaders/vertex.wgsl"),
+                    include_str!("shaders/hatch.wgsl")
+                ),
+            ),
+            // the source of custom shaders is complete and has been validated
+            Self::Custom(custom) => ("custom.wgsl", custom.shader.source()),
+        };
 
-    /// Returns the fragment shader module for this material.
-    pub fn fragment_shader_module(&self, device: &wgpu::Device) -> wgpu::ShaderModule {
-        match self {
-            Self::Colour(..) => {
-                device.create_shader_module(wgpu::include_wgsl!("shaders/colour.wgsl"))
-            }
-            Self::Texture(..) => {
-                device.create_shader_module(wgpu::include_wgsl!("shaders/texture.wgsl"))
-            }
-            Self::Gradient(..) => {
-                device.create_shader_module(wgpu::include_wgsl!("shaders/gradient.wgsl"))
-            }
-        }
+        device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(label),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        })
     }
 
     /// Returns the material type.
@@ -206,6 +895,18 @@ impl Material {
             Self::Colour { .. } => MaterialType::Color,
             Self::Texture { .. } => MaterialType::Texture,
             Self::Gradient { .. } => MaterialType::Gradient,
+            Self::Grating { .. } => MaterialType::Grating,
+            Self::Gabor { .. } => MaterialType::Gabor,
+            Self::Noise { .. } => MaterialType::Noise,
+            Self::Checkerboard { .. } => MaterialType::Checkerboard,
+            Self::RadialCheckerboard { .. } => MaterialType::RadialCheckerboard,
+            Self::Plaid { .. } => MaterialType::Plaid,
+            Self::Hatch { .. } => MaterialType::Hatch,
+            Self::Custom(custom) => MaterialType::Custom {
+                id: custom.shader.id(),
+                params_size: custom.shader.params_size(),
+                uses_texture: custom.shader.uses_texture(),
+            },
         }
     }
 
@@ -214,6 +915,8 @@ impl Material {
         match self {
             Self::Texture(TextureMaterial { texture, .. }) => Some(texture),
             Self::Gradient(GradientMaterial { ramp_texture, .. }) => Some(ramp_texture),
+            Self::Noise(NoiseMaterial { texture, .. }) => Some(texture),
+            Self::Custom(custom) => custom.texture(),
             _ => None,
         }
     }
@@ -223,8 +926,9 @@ impl Material {
         self.texture().is_some()
     }
 
-    /// Returns the uniform buffer for this material.
-    pub fn uniform_bytes(&self) -> Vec<u8> {
+    /// Returns the uniform buffer for this material. `pixel_scale` is the scale of the geom in
+    /// pixels per unit (see `Transformation::mean_pixel_scale`).
+    pub fn uniform_bytes(&self, pixel_scale: f32) -> Vec<u8> {
         match self {
             Self::Colour(colour) => bytemuck::bytes_of(colour).to_vec(),
             Self::Texture(TextureMaterial { size_x, size_y, .. }) => {
@@ -238,29 +942,66 @@ impl Material {
                 bytemuck::bytes_of(&uniforms).to_vec()
             }
             Self::Gradient(GradientMaterial {
-                centre, rotation, ..
+                gradient_type,
+                extent,
+                repeat,
+                centre,
+                rotation,
+                ..
             }) => {
-                let mut bytes = Vec::new();
-                bytes.extend_from_slice(bytemuck::bytes_of(centre));
-                bytes.extend_from_slice(bytemuck::bytes_of(rotation));
-                bytes
+                let uniforms = uniform_structs::GradientUniforms {
+                    centre: [centre.x, centre.y],
+                    rotation: rotation.to_radians(),
+                    gradient_type: *gradient_type as u32,
+                    extent_mode: extent.get().0,
+                    extent_value: extent.get().1,
+                    repeat: *repeat as u32,
+                    _padding: 0,
+                };
+
+                bytemuck::bytes_of(&uniforms).to_vec()
             }
+            Self::Grating(grating) => bytemuck::bytes_of(&grating.uniforms()).to_vec(),
+            Self::Gabor(gabor) => bytemuck::bytes_of(&gabor.uniforms(pixel_scale)).to_vec(),
+            Self::Noise(noise) => bytemuck::bytes_of(&noise.uniforms()).to_vec(),
+            Self::Checkerboard(checkerboard) => {
+                bytemuck::bytes_of(&checkerboard.uniforms()).to_vec()
+            }
+            Self::RadialCheckerboard(checkerboard) => {
+                bytemuck::bytes_of(&checkerboard.uniforms()).to_vec()
+            }
+            Self::Plaid(plaid) => bytemuck::bytes_of(&plaid.uniforms()).to_vec(),
+            Self::Hatch(hatch) => bytemuck::bytes_of(&hatch.uniforms()).to_vec(),
+            Self::Custom(custom) => custom.params().to_vec(),
         }
     }
 
-    /// Texture repeat modes.
-    pub fn texture_repeat_modes(&self) -> Option<(TextureRepeat, TextureRepeat)> {
+    /// Returns the state of the sampler for the texture of this material, if it has one.
+    pub fn sampler_key(&self) -> Option<SamplerKey> {
         match self {
             Self::Texture(TextureMaterial {
-                repeat_x, repeat_y, ..
-            }) => Some((*repeat_x, *repeat_y)),
-            _ => None,
-        }
-    }
-
-    pub fn texture_filter(&self) -> Option<TextureFilter> {
-        match self {
-            Self::Texture(TextureMaterial { filter, .. }) => Some(*filter),
+                repeat_x,
+                repeat_y,
+                filter,
+                ..
+            }) => Some(SamplerKey {
+                address_mode_u: repeat_x.address_mode(),
+                address_mode_v: repeat_y.address_mode(),
+                filter: filter.filter_mode(),
+            }),
+            // the colours of the ramp are not interpolated, and repeating is done in the shader
+            Self::Gradient(..) => Some(SamplerKey {
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                filter: wgpu::FilterMode::Nearest,
+            }),
+            // one texel per grain, and the noise tiles seamlessly
+            Self::Noise(..) => Some(SamplerKey {
+                address_mode_u: wgpu::AddressMode::Repeat,
+                address_mode_v: wgpu::AddressMode::Repeat,
+                filter: wgpu::FilterMode::Nearest,
+            }),
+            Self::Custom(custom) => custom.sampler_key(),
             _ => None,
         }
     }
@@ -272,30 +1013,142 @@ impl Material {
 }
 
 impl MaterialType {
-    /// Returns the name of the material type.
-    pub fn name(&self) -> &'static str {
-        match self {
-            Self::Color => "Color",
-            Self::Texture => "Texture",
-            Self::Gradient => "Gradient",
-        }
-    }
-
     /// Returns the size of the uniform buffer for this material.
     pub fn uniform_buffer_size(&self) -> usize {
         match self {
             Self::Color { .. } => std::mem::size_of::<[f32; 4]>(),
             Self::Texture { .. } => std::mem::size_of::<[f32; 4]>(),
-            Self::Gradient { .. } => std::mem::size_of::<[f32; 4]>(),
+            Self::Gradient { .. } => std::mem::size_of::<uniform_structs::GradientUniforms>(),
+            Self::Grating { .. } => std::mem::size_of::<uniform_structs::GratingUniforms>(),
+            Self::Gabor { .. } => std::mem::size_of::<uniform_structs::GaborUniforms>(),
+            Self::Noise { .. } => std::mem::size_of::<uniform_structs::NoiseUniforms>(),
+            Self::Checkerboard { .. } => {
+                std::mem::size_of::<uniform_structs::CheckerboardUniforms>()
+            }
+            Self::RadialCheckerboard { .. } => {
+                std::mem::size_of::<uniform_structs::RadialCheckerboardUniforms>()
+            }
+            Self::Plaid { .. } => std::mem::size_of::<uniform_structs::PlaidUniforms>(),
+            Self::Hatch { .. } => std::mem::size_of::<uniform_structs::HatchUniforms>(),
+            Self::Custom { params_size, .. } => *params_size,
         }
     }
+}
 
-    /// Returns true if the material has a texture.
-    pub fn has_texture(&self) -> bool {
-        match self {
-            Self::Texture => true,
-            Self::Gradient => true,
-            _ => false,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gabor_normalisation() {
+        let gabor = |frequency: SpatialFrequency, phase: f32, normalise: bool| GaborMaterial {
+            carrier: GratingMaterial {
+                frequency,
+                phase,
+                orientation: 30.0,
+                contrast: 1.0,
+                mean: Colour::new(0.5, 0.5, 0.5, 1.0),
+                waveform: Waveform::Sine,
+            },
+            sigma: Vector2::new(4.0, 4.0),
+            envelope_orientation: 0.0,
+            normalise,
+        };
+
+        // a cosine phase peaks at the centre, where the envelope is 1
+        let uniforms = gabor(SpatialFrequency::CyclesPerUnit(0.1), 90.0, true).uniforms(1.0);
+        assert!((uniforms.amplitude_scale - 1.0).abs() < 1e-3);
+
+        // a sine phase peaks off-centre, where the envelope is below 1
+        let uniforms = gabor(SpatialFrequency::CyclesPerUnit(0.1), 0.0, true).uniforms(1.0);
+        assert!(uniforms.amplitude_scale > 1.1);
+        let uniforms = gabor(SpatialFrequency::CyclesPerUnit(0.1), 0.0, false).uniforms(1.0);
+        assert_eq!(uniforms.amplitude_scale, 1.0);
+
+        // frequencies in cycles per pixel depend on the scale of the geom
+        let per_unit = gabor(SpatialFrequency::CyclesPerUnit(0.1), 0.0, true).uniforms(1.0);
+        let per_pixel = gabor(SpatialFrequency::CyclesPerPixel(0.05), 0.0, true).uniforms(2.0);
+        assert_eq!(per_unit.amplitude_scale, per_pixel.amplitude_scale);
+
+        // without a carrier, there is nothing to normalise
+        let uniforms = gabor(SpatialFrequency::CyclesPerUnit(0.0), 0.0, true).uniforms(1.0);
+        assert_eq!(uniforms.amplitude_scale, 1.0);
+    }
+
+    #[test]
+    fn colour_conversions() {
+        let interpolations = [
+            ColourInterpolation::Srgb,
+            ColourInterpolation::LinearRgb,
+            ColourInterpolation::Lab,
+            ColourInterpolation::OkLab,
+        ];
+
+        // every conversion can be reversed
+        for interpolation in interpolations {
+            for colour in [
+                Colour::BLACK,
+                Colour::WHITE,
+                Colour::new(0.9, 0.1, 0.1, 1.0),
+                Colour::new(0.02, 0.5, 0.8, 1.0),
+            ] {
+                let srgb = interpolation.srgb(interpolation.components(&colour));
+                for (actual, expected) in srgb.iter().zip([colour.r, colour.g, colour.b]) {
+                    assert!(
+                        (actual - expected as f64).abs() < 1e-6,
+                        "{interpolation:?}: {srgb:?} != {colour:?}"
+                    );
+                }
+            }
+        }
+
+        // white has the full lightness and no chroma
+        let white = ColourInterpolation::Lab.components(&Colour::WHITE);
+        assert!((white[0] - 100.0).abs() < 1e-3 && white[1].abs() < 1e-3 && white[2].abs() < 1e-3);
+        let white = ColourInterpolation::OkLab.components(&Colour::WHITE);
+        assert!((white[0] - 1.0).abs() < 1e-3 && white[1].abs() < 1e-3 && white[2].abs() < 1e-3);
+        let grey = ColourInterpolation::LinearRgb.components(&Colour::new(0.5, 0.5, 0.5, 1.0));
+        assert!((grey[0] - 0.2140).abs() < 1e-4);
+    }
+
+    #[test]
+    fn gradient_ramps() {
+        let ramp = |stops: &[(f32, Colour)], interpolation| -> Vec<[f32; 4]> {
+            let texture = gradient_ramp(stops, interpolation);
+            assert_eq!(texture.size(), (GRADIENT_RAMP_SIZE, 1));
+            bytemuck::cast_slice(texture.data()).to_vec()
+        };
+        let last = GRADIENT_RAMP_SIZE as usize - 1;
+
+        // stops are sorted, and the first and last colour extend to the ends
+        let pixels = ramp(
+            &[(0.75, Colour::BLUE), (0.25, Colour::RED)],
+            ColourInterpolation::Srgb,
+        );
+        assert_eq!(pixels[0], [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(pixels[last / 4], [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(pixels[last], [0.0, 0.0, 1.0, 1.0]);
+        let [r, _, b, _] = pixels[last / 2];
+        assert!((r - 0.5).abs() < 1e-3 && (b - 0.5).abs() < 1e-3);
+
+        // a single stop is a solid colour, and no stops are transparent
+        let pixels = ramp(&[(0.5, Colour::GREEN)], ColourInterpolation::OkLab);
+        assert!(pixels.iter().all(|pixel| *pixel == pixels[0]));
+        assert!(ramp(&[], ColourInterpolation::Lab)
+            .iter()
+            .all(|pixel| *pixel == [0.0; 4]));
+
+        // alpha is premultiplied, so fading out does not change the colour
+        let transparent = Colour::new(0.0, 0.0, 1.0, 0.0);
+        for pixel in &ramp(
+            &[(0.0, Colour::RED), (1.0, transparent)],
+            ColourInterpolation::LinearRgb,
+        )[..last]
+        {
+            assert!(
+                (pixel[0] - 1.0).abs() < 1e-3 && pixel[2].abs() < 1e-3,
+                "{pixel:?}"
+            );
         }
     }
 }
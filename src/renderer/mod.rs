@@ -1,60 +1,277 @@
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::num::NonZeroU64;
 
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use error::RendererError;
+use filter::{FilterLayer, LayerWindow, LAYER_FORMAT};
 use geometry::Geom;
 
-use geometry::Transformation;
-use helpers::Cache;
-use material::TextureFilter;
-use material::TextureRepeat;
+use geometry::{BBox, BlendMode, Instance, Point2D, Transformation};
+use helpers::{Cache, CacheEntry, Cacheable, Fingerprint};
 use material::{Material, MaterialType};
+use pipeline::{FilterPipelineKey, PipelineKey, SamplerKey, TextureBinding};
 
 use texture::Texture;
 use texture::TextureFormat;
-use uniform_structs::ScreenUniforms;
+use uniform_structs::{FilterRange, FilterUniforms, ScreenUniforms};
 use vertex::GPUGeometryBuffer;
+use vertex::GPUInstance;
 use vertex::GPUVertex;
 use wgpu;
 
+pub mod custom;
+pub mod error;
+pub mod filter;
 pub mod geometry;
 pub mod helpers;
 pub mod material;
+pub mod noise;
+pub mod offscreen;
+pub mod path;
+pub mod pipeline;
 pub mod texture;
 pub mod uniform_structs;
 pub mod vertex;
 
-const VERRTEX_BUFFER_SIZE_MB: u32 = 20;
-const INDEX_BUFFER_SIZE_MB: u32 = 20;
-const UNIFORM_BUFFER_SIZE_MB: u32 = 20;
+#[cfg(test)]
+mod golden;
+
+/// Initial sizes of the global buffers in bytes. The buffers grow as needed.
+const INITIAL_VERTEX_BUFFER_SIZE: u64 = 1 << 20;
+const INITIAL_INDEX_BUFFER_SIZE: u64 = 1 << 20;
+const INITIAL_INSTANCE_BUFFER_SIZE: u64 = 1 << 16;
+const INITIAL_FILTER_BUFFER_SIZE: u64 = 1 << 16;
+const INITIAL_UNIFORM_BUFFER_SIZE: u64 = 1 << 20;
+
+/// The alignment of the per-geom uniforms in the uniform buffer, which matches the alignment of
+/// a `mat4x4<f32>` in WGSL.
+const UNIFORM_ALIGNMENT: usize = 16;
+
+/// The number of samples per pixel of the render targets.
+const SAMPLE_COUNT: u32 = 1;
 
+/// The default texture format of the render target, used for offscreen rendering.
+pub const TARGET_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// The vertices and indices of a tessellated geom. The indices are relative to the first vertex.
 pub type CachedTesselation = (Vec<GPUVertex>, Vec<u32>);
 pub type CachedTexture = (wgpu::Buffer, wgpu::Texture, wgpu::TextureView);
 
 pub struct Renderer {
-    /// A HashMap mapping material types to material instances.
-    materials: HashMap<MaterialType, MaterialInstance>,
+    /// The shader modules of the material types.
+    shaders: HashMap<MaterialType, wgpu::ShaderModule>,
+    /// The render pipelines, keyed by the state they were created for.
+    pipelines: HashMap<PipelineKey, wgpu::RenderPipeline>,
+    /// The texture samplers, keyed by their state.
+    samplers: HashMap<SamplerKey, wgpu::Sampler>,
+    /// The bind group layouts of textures and their samplers.
+    texture_bind_group_layouts: HashMap<TextureBinding, wgpu::BindGroupLayout>,
+    /// The custom shaders whose shader modules and pipelines are in the renderer.
+    custom_shaders: HashMap<u64, CacheEntry>,
+    /// The shader module of the passes that apply spatial filters.
+    filter_shader: wgpu::ShaderModule,
+    /// The pipelines of the passes that apply spatial filters.
+    filter_pipelines: HashMap<FilterPipelineKey, wgpu::RenderPipeline>,
+    /// The bind group layout of the passes that apply spatial filters.
+    filter_bind_group_layout: wgpu::BindGroupLayout,
+    /// The intermediate textures of geoms with spatial filters. The first texture is scratch
+    /// space, the others are the layers of the last frame, which cover only the part of the
+    /// render target their geom can reach.
+    filter_textures: Vec<(wgpu::Texture, wgpu::TextureView)>,
+    /// The uniforms of the filter passes of the last frame.
+    filter_pass_uniform_buffer: wgpu::Buffer,
+    /// The kernels of the filter passes of the last frame.
+    filter_kernel_buffer: wgpu::Buffer,
+    /// The number of bytes of the filter pass uniform and kernel buffers used by the last frame.
+    used_filter_pass_sizes: (u64, u64),
     /// The global vertex buffer.
     vertex_buffer: wgpu::Buffer,
     /// The global index buffer.
     index_buffer: wgpu::Buffer,
-    /// The global uniform buffer.
+    /// The global instance buffer.
+    instance_buffer: wgpu::Buffer,
+    /// The screen uniforms.
+    screen_buffer: wgpu::Buffer,
+    /// The global storage buffer with the uniforms of all geoms.
     uniform_buffer: wgpu::Buffer,
+    /// The size of the binding of the uniform buffer, i.e. of the largest batch.
+    uniform_binding_size: u64,
+    /// The global storage buffer with the pixel filters of all geoms.
+    filter_buffer: wgpu::Buffer,
     /// The global bind group layout.
     bind_group_layout: wgpu::BindGroupLayout,
     /// The global bind group.
     bind_group: wgpu::BindGroup,
     /// Global tesselation cache.
-    #[allow(dead_code)]
     tesselation_cache: Cache<CachedTesselation>,
     /// Global texture cache.
     texture_cache: Cache<CachedTexture>,
+    /// The number of bytes of the vertex, index, instance, filter and uniform buffers used by the
+    /// last frame.
+    used_buffer_sizes: (u64, u64, u64, u64, u64),
+    /// How geoms are grouped into draw calls.
+    batching: Batching,
+}
+
+/// How the renderer groups geoms into draw calls.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Batching {
+    /// Every geom is drawn with its own draw call.
+    #[default]
+    None,
+    /// Consecutive geoms with the same material type and texture are drawn with a single draw
+    /// call.
+    Consecutive,
+    /// Like `Consecutive`, but geoms may also be moved to an earlier draw call with the same
+    /// material type and texture, as long as they do not overlap any geom drawn in between. This
+    /// does not change the result, but may reduce the number of draw calls considerably.
+    Reorder,
 }
 
 pub struct RenderData {
-    pub index_buffer_offsets: Vec<u32>,
-    pub index_buffer_sizes: Vec<u32>,
-    pub uniform_buffer_offsets: Vec<u32>,
-    pub texture_bind_groups: Vec<Option<wgpu::BindGroup>>,
+    /// The draw calls of the frame, in order.
+    pub draws: Vec<DrawCall>,
+    /// The geoms with spatial filters, which are rendered by `render_filter_layers`.
+    pub layers: Vec<FilterLayer>,
+    /// The geoms that could not be prepared, as their index and the error. These geoms are
+    /// skipped, and the rest of the frame is rendered as usual.
+    pub errors: Vec<(usize, RendererError)>,
+}
+
+/// A draw call that renders one or more geoms with the same pipeline and texture.
+pub struct DrawCall {
+    /// The key of the pipeline.
+    pub pipeline: PipelineKey,
+    /// The range of the index buffer to draw.
+    pub indices: std::ops::Range<u32>,
+    /// The range of the instance buffer to draw.
+    pub instances: std::ops::Range<u32>,
+    /// The offset of the uniforms of the geoms in the uniform buffer.
+    pub uniform_offset: u32,
+    /// The bind group of the texture, if the material uses one.
+    pub texture_bind_group: Option<wgpu::BindGroup>,
+    /// The index of the filter layer of the geom, if it has spatial filters. In that case the
+    /// draw call blends the layer with the render target instead of drawing the geom.
+    pub layer: Option<usize>,
+}
+
+fn create_vertex_buffer(device: &wgpu::Device, size: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Vertex Buffer"),
+        size,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_index_buffer(device: &wgpu::Device, size: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Index Buffer"),
+        size,
+        usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_instance_buffer(device: &wgpu::Device, size: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Instance Buffer"),
+        size,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_filter_buffer(device: &wgpu::Device, size: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Filter Buffer"),
+        size,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_uniform_buffer(device: &wgpu::Device, size: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Uniform Buffer"),
+        size,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// Creates the global bind group, which binds the screen uniforms, the array of per-geom
+/// uniforms of a draw call (selected by a dynamic offset) from the uniform buffer, and the pixel
+/// filters of all geoms.
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    screen_buffer: &wgpu::Buffer,
+    uniform_buffer: &wgpu::Buffer,
+    uniform_binding_size: u64,
+    filter_buffer: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: screen_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: uniform_buffer,
+                    offset: 0,
+                    size: NonZeroU64::new(uniform_binding_size),
+                }),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: filter_buffer.as_entire_binding(),
+            },
+        ],
+        label: Some("Global uniform bind Group"),
+    })
+}
+
+/// The key of a cached tessellation: the id of the geom plus everything the tessellation depends
+/// on, including the resolved tolerance (which changes with the transform for
+/// `Tolerance::Auto`).
+struct TessellationKey<'a> {
+    geom: &'a Geom,
+    tolerance: f32,
+}
+
+impl Cacheable for TessellationKey<'_> {
+    fn cache_id(&self) -> CacheEntry {
+        self.geom.cache_id()
+    }
+}
+
+impl Fingerprint for TessellationKey<'_> {
+    fn fingerprint(&self) -> u64 {
+        let mut state = DefaultHasher::new();
+        self.geom.fingerprint().hash(&mut state);
+        self.tolerance.to_bits().hash(&mut state);
+        state.finish()
+    }
+}
+
+/// A geom whose pipelines, texture and tessellation have been added to the renderer, see
+/// `Renderer::prepare_geom`.
+struct PreparedGeom<'a> {
+    /// The pipeline that draws the geom.
+    pipeline: PipelineKey,
+    /// The pipelines that render the geom into its filter layer and blend the layer with the
+    /// render target, if the geom has spatial filters.
+    layer_pipelines: Option<(PipelineKey, FilterPipelineKey)>,
+    /// The key of the cached tessellation.
+    tessellation: TessellationKey<'a>,
+    /// The pixel filters and the spatial passes of the geom.
+    filters: filter::SplitFilters<'a>,
 }
 
 /// A renderable object.
@@ -67,37 +284,28 @@ pub enum Renderable {
     // TextElement(TextElement),
 }
 
-pub struct MaterialInstance {
-    pub vertex_shader: wgpu::ShaderModule,
-    pub fragment_shader: wgpu::ShaderModule,
-    pub pipeline: wgpu::RenderPipeline,
-    pub texture_bind_group_layout: Option<wgpu::BindGroupLayout>,
-    pub texture_sampler: Option<wgpu::Sampler>,
-}
-
 impl Renderer {
     /// Creates a new primitive renderer.
     pub fn new(device: &wgpu::Device) -> Self {
         // Create the global vertex buffer that will store all the vertices for all the primitives.
-        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Vertex Buffer"),
-            size: (VERRTEX_BUFFER_SIZE_MB * 1024) as u64,
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        let vertex_buffer = create_vertex_buffer(device, INITIAL_VERTEX_BUFFER_SIZE);
 
         // Create the global index buffer that will store all the indices for all the primitives.
-        let index_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Index Buffer"),
-            size: (INDEX_BUFFER_SIZE_MB * 1024) as u64,
-            usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        let index_buffer = create_index_buffer(device, INITIAL_INDEX_BUFFER_SIZE);
+
+        // Create the global instance buffer that will store the instances of all instanced geoms.
+        let instance_buffer = create_instance_buffer(device, INITIAL_INSTANCE_BUFFER_SIZE);
 
         // Create the global uniform buffer that will store all the uniform data for all the primitives.
-        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Uniform Buffer"),
-            size: (UNIFORM_BUFFER_SIZE_MB * 1024) as u64,
+        let uniform_buffer = create_uniform_buffer(device, INITIAL_UNIFORM_BUFFER_SIZE);
+
+        // Create the global filter buffer that will store the pixel filters of all primitives.
+        let filter_buffer = create_filter_buffer(device, INITIAL_FILTER_BUFFER_SIZE);
+
+        // Create the buffer for the screen uniforms.
+        let screen_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Screen Uniform Buffer"),
+            size: std::mem::size_of::<ScreenUniforms>() as u64,
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
@@ -112,70 +320,207 @@ impl Renderer {
                     visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: true,
+                        has_dynamic_offset: false,
                         min_binding_size: None,
                     },
                     count: None,
                 },
-                // buffer 1 contains uniform data
+                // buffer 1 contains the per-geom uniforms of a draw call
                 wgpu::BindGroupLayoutEntry {
                     binding: 1,
                     visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
                     ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
                         has_dynamic_offset: true,
                         min_binding_size: None,
                     },
                     count: None,
                 },
-            ],
-        });
-
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                        buffer: &uniform_buffer,
-                        offset: 0,
-                        size: Some(NonZeroU64::new(256).unwrap()),
-                    }),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                        buffer: &uniform_buffer,
-                        offset: 0,
-                        size: Some(NonZeroU64::new(256).unwrap()),
-                    }),
+                // buffer 2 contains the pixel filters of all geoms
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
                 },
             ],
-            label: Some("Global uniform bind Group"),
         });
 
-        // Create the material cache.
-        let materials = HashMap::<MaterialType, MaterialInstance>::new();
+        let uniform_binding_size = UNIFORM_ALIGNMENT as u64;
+        let bind_group = create_bind_group(
+            device,
+            &bind_group_layout,
+            &screen_buffer,
+            &uniform_buffer,
+            uniform_binding_size,
+            &filter_buffer,
+        );
 
         Self {
-            materials,
+            shaders: HashMap::new(),
+            pipelines: HashMap::new(),
+            samplers: HashMap::new(),
+            texture_bind_group_layouts: HashMap::new(),
+            filter_shader: filter::create_filter_shader(device),
+            filter_pipelines: HashMap::new(),
+            filter_bind_group_layout: filter::create_filter_bind_group_layout(device),
+            custom_shaders: HashMap::new(),
+            filter_textures: vec![],
+            filter_pass_uniform_buffer: filter::create_filter_pass_uniform_buffer(
+                device,
+                filter::INITIAL_FILTER_PASS_BUFFER_SIZE,
+            ),
+            filter_kernel_buffer: filter::create_filter_kernel_buffer(
+                device,
+                filter::INITIAL_FILTER_PASS_BUFFER_SIZE,
+            ),
+            used_filter_pass_sizes: (0, 0),
             vertex_buffer,
+            screen_buffer,
             uniform_buffer,
+            uniform_binding_size,
+            filter_buffer,
             index_buffer,
+            instance_buffer,
             bind_group_layout,
             bind_group,
             tesselation_cache: Cache::new(),
             texture_cache: Cache::new(),
+            used_buffer_sizes: (0, 0, 0, 0, 0),
+            batching: Batching::default(),
+        }
+    }
+
+    /// Makes sure the global buffers can hold the given number of bytes, reallocating them if
+    /// necessary. Buffers grow to the next power of two to avoid reallocating every frame. The
+    /// contents of reallocated buffers are lost. Returns true if the uniform or the filter buffer
+    /// was reallocated, in which case the global bind group must be recreated.
+    fn reserve_buffers(
+        &mut self,
+        device: &wgpu::Device,
+        vertex_bytes: u64,
+        index_bytes: u64,
+        instance_bytes: u64,
+        filter_bytes: u64,
+        uniform_bytes: u64,
+    ) -> bool {
+        if self.vertex_buffer.size() < vertex_bytes {
+            self.vertex_buffer = create_vertex_buffer(device, vertex_bytes.next_power_of_two());
+        }
+
+        if self.index_buffer.size() < index_bytes {
+            self.index_buffer = create_index_buffer(device, index_bytes.next_power_of_two());
+        }
+
+        if self.instance_buffer.size() < instance_bytes {
+            self.instance_buffer =
+                create_instance_buffer(device, instance_bytes.next_power_of_two());
+        }
+
+        let grow_filter_buffer = self.filter_buffer.size() < filter_bytes;
+        if grow_filter_buffer {
+            self.filter_buffer = create_filter_buffer(device, filter_bytes.next_power_of_two());
+        }
+
+        let grow_uniform_buffer = self.uniform_buffer.size() < uniform_bytes;
+        if grow_uniform_buffer {
+            self.uniform_buffer = create_uniform_buffer(device, uniform_bytes.next_power_of_two());
+        }
+
+        grow_filter_buffer || grow_uniform_buffer
+    }
+
+    /// Shrinks the global buffers to what the last frame needed (but not below their initial
+    /// sizes), e.g. after rendering an unusually large scene. Must not be called between
+    /// `prepare` and `render`.
+    pub fn shrink_buffers(&mut self, device: &wgpu::Device) {
+        let (vertex_bytes, index_bytes, instance_bytes, filter_bytes, uniform_bytes) =
+            self.used_buffer_sizes;
+
+        let vertex_size = vertex_bytes
+            .next_power_of_two()
+            .max(INITIAL_VERTEX_BUFFER_SIZE);
+        if self.vertex_buffer.size() > vertex_size {
+            self.vertex_buffer = create_vertex_buffer(device, vertex_size);
+        }
+
+        let index_size = index_bytes
+            .next_power_of_two()
+            .max(INITIAL_INDEX_BUFFER_SIZE);
+        if self.index_buffer.size() > index_size {
+            self.index_buffer = create_index_buffer(device, index_size);
+        }
+
+        let instance_size = instance_bytes
+            .next_power_of_two()
+            .max(INITIAL_INSTANCE_BUFFER_SIZE);
+        if self.instance_buffer.size() > instance_size {
+            self.instance_buffer = create_instance_buffer(device, instance_size);
+        }
+
+        let filter_size = filter_bytes
+            .next_power_of_two()
+            .max(INITIAL_FILTER_BUFFER_SIZE);
+        let shrink_filter_buffer = self.filter_buffer.size() > filter_size;
+        if shrink_filter_buffer {
+            self.filter_buffer = create_filter_buffer(device, filter_size);
+        }
+
+        let uniform_size = uniform_bytes
+            .next_power_of_two()
+            .max(INITIAL_UNIFORM_BUFFER_SIZE);
+        let shrink_uniform_buffer = self.uniform_buffer.size() > uniform_size;
+        if shrink_uniform_buffer {
+            self.uniform_buffer = create_uniform_buffer(device, uniform_size);
+        }
+
+        self.shrink_filter_pass_buffers(device);
+
+        // the bind group refers to the old buffers
+        if shrink_filter_buffer || shrink_uniform_buffer {
+            self.bind_group = create_bind_group(
+                device,
+                &self.bind_group_layout,
+                &self.screen_buffer,
+                &self.uniform_buffer,
+                self.uniform_binding_size,
+                &self.filter_buffer,
+            );
         }
     }
 
     /// Adds a texture to the cache. If the texture is already in the cache, this is a no-op.
     /// If the texture is in the cache, but the texture has changed, the texture is updated using the same buffer.
-    pub fn add_texture(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, texture: &Texture) {
+    pub fn add_texture(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture: &Texture,
+    ) -> Result<(), RendererError> {
         if self.texture_cache.get_sweep(texture).is_some() {
             // this will also remove the texture from the cache
             // if the fingerprint has changed
-            return;
+            return Ok(());
+        }
+
+        // make sure the texture data matches its size, otherwise wgpu would panic
+        if texture.width() == 0 || texture.height() == 0 {
+            return Err(RendererError::InvalidTextureSize {
+                width: texture.width(),
+                height: texture.height(),
+            });
+        }
+
+        let expected_len = texture.bytes_per_row() as usize * texture.height() as usize;
+        if texture.data().len() != expected_len {
+            return Err(RendererError::InvalidTextureData {
+                expected: expected_len,
+                actual: texture.data().len(),
+            });
         }
 
         let texture_format = match texture.format() {
@@ -219,7 +564,7 @@ impl Renderer {
                 origin: wgpu::Origin3d::ZERO,
                 aspect: wgpu::TextureAspect::All,
             },
-            &texture.data(),
+            texture.data(),
             wgpu::ImageDataLayout {
                 offset: 0,
                 bytes_per_row: Some(texture.bytes_per_row()),
@@ -235,34 +580,46 @@ impl Renderer {
         // add the texture to the cache
         self.texture_cache
             .insert(texture, (texture_buffer, gpu_texture, texture_view));
+
+        Ok(())
     }
 
-    /// Prepares the bind group given a texture and a material instance.
-    pub fn get_texture_bind_group(
+    /// Returns the view of a texture, the sampler with the given state and the layout of their
+    /// bind group, or an error if any of them has not been added to the renderer.
+    fn texture_binding_resources(
         &self,
-        device: &wgpu::Device,
         texture: &Texture,
-        material_instance: &MaterialInstance,
-    ) -> wgpu::BindGroup {
-        // If the texture is not in the cache, we error out (this should be impossible).
+        sampler_key: &SamplerKey,
+    ) -> Result<(&wgpu::TextureView, &wgpu::Sampler, &wgpu::BindGroupLayout), RendererError> {
+        // the texture must have been added with `add_texture`
         let (_texture_buffer, _texture, texture_view) = self
             .texture_cache
             .get(texture)
-            .expect("Texture not in cache. This should not happen.");
-
-        // get the texture sampler from the material instance
-        let texture_sampler = material_instance
-            .texture_sampler
-            .as_ref()
-            .expect("Material instance does not have a texture sampler. This should not happen.");
-
-        // get the texture bind group layout from the material instance
-        let texture_bind_group_layout = material_instance
-            .texture_bind_group_layout
-            .as_ref()
-            .expect(
-            "Material instance does not have a texture bind group layout. This should not happen.",
-        );
+            .ok_or(RendererError::MissingTexture)?;
+
+        // the sampler and the layout are created by `add_material`
+        let texture_sampler = self
+            .samplers
+            .get(sampler_key)
+            .ok_or(RendererError::MissingTextureBinding)?;
+
+        let texture_bind_group_layout = self
+            .texture_bind_group_layouts
+            .get(&sampler_key.texture_binding())
+            .ok_or(RendererError::MissingTextureBinding)?;
+
+        Ok((texture_view, texture_sampler, texture_bind_group_layout))
+    }
+
+    /// Prepares the bind group of a texture and the sampler with the given state.
+    pub fn get_texture_bind_group(
+        &self,
+        device: &wgpu::Device,
+        texture: &Texture,
+        sampler_key: &SamplerKey,
+    ) -> Result<wgpu::BindGroup, RendererError> {
+        let (texture_view, texture_sampler, texture_bind_group_layout) =
+            self.texture_binding_resources(texture, sampler_key)?;
 
         // create the texture bind group
         let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -280,262 +637,482 @@ impl Renderer {
             label: Some("Texture Bind Group"),
         });
 
-        texture_bind_group
+        Ok(texture_bind_group)
     }
 
-    /// Add a material type to the renderer. If the material type is already in the renderer, this is a no-op.
-    pub fn add_material(&mut self, device: &wgpu::Device, material: Material) {
-        // Check if the material type is already in the renderer.
-        if self.materials.contains_key(&material.material_type()) {
-            return;
+    /// Returns the key of the pipeline that renders the given material with the given blend mode
+    /// into a target of the given format.
+    pub fn pipeline_key(
+        &self,
+        material: &Material,
+        blend_mode: BlendMode,
+        target_format: wgpu::TextureFormat,
+    ) -> PipelineKey {
+        PipelineKey {
+            material_type: material.material_type(),
+            texture_binding: material.sampler_key().map(|key| key.texture_binding()),
+            blend: blend_mode.blend_state(),
+            target_format,
+            sample_count: SAMPLE_COUNT,
         }
+    }
 
-        // create the pipeline layout
-        let pipeline_layout;
-        let mut texture_sampler = None;
-        let mut texture_bind_group_layout = None;
-
-        if material.has_texture() {
-            let filter = material
-                .texture_filter()
-                .expect("Material does not have texture filter. This should not happen.");
-
-            let gpu_filter = match filter {
-                TextureFilter::Nearest => wgpu::FilterMode::Nearest,
-                TextureFilter::Linear => wgpu::FilterMode::Linear,
-            };
+    /// Adds the pipeline and sampler needed to render the given material with the given blend
+    /// mode into a target of the given format, and returns the key of the pipeline. Pipelines and
+    /// samplers that are already in the renderer are reused.
+    pub fn add_material(
+        &mut self,
+        device: &wgpu::Device,
+        material: &Material,
+        blend_mode: BlendMode,
+        target_format: wgpu::TextureFormat,
+    ) -> Result<PipelineKey, RendererError> {
+        // every distinct sampler state gets its own sampler
+        if let Some(sampler_key) = material.sampler_key() {
+            if let Entry::Vacant(entry) = self.samplers.entry(sampler_key) {
+                let missing_features = sampler_key.required_features() - device.features();
+                if !missing_features.is_empty() {
+                    return Err(RendererError::UnsupportedFeatures(missing_features));
+                }
+
+                device.push_error_scope(wgpu::ErrorFilter::Validation);
+                let sampler = sampler_key.create_sampler(device);
+                if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+                    return Err(RendererError::Gpu(error.to_string()));
+                }
+                entry.insert(sampler);
+            }
+        }
 
-            let sampler_binding_type = match gpu_filter {
-                wgpu::FilterMode::Nearest => wgpu::SamplerBindingType::NonFiltering,
-                wgpu::FilterMode::Linear => wgpu::SamplerBindingType::Filtering,
-            };
+        // custom shaders that sample a texture cannot be rendered without one
+        if let Material::Custom(custom) = material {
+            if custom.shader.uses_texture() && custom.texture.is_none() {
+                return Err(RendererError::MissingShaderTexture);
+            }
 
-            let _texture_bind_group_layout =
-                device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                    label: Some("Texture Bind Group Layout"),
-                    entries: &[
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 0,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Texture {
-                                multisampled: false,
-                                view_dimension: wgpu::TextureViewDimension::D2,
-                                sample_type: wgpu::TextureSampleType::Float {
-                                    filterable: (gpu_filter == wgpu::FilterMode::Linear),
-                                },
-                            },
-                            count: None,
-                        },
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 1,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Sampler(sampler_binding_type),
-                            count: None,
-                        },
-                    ],
-                });
-
-            pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Pipeline Layout"),
-                bind_group_layouts: &[&self.bind_group_layout, &_texture_bind_group_layout],
-                push_constant_ranges: &[],
-            });
+            // remember the shader, so that its pipelines can be removed once it is dropped
+            self.custom_shaders
+                .entry(custom.shader.id())
+                .or_insert_with(|| custom.shader.cache_id());
+        }
 
-            // get address modes from material
-            let repeat_modes = material
-                .texture_repeat_modes()
-                .expect("Material does not have texture repeat modes. This should not happen.");
+        let key = self.pipeline_key(material, blend_mode, target_format);
 
-            let address_mode_u = match repeat_modes.0 {
-                TextureRepeat::Repeat => wgpu::AddressMode::Repeat,
-                TextureRepeat::Clamp => wgpu::AddressMode::ClampToEdge,
-                TextureRepeat::Mirror => wgpu::AddressMode::MirrorRepeat,
-                TextureRepeat::None => wgpu::AddressMode::ClampToBorder,
-            };
+        // Check if the pipeline is already in the renderer.
+        if self.pipelines.contains_key(&key) {
+            return Ok(key);
+        }
 
-            let address_mode_v = match repeat_modes.1 {
-                TextureRepeat::Repeat => wgpu::AddressMode::Repeat,
-                TextureRepeat::Clamp => wgpu::AddressMode::ClampToEdge,
-                TextureRepeat::Mirror => wgpu::AddressMode::MirrorRepeat,
-                TextureRepeat::None => wgpu::AddressMode::ClampToBorder,
-            };
+        // catch validation errors (e.g. in shaders) instead of panicking
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
 
-            texture_sampler = Some(device.create_sampler(&wgpu::SamplerDescriptor {
-                label: Some("Texture Sampler"),
-                address_mode_u: address_mode_u,
-                address_mode_v: address_mode_v,
-                address_mode_w: wgpu::AddressMode::ClampToEdge,
-                mag_filter: gpu_filter,
-                min_filter: gpu_filter,
-                mipmap_filter: wgpu::FilterMode::Nearest,
-                lod_min_clamp: 0.0,
-                lod_max_clamp: 100.0,
-                compare: None,
-                anisotropy_clamp: 1,
-                border_color: Some(wgpu::SamplerBorderColor::TransparentBlack),
-            }));
-
-            texture_bind_group_layout = Some(_texture_bind_group_layout);
-        } else {
-            pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        // create the pipeline layout
+        let pipeline_layout = match key.texture_binding {
+            Some(texture_binding) => {
+                let texture_bind_group_layout = self
+                    .texture_bind_group_layouts
+                    .entry(texture_binding)
+                    .or_insert_with(|| texture_binding.create_bind_group_layout(device));
+
+                device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("Pipeline Layout"),
+                    bind_group_layouts: &[&self.bind_group_layout, texture_bind_group_layout],
+                    push_constant_ranges: &[],
+                })
+            }
+            None => device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Pipeline Layout"),
                 bind_group_layouts: &[&self.bind_group_layout],
                 push_constant_ranges: &[],
-            });
+            }),
         };
 
-        // Create the shader modules for the material.
-        let vertex_shader = material.vertex_shader_module(device);
-        let fragment_shader = material.fragment_shader_module(device);
+        // Create the shader module for the material, which is shared by all its pipelines.
+        let shader = self
+            .shaders
+            .entry(key.material_type)
+            .or_insert_with(|| material.shader_module(device));
 
         // create the pipeline
         let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: None,
             layout: Some(&pipeline_layout),
             vertex: wgpu::VertexState {
-                module: &vertex_shader,
+                module: shader,
                 entry_point: "vs_main",
-                buffers: &[wgpu::VertexBufferLayout {
-                    array_stride: std::mem::size_of::<GPUVertex>() as u64,
-                    step_mode: wgpu::VertexStepMode::Vertex,
-                    attributes: GPUVertex::desc(),
-                }],
+                buffers: &[
+                    wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<GPUVertex>() as u64,
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: GPUVertex::desc(),
+                    },
+                    wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<GPUInstance>() as u64,
+                        step_mode: wgpu::VertexStepMode::Instance,
+                        attributes: GPUInstance::desc(),
+                    },
+                ],
                 compilation_options: wgpu::PipelineCompilationOptions::default(),
             },
             fragment: Some(wgpu::FragmentState {
-                module: &fragment_shader,
+                module: shader,
                 compilation_options: wgpu::PipelineCompilationOptions::default(),
                 entry_point: "fs_main",
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: wgpu::TextureFormat::Rgba16Float,
-                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    format: key.target_format,
+                    blend: Some(key.blend),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
             }),
             primitive: wgpu::PrimitiveState::default(),
             depth_stencil: None,
             multisample: wgpu::MultisampleState {
-                count: 1,
+                count: key.sample_count,
                 mask: !0,
                 alpha_to_coverage_enabled: false,
             },
             multiview: None,
         });
 
-        self.materials.insert(
-            material.material_type(),
-            MaterialInstance {
-                vertex_shader,
-                fragment_shader,
-                pipeline,
-                texture_bind_group_layout,
-                texture_sampler,
-            },
-        );
+        if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+            // the shader may be invalid, so do not keep it around
+            self.shaders.remove(&key.material_type);
+            return Err(RendererError::Gpu(error.to_string()));
+        }
+
+        self.pipelines.insert(key, pipeline);
+
+        Ok(key)
+    }
+
+    /// Removes the shader modules and pipelines of custom shaders that no longer exist.
+    fn sweep_custom_shaders(&mut self) {
+        let mut dropped = vec![];
+        self.custom_shaders.retain(|id, entry| {
+            let is_dropped = entry.is_sole_ref();
+            if is_dropped {
+                dropped.push(*id);
+            }
+            !is_dropped
+        });
+        if dropped.is_empty() {
+            return;
+        }
+
+        let is_dropped = |material_type: &MaterialType| matches!(material_type, MaterialType::Custom { id, .. } if dropped.contains(id));
+        self.shaders
+            .retain(|material_type, _| !is_dropped(material_type));
+        self.pipelines
+            .retain(|key, _| !is_dropped(&key.material_type));
+    }
+
+    /// Sets how geoms are grouped into draw calls (see `Batching`).
+    pub fn set_batching(&mut self, batching: Batching) {
+        self.batching = batching;
     }
 
-    /// Prepare the renderer for rendering.
+    /// Prepare the renderer for rendering. Geoms that cannot be prepared (e.g. because their
+    /// primitive is not finite) are skipped and returned in `RenderData::errors`.
     pub fn prepare(
         &mut self,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         surface_desc: &wgpu::SurfaceConfiguration,
         geoms: &[Geom],
-    ) -> RenderData {
+    ) -> Result<RenderData, RendererError> {
         let mut draw_buffer_collector = GPUGeometryBuffer::new();
 
-        let offset_alignment = device.limits().min_uniform_buffer_offset_alignment as usize;
-
-        let mut uniform_buffer_offsets: Vec<u32> = vec![2 * offset_alignment as u32];
-        let mut texture_bind_groups: Vec<Option<wgpu::BindGroup>> = vec![];
+        // drop the tessellations of geoms that no longer exist
+        self.tesselation_cache.sweep();
+        self.sweep_custom_shaders();
 
-        // calculate total size of the uniform buffer
-        let uniform_buffer_size = geoms.len() * offset_alignment + 2 * offset_alignment;
-
-        for geom in geoms {
-            // add material
-            self.add_material(&device, geom.material.clone());
+        // write screen uniforms
+        let screen_uniforms = ScreenUniforms {
+            screen_width: surface_desc.width,
+            screen_height: surface_desc.height,
+        };
+        queue.write_buffer(&self.screen_buffer, 0, bytemuck::bytes_of(&screen_uniforms));
+
+        // prepare the pipelines, textures, filters and tessellations of the geoms. Geoms that
+        // cannot be prepared are skipped and reported, so that the rest of the frame is rendered
+        let mut errors = vec![];
+        let mut prepared = Vec::with_capacity(geoms.len());
+        let mut pipeline_keys = Vec::with_capacity(geoms.len());
+        let mut layer_pipelines = Vec::with_capacity(geoms.len());
+        let mut keys = Vec::with_capacity(geoms.len());
+        let mut geom_filters = Vec::with_capacity(geoms.len());
+        for (index, geom) in geoms.iter().enumerate() {
+            match self.prepare_geom(device, queue, surface_desc, geom) {
+                Ok(prepared_geom) => {
+                    prepared.push(geom);
+                    pipeline_keys.push(prepared_geom.pipeline);
+                    layer_pipelines.push(prepared_geom.layer_pipelines);
+                    keys.push(prepared_geom.tessellation);
+                    geom_filters.push(Some(prepared_geom.filters));
+                }
+                Err(error) => errors.push((index, error)),
+            }
         }
+        let geoms = prepared;
+
+        let mut filter_pass_layout = self.reserve_filter_pass_buffers(
+            device,
+            geom_filters
+                .iter()
+                .flatten()
+                .map(|(_, passes)| passes.as_slice()),
+        );
+
+        let batches = self.batch(&geoms, &keys, &pipeline_keys, surface_desc);
 
-        {
-            let mut staging_buffer = queue
-                .write_buffer_with(
-                    &self.uniform_buffer,
-                    0,
-                    NonZeroU64::new(uniform_buffer_size as u64).unwrap(),
-                )
-                .expect("Failed to write buffer");
-
-            // write screen uniforms
-            let screen_uniforms = ScreenUniforms {
-                screen_width: surface_desc.width,
-                screen_height: surface_desc.height,
+        // geoms with spatial filters are rendered into their own texture first, which covers the
+        // part of the render target that the filters can spread the geom over. The textures are
+        // used in the order of the batches, after the scratch texture that all layers share
+        let layer_windows: Vec<Option<LayerWindow>> = (0..geoms.len())
+            .map(|i| {
+                layer_pipelines[i]?;
+                let (_, passes) = geom_filters[i]
+                    .as_ref()
+                    .expect("Filters of a geom are missing. This should not happen.");
+                let (vertices, _) = self
+                    .tesselation_cache
+                    .get(&keys[i])
+                    .expect("Tesselation not in cache. This should not happen.");
+                let bounds = screen_bounds(
+                    vertices,
+                    &geoms[i].transform.unwrap_or(Transformation::identity()),
+                    geoms[i].instances.as_deref(),
+                    surface_desc.width,
+                    surface_desc.height,
+                );
+                Some(filter::layer_window(
+                    &bounds,
+                    passes,
+                    surface_desc.width,
+                    surface_desc.height,
+                ))
+            })
+            .collect();
+        let layer_sizes: Vec<(u32, u32)> = batches
+            .iter()
+            .filter_map(|batch| layer_windows[batch[0]])
+            .map(|window| (window.width, window.height))
+            .collect();
+        self.reserve_filter_textures(device, &layer_sizes);
+
+        // lay out the uniforms of each batch as an array, starting at an aligned offset
+        let offset_alignment = device.limits().min_storage_buffer_offset_alignment as usize;
+        let primitive_uniforms_len = std::mem::size_of::<Transformation>()
+            + std::mem::size_of::<BBox>()
+            + std::mem::size_of::<FilterRange>();
+
+        // the first instance is used by all geoms that are not instanced
+        let mut instances = vec![GPUInstance::IDENTITY];
+
+        // the filters of all geoms (an empty storage buffer binding is not allowed, so there is
+        // always at least one, unused filter)
+        let mut filters: Vec<FilterUniforms> = vec![bytemuck::Zeroable::zeroed()];
+
+        let mut uniforms = Vec::<u8>::new();
+        let mut binding_size = UNIFORM_ALIGNMENT;
+        let mut draws = Vec::with_capacity(batches.len());
+        let mut layers = vec![];
+
+        for batch in batches {
+            let first_geom = &geoms[batch[0]];
+
+            uniforms.resize(uniforms.len().next_multiple_of(offset_alignment), 0);
+            let uniform_offset = uniforms.len();
+
+            // some materials depend on the scale of the geom in pixels
+            let material_uniforms: Vec<Vec<u8>> = batch
+                .iter()
+                .map(|&i| {
+                    let transform = geoms[i].transform.unwrap_or(Transformation::identity());
+                    geoms[i]
+                        .material
+                        .uniform_bytes(transform.mean_pixel_scale())
+                })
+                .collect();
+
+            // all geoms in a batch use the same material type, so the array elements usually
+            // have the same size, but we use the largest one to be safe
+            let stride = material_uniforms
+                .iter()
+                .map(|bytes| {
+                    (primitive_uniforms_len + bytes.len()).next_multiple_of(UNIFORM_ALIGNMENT)
+                })
+                .max()
+                .unwrap_or(UNIFORM_ALIGNMENT);
+
+            let first_index = draw_buffer_collector.indices.len() as u32;
+
+            // instanced geoms are always drawn on their own
+            let first_instance = instances.len() as u32;
+            let instance_range = match &first_geom.instances {
+                Some(geom_instances) => {
+                    instances.extend(geom_instances.iter().map(GPUInstance::from));
+                    first_instance..instances.len() as u32
+                }
+                None => 0..1,
             };
 
-            staging_buffer[0..std::mem::size_of::<ScreenUniforms>()]
-                .copy_from_slice(bytemuck::bytes_of(&screen_uniforms));
+            // geoms with spatial filters are always drawn on their own
+            let mut spatial_passes = vec![];
 
-            // prepare the draw buffer
-            for geom in geoms {
-                draw_buffer_collector.tesselate(&geom.primitive, &geom.options);
+            for (geom_index, &i) in batch.iter().enumerate() {
+                let geom = geoms[i];
+                let (pixel_filters, passes) = geom_filters[i]
+                    .take()
+                    .expect("Filters of a geom are used twice. This should not happen.");
+                spatial_passes = passes;
 
-                // as primitive uniforms
-                let mut primitive_uniforms = Vec::<u8>::with_capacity(80);
-                primitive_uniforms.extend(bytemuck::bytes_of(
+                // primitive uniforms: the transform and the bbox (min, max)
+                let element_start = uniforms.len();
+                uniforms.extend(bytemuck::bytes_of(
                     &geom.transform.unwrap_or(Transformation::identity()),
                 ));
+                uniforms.extend(bytemuck::bytes_of(&geom.primitive.bbox()));
+
+                // the range of the filters of the geom in the filter buffer (spatial filters and
+                // the filters that follow them are applied by the passes of its layer)
+                let filter_range = FilterRange {
+                    start: filters.len() as u32,
+                    count: pixel_filters.len() as u32,
+                    _padding: [0; 2],
+                };
+                filters.extend(pixel_filters.iter().map(|filter| filter.uniforms()));
+                uniforms.extend(bytemuck::bytes_of(&filter_range));
+
+                // material uniforms
+                uniforms.extend(&material_uniforms[geom_index]);
+                uniforms.resize(element_start + stride, 0);
+
+                let (vertices, indices) = self
+                    .tesselation_cache
+                    .get(&keys[i])
+                    .expect("Tesselation not in cache. This should not happen.");
+                draw_buffer_collector.append(vertices, indices, geom_index as u32);
+            }
 
-                // add the bbox (min, max)
-                primitive_uniforms.extend(bytemuck::bytes_of(&geom.primitive.bbox()));
-
-                let primitive_uniforms_len = primitive_uniforms.len() as usize;
-
-                // add material uniforms
-                let material_uniforms = geom.material.uniform_bytes();
-
-                let current_uniform_offset = uniform_buffer_offsets.last().unwrap().clone();
-
-                // lenght must be a multiple of the alignment
-                let current_uniform_length =
-                    (material_uniforms.len() + primitive_uniforms_len + offset_alignment - 1)
-                        & !(offset_alignment - 1);
+            binding_size = binding_size.max(stride * batch.len());
+
+            // all geoms in a batch use the same texture and sampler, which were checked by
+            // `prepare_geom`
+            let texture_bind_group = match (
+                first_geom.material.texture(),
+                first_geom.material.sampler_key(),
+            ) {
+                (Some(texture), Some(sampler_key)) => Some(
+                    self.get_texture_bind_group(device, texture, &sampler_key)
+                        .expect(
+                            "Texture of a geom is not in the renderer. This should not happen.",
+                        ),
+                ),
+                _ => None,
+            };
 
-                // copy the uniforms into the buffer at the correct offset
-                staging_buffer[(current_uniform_offset as usize)
-                    ..(current_uniform_offset as usize + primitive_uniforms_len as usize)]
-                    .copy_from_slice(bytemuck::cast_slice(primitive_uniforms.as_slice()));
+            let mut draw = DrawCall {
+                pipeline: pipeline_keys[batch[0]],
+                indices: first_index..draw_buffer_collector.indices.len() as u32,
+                instances: instance_range,
+                uniform_offset: uniform_offset as u32,
+                texture_bind_group,
+                layer: None,
+            };
 
-                staging_buffer[(current_uniform_offset as usize + primitive_uniforms_len)
-                    ..(current_uniform_offset as usize
-                        + material_uniforms.len()
-                        + primitive_uniforms_len as usize)]
-                    .copy_from_slice(material_uniforms.as_slice());
+            if let Some((layer_pipeline, composite_pipeline)) = layer_pipelines[batch[0]] {
+                let window = layer_windows[batch[0]]
+                    .expect("Window of a layer is missing. This should not happen.");
+                let texture = layers.len() + 1;
+
+                // the geom is drawn into the layer with a copy of its uniforms, whose transform
+                // maps the window of the layer to the texture
+                uniforms.resize(uniforms.len().next_multiple_of(offset_alignment), 0);
+                let layer_uniform_offset = uniforms.len();
+                uniforms.extend_from_within(uniform_offset..uniform_offset + stride);
+                let layer_transform = self.layer_transform(
+                    &first_geom.transform.unwrap_or(Transformation::identity()),
+                    texture,
+                    &window,
+                    surface_desc.width,
+                    surface_desc.height,
+                );
+                uniforms[layer_uniform_offset..][..std::mem::size_of::<Transformation>()]
+                    .copy_from_slice(bytemuck::bytes_of(&layer_transform));
+
+                // the layer is rendered with premultiplied alpha, and blended with the render
+                // target using the blend mode of the geom
+                let layer_draw = DrawCall {
+                    pipeline: layer_pipeline,
+                    uniform_offset: layer_uniform_offset as u32,
+                    ..draw
+                };
+                layers.push(self.prepare_filter_layer(
+                    device,
+                    queue,
+                    &mut filter_pass_layout,
+                    layer_draw,
+                    uniform_offset as u32,
+                    texture,
+                    &window,
+                    spatial_passes,
+                    composite_pipeline,
+                    &mut filters,
+                ));
 
-                // add the offset to the list
-                uniform_buffer_offsets.push(current_uniform_offset + current_uniform_length as u32);
+                draw = DrawCall {
+                    pipeline: pipeline_keys[batch[0]],
+                    indices: 0..0,
+                    instances: 0..0,
+                    uniform_offset: uniform_offset as u32,
+                    texture_bind_group: None,
+                    layer: Some(layers.len() - 1),
+                };
             }
-        }
-
-        // handle textures
 
-        for geom in geoms {
-            // if the material has a texture, we need to add the texture to the renderer
-            if let Some(texture) = geom.material.texture() {
-                self.add_texture(&device, queue, texture);
+            draws.push(draw);
+        }
 
-                let material = self
-                    .materials
-                    .get(&geom.material.material_type())
-                    .expect("Material not found");
+        // make sure everything fits into the buffers (including the binding of the last batch)
+        let vertex_bytes = std::mem::size_of_val(draw_buffer_collector.vertices.as_slice()) as u64;
+        let index_bytes = std::mem::size_of_val(draw_buffer_collector.indices.as_slice()) as u64;
+        let instance_bytes = std::mem::size_of_val(instances.as_slice()) as u64;
+        let filter_bytes = std::mem::size_of_val(filters.as_slice()) as u64;
+        let uniform_bytes = (uniforms.len() + binding_size) as u64;
+        let replaced_bound_buffers = self.reserve_buffers(
+            device,
+            vertex_bytes,
+            index_bytes,
+            instance_bytes,
+            filter_bytes,
+            uniform_bytes,
+        );
+        self.used_buffer_sizes = (
+            vertex_bytes,
+            index_bytes,
+            instance_bytes,
+            filter_bytes,
+            uniform_bytes,
+        );
 
-                texture_bind_groups.push(Some(
-                    self.get_texture_bind_group(&device, &texture, &material),
-                ));
-            } else {
-                texture_bind_groups.push(None);
-            }
+        // the bind group refers to the uniform and filter buffers, and the binding size depends
+        // on the largest batch, so it only changes if one of them does
+        if replaced_bound_buffers || self.uniform_binding_size != binding_size as u64 {
+            self.uniform_binding_size = binding_size as u64;
+            self.bind_group = create_bind_group(
+                device,
+                &self.bind_group_layout,
+                &self.screen_buffer,
+                &self.uniform_buffer,
+                self.uniform_binding_size,
+                &self.filter_buffer,
+            );
         }
 
+        // Write the uniform buffer data.
+        queue.write_buffer(&self.uniform_buffer, 0, &uniforms);
+
         // Write the vertex buffer data.
         queue.write_buffer(
             &self.vertex_buffer,
@@ -543,6 +1120,12 @@ impl Renderer {
             bytemuck::cast_slice(&draw_buffer_collector.vertices),
         );
 
+        // Write the filter buffer data.
+        queue.write_buffer(&self.filter_buffer, 0, bytemuck::cast_slice(&filters));
+
+        // Write the instance buffer data.
+        queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&instances));
+
         // Write the index buffer data.
         queue.write_buffer(
             &self.index_buffer,
@@ -550,60 +1133,306 @@ impl Renderer {
             bytemuck::cast_slice(&draw_buffer_collector.indices),
         );
 
-        RenderData {
-            index_buffer_offsets: draw_buffer_collector.indices_offsets,
-            index_buffer_sizes: draw_buffer_collector.indices_sizes,
-            uniform_buffer_offsets: uniform_buffer_offsets,
-            texture_bind_groups: texture_bind_groups,
+        Ok(RenderData {
+            draws,
+            layers,
+            errors,
+        })
+    }
+
+    /// Adds the pipelines and texture of a geom, splits its filters and tessellates it (unless
+    /// its tessellation is cached). Everything the geom needs to be drawn is checked here, so
+    /// that a geom that cannot be drawn is skipped before it is added to a batch.
+    fn prepare_geom<'a>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        surface_desc: &wgpu::SurfaceConfiguration,
+        geom: &'a Geom,
+    ) -> Result<PreparedGeom<'a>, RendererError> {
+        let pipeline =
+            self.add_material(device, &geom.material, geom.blend_mode, surface_desc.format)?;
+
+        match (geom.material.texture(), geom.material.sampler_key()) {
+            (Some(texture), Some(sampler_key)) => {
+                self.add_texture(device, queue, texture)?;
+                self.texture_binding_resources(texture, &sampler_key)?;
+            }
+            (Some(_), None) => return Err(RendererError::MissingTextureBinding),
+            _ => {}
+        }
+
+        let filters = filter::split_filters(&geom.filters)?;
+
+        // geoms with spatial filters are rendered into a layer with premultiplied alpha, which is
+        // blended with the render target using the blend mode of the geom
+        let layer_pipelines = if filters.1.is_empty() {
+            None
+        } else {
+            let layer_pipeline =
+                self.add_material(device, &geom.material, BlendMode::Alpha, LAYER_FORMAT)?;
+            let composite_pipeline = self.add_filter_layer_pipelines(
+                device,
+                geom.blend_mode.blend_state(),
+                surface_desc.format,
+            )?;
+            Some((layer_pipeline, composite_pipeline))
+        };
+
+        let transform = geom.transform.unwrap_or(Transformation::identity());
+
+        // instances may scale the primitive, so use the largest scale of all instances
+        let instance_scale = geom.instances.as_ref().map_or(1.0, |instances| {
+            instances
+                .iter()
+                .map(|instance| instance.transform.scale())
+                .fold(0.0, f32::max)
+        });
+        let pixel_scale =
+            transform.pixel_scale(surface_desc.width, surface_desc.height) * instance_scale;
+        let key = TessellationKey {
+            geom,
+            tolerance: geom.options.tolerance().resolve(pixel_scale),
+        };
+
+        // only tessellate geoms that are new or have changed
+        if self.tesselation_cache.get_sweep(&key).is_none() {
+            let mut buffer = GPUGeometryBuffer::new();
+            buffer.tesselate(&geom.primitive, &geom.options, pixel_scale)?;
+            self.tesselation_cache
+                .insert(&key, (buffer.vertices, buffer.indices));
+        }
+
+        Ok(PreparedGeom {
+            pipeline,
+            layer_pipelines,
+            tessellation: key,
+            filters,
+        })
+    }
+
+    /// Groups the geoms into batches that can be drawn with a single draw call. Returns the
+    /// indices of the geoms in each batch, in drawing order.
+    fn batch(
+        &self,
+        geoms: &[&Geom],
+        keys: &[TessellationKey],
+        pipeline_keys: &[PipelineKey],
+        surface_desc: &wgpu::SurfaceConfiguration,
+    ) -> Vec<Vec<usize>> {
+        // geoms can only be drawn together if they use the same pipeline, texture and sampler,
+        // and instanced geoms and geoms with spatial filters are never drawn together with other
+        // geoms
+        let batch_key = |i: usize| {
+            let geom = &geoms[i];
+            (
+                pipeline_keys[i],
+                geom.material.texture().map(|t| t.cache_id().id()),
+                geom.material.sampler_key(),
+                (geom.instances.is_some() || geom.has_spatial_filters()).then_some(i),
+            )
+        };
+
+        let mut batches: Vec<Vec<usize>> = vec![];
+
+        match self.batching {
+            Batching::None => {
+                batches.extend((0..geoms.len()).map(|i| vec![i]));
+            }
+            Batching::Consecutive => {
+                for i in 0..geoms.len() {
+                    match batches.last_mut() {
+                        Some(batch) if batch_key(batch[0]) == batch_key(i) => batch.push(i),
+                        _ => batches.push(vec![i]),
+                    }
+                }
+            }
+            Batching::Reorder => {
+                // the on-screen bounds of every geom and the union of the bounds of each batch
+                let bounds: Vec<BBox> = geoms
+                    .iter()
+                    .zip(keys)
+                    .map(|(geom, key)| {
+                        // spatial filters may spread the geom over the whole screen
+                        if geom.has_spatial_filters() {
+                            return BBox {
+                                aa: Point2D::new(-1.0, -1.0),
+                                bb: Point2D::new(1.0, 1.0),
+                            };
+                        }
+
+                        let (vertices, _) = self
+                            .tesselation_cache
+                            .get(key)
+                            .expect("Tesselation not in cache. This should not happen.");
+                        screen_bounds(
+                            vertices,
+                            &geom.transform.unwrap_or(Transformation::identity()),
+                            geom.instances.as_deref(),
+                            surface_desc.width,
+                            surface_desc.height,
+                        )
+                    })
+                    .collect();
+                let mut batch_bounds: Vec<BBox> = vec![];
+
+                for i in 0..geoms.len() {
+                    // a geom can join an earlier batch if it does not overlap any geom that is
+                    // drawn after that batch, so the order of overlapping geoms is preserved
+                    let mut target = None;
+                    for (b, batch) in batches.iter().enumerate().rev() {
+                        if batch_key(batch[0]) == batch_key(i) {
+                            target = Some(b);
+                            break;
+                        }
+                        if bboxes_overlap(&batch_bounds[b], &bounds[i])
+                            && batch
+                                .iter()
+                                .any(|&j| bboxes_overlap(&bounds[j], &bounds[i]))
+                        {
+                            break;
+                        }
+                    }
+
+                    match target {
+                        Some(b) => {
+                            batches[b].push(i);
+                            batch_bounds[b] = union_bboxes(&batch_bounds[b], &bounds[i]);
+                        }
+                        None => {
+                            batches.push(vec![i]);
+                            batch_bounds.push(bounds[i]);
+                        }
+                    }
+                }
+            }
         }
+
+        batches
     }
 
-    /// Render.
+    /// Render. Geoms with spatial filters must have been rendered into their layers with
+    /// `render_filter_layers` before.
     pub fn render<'rpass>(
         &'rpass self,
         rpass: &mut wgpu::RenderPass<'rpass>,
-        _device: &wgpu::Device,
-        primitives: &'rpass [Geom],
         rdata: &'rpass RenderData,
-    ) {
+    ) -> Result<(), RendererError> {
         // Set the global vertex and index buffers.
         rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rpass.set_vertex_buffer(1, self.instance_buffer.slice(..));
         rpass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
 
-        let mut last_material = None;
-
-        for (i, primitive) in primitives.iter().enumerate() {
-            let material = self
-                .materials
-                .get(&primitive.material.material_type())
-                .expect("Material not found");
+        let mut last_pipeline = None;
+
+        for draw in &rdata.draws {
+            // geoms with spatial filters are blended from their layer
+            if let Some(layer) = draw.layer {
+                let layer = &rdata.layers[layer];
+                let pipeline = self
+                    .filter_pipelines
+                    .get(&layer.composite_pipeline)
+                    .ok_or(RendererError::MissingPipeline)?;
+                rpass.set_pipeline(pipeline);
+                rpass.set_bind_group(0, &self.bind_group, &[draw.uniform_offset]);
+                rpass.set_bind_group(1, &layer.composite_bind_group, &[]);
+                rpass.draw(0..3, 0..1);
+                last_pipeline = None;
+                continue;
+            }
 
-            if last_material != Some(primitive.material.material_type()) {
+            if last_pipeline != Some(draw.pipeline) {
                 // Set the pipeline.
-                rpass.set_pipeline(&material.pipeline);
-                last_material = Some(primitive.material.material_type());
+                let pipeline = self
+                    .pipelines
+                    .get(&draw.pipeline)
+                    .ok_or(RendererError::MissingPipeline)?;
+                rpass.set_pipeline(pipeline);
+                last_pipeline = Some(draw.pipeline);
             }
 
-            let uniform_offset = rdata.uniform_buffer_offsets[i];
-            rpass.set_bind_group(0, &self.bind_group, &[0, uniform_offset as u32]);
+            rpass.set_bind_group(0, &self.bind_group, &[draw.uniform_offset]);
 
             // if the material has a texture, we need to bind the extra bind group
-            if let Some(..) = primitive.material.texture() {
-                let texture_bind_group = rdata.texture_bind_groups[i]
-                    .as_ref()
-                    .expect("Texture bind group not found");
-                rpass.set_bind_group(1, &texture_bind_group, &[]);
+            if let Some(texture_bind_group) = &draw.texture_bind_group {
+                rpass.set_bind_group(1, texture_bind_group, &[]);
             }
 
             // Draw
-            let index_buffer_offset = rdata.index_buffer_offsets[i];
-            let index_buffer_size = rdata.index_buffer_sizes[i];
+            rpass.draw_indexed(draw.indices.clone(), 0, draw.instances.clone());
+        }
 
-            rpass.draw_indexed(
-                index_buffer_offset..(index_buffer_offset + index_buffer_size as u32),
-                0,
-                0..1,
-            );
+        Ok(())
+    }
+}
+
+/// Returns the bounds of the given vertices in clip space, using the same mapping as the vertex
+/// shader. If `instances` is given, the bounds contain all instances.
+fn screen_bounds(
+    vertices: &[GPUVertex],
+    transform: &Transformation,
+    instances: Option<&[Instance]>,
+    width: u32,
+    height: u32,
+) -> BBox {
+    let mut bounds = BBox {
+        aa: Point2D::new(f32::INFINITY, f32::INFINITY),
+        bb: Point2D::new(f32::NEG_INFINITY, f32::NEG_INFINITY),
+    };
+    for vertex in vertices {
+        bounds.aa.x = bounds.aa.x.min(vertex.position.x);
+        bounds.aa.y = bounds.aa.y.min(vertex.position.y);
+        bounds.bb.x = bounds.bb.x.max(vertex.position.x);
+        bounds.bb.y = bounds.bb.y.max(vertex.position.y);
+    }
+
+    if vertices.is_empty() {
+        return bounds;
+    }
+
+    // the transforms are affine, so the bounds of the transformed corners contain all vertices
+    let corners = [
+        Point2D::new(bounds.aa.x, bounds.aa.y),
+        Point2D::new(bounds.bb.x, bounds.aa.y),
+        Point2D::new(bounds.bb.x, bounds.bb.y),
+        Point2D::new(bounds.aa.x, bounds.bb.y),
+    ];
+    let instance_transforms = match instances {
+        Some(instances) => instances
+            .iter()
+            .map(|instance| instance.transform)
+            .collect(),
+        None => vec![Transformation::identity()],
+    };
+
+    let mut screen_bounds = BBox {
+        aa: Point2D::new(f32::INFINITY, f32::INFINITY),
+        bb: Point2D::new(f32::NEG_INFINITY, f32::NEG_INFINITY),
+    };
+    for instance_transform in instance_transforms {
+        for corner in corners {
+            let p = instance_transform.transform_point(corner);
+            let p = transform.transform_point(Point2D::new(
+                -2.0 * p.x / width as f32,
+                -2.0 * p.y / height as f32,
+            ));
+            screen_bounds.aa.x = screen_bounds.aa.x.min(p.x);
+            screen_bounds.aa.y = screen_bounds.aa.y.min(p.y);
+            screen_bounds.bb.x = screen_bounds.bb.x.max(p.x);
+            screen_bounds.bb.y = screen_bounds.bb.y.max(p.y);
         }
     }
+
+    screen_bounds
+}
+
+fn bboxes_overlap(a: &BBox, b: &BBox) -> bool {
+    a.aa.x <= b.bb.x && b.aa.x <= a.bb.x && a.aa.y <= b.bb.y && b.aa.y <= a.bb.y
+}
+
+fn union_bboxes(a: &BBox, b: &BBox) -> BBox {
+    BBox {
+        aa: Point2D::new(a.aa.x.min(b.aa.x), a.aa.y.min(b.aa.y)),
+        bb: Point2D::new(a.bb.x.max(b.bb.x), a.bb.y.max(b.bb.y)),
+    }
 }
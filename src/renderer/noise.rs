@@ -0,0 +1,412 @@
+use image::Rgba32FImage;
+use nalgebra::Complex;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use super::texture::{Texture, TextureFormat};
+
+/// The kind of noise.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NoiseKind {
+    /// White noise with a standard normal distribution.
+    Gaussian,
+    /// White noise with a uniform distribution between -1 and 1.
+    Uniform,
+    /// White noise that is either -1 or 1.
+    Binary,
+    /// Gaussian noise whose power spectrum falls off with `1 / f^alpha`, normalised to a standard
+    /// deviation of 1. An `alpha` of 0 is white, 1 is pink and 2 is brown noise.
+    Spectral { alpha: f32 },
+    /// Perlin gradient noise with the given size of the lattice cells in grains, scaled to values
+    /// between -1 and 1. The cell size is rounded up to a power of two, so that the noise tiles.
+    Perlin { cell_size: u32 },
+}
+
+/// Whether the channels of the noise are independent.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum NoiseColour {
+    /// The same noise in all channels, i.e. only the luminance varies.
+    #[default]
+    Luminance,
+    /// Independent noise in the red, green and blue channels.
+    Colour,
+}
+
+/// The largest width and height of generated noise. The noise is uploaded as an `Rgba32F`
+/// texture, which at this size just fits the default limits of wgpu (256 MiB per buffer, and
+/// 8192 pixels per side).
+pub const MAX_NOISE_SIZE: u32 = 4096;
+
+/// Generates seeded noise textures. The noise tiles seamlessly, so it can be repeated over larger
+/// shapes.
+///
+/// The same generator always produces the same noise: the random numbers are drawn from ChaCha8
+/// (`rand_chacha`), whose output for a given seed is fixed by its specification and does not
+/// depend on the platform, and are converted to floating point values explicitly. Only functions
+/// like `ln` and `cos` may round differently on other platforms, which can change the last bits
+/// of some values.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NoiseGenerator {
+    /// The kind of noise.
+    pub kind: NoiseKind,
+    /// Whether the channels of the noise are independent.
+    pub colour: NoiseColour,
+    /// The width and height of the noise in grains. This is rounded up to a power of two, and is
+    /// at most `MAX_NOISE_SIZE`.
+    pub size: u32,
+    /// The seed of the random number generator.
+    pub seed: u64,
+}
+
+impl NoiseGenerator {
+    /// Creates a generator for luminance noise of the given kind and size.
+    pub fn new(kind: NoiseKind, size: u32, seed: u64) -> Self {
+        Self {
+            kind,
+            colour: NoiseColour::Luminance,
+            size,
+            seed,
+        }
+    }
+
+    /// Returns the width and height of the generated noise.
+    pub fn resolution(&self) -> u32 {
+        self.size.clamp(1, MAX_NOISE_SIZE).next_power_of_two()
+    }
+
+    /// Generates the noise with one pixel per grain. The noise is stored in the RGB channels as
+    /// floating point values around 0, and the alpha channel is 1.
+    pub fn image(&self) -> Rgba32FImage {
+        let size = self.resolution();
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+
+        let channels: Vec<Vec<f32>> = match self.colour {
+            NoiseColour::Luminance => vec![self.channel(&mut rng, size); 3],
+            NoiseColour::Colour => (0..3).map(|_| self.channel(&mut rng, size)).collect(),
+        };
+
+        let pixels = (0..size as usize * size as usize)
+            .flat_map(|i| [channels[0][i], channels[1][i], channels[2][i], 1.0])
+            .collect();
+        Rgba32FImage::from_raw(size, size, pixels)
+            .expect("Noise size does not match its data. This should not happen.")
+    }
+
+    /// Generates the noise as a texture, see `image`.
+    pub fn texture(&self) -> Texture {
+        Texture::from_image(self.image().into(), TextureFormat::Rgba32F)
+    }
+
+    /// Generates one channel of noise, row by row.
+    fn channel(&self, rng: &mut ChaCha8Rng, size: u32) -> Vec<f32> {
+        let n = size as usize * size as usize;
+        match self.kind {
+            NoiseKind::Gaussian => (0..n).map(|_| gaussian(rng)).collect(),
+            NoiseKind::Uniform => (0..n).map(|_| 2.0 * uniform(rng) - 1.0).collect(),
+            NoiseKind::Binary => (0..n)
+                .map(|_| if rng.next_u32() >> 31 == 1 { 1.0 } else { -1.0 })
+                .collect(),
+            NoiseKind::Spectral { alpha } => spectral(rng, size, alpha),
+            NoiseKind::Perlin { cell_size } => perlin(rng, size, cell_size),
+        }
+    }
+}
+
+/// Draws a sample from the uniform distribution between 0 (inclusive) and 1 (exclusive), using
+/// the upper 24 bits of the next random number.
+fn uniform(rng: &mut ChaCha8Rng) -> f32 {
+    (rng.next_u32() >> 8) as f32 / (1 << 24) as f32
+}
+
+/// Draws a sample from the standard normal distribution (Box-Muller transform).
+fn gaussian(rng: &mut ChaCha8Rng) -> f32 {
+    let u = 1.0 - uniform(rng);
+    let v = uniform(rng);
+    (-2.0 * u.ln()).sqrt() * (2.0 * std::f32::consts::PI * v).cos()
+}
+
+/// Filters Gaussian white noise with `1 / f^(alpha / 2)` in the frequency domain, so that its
+/// power spectrum falls off with `1 / f^alpha`. The result is normalised to zero mean and unit
+/// standard deviation.
+fn spectral(rng: &mut ChaCha8Rng, size: u32, alpha: f32) -> Vec<f32> {
+    let size = size as usize;
+    let mut data: Vec<Complex<f32>> = (0..size * size)
+        .map(|_| Complex::new(gaussian(rng), 0.0))
+        .collect();
+
+    fft_2d(&mut data, size, false);
+    for y in 0..size {
+        for x in 0..size {
+            // the frequencies wrap around at the Nyquist frequency
+            let fx = x.min(size - x) as f32;
+            let fy = y.min(size - y) as f32;
+            let f = (fx * fx + fy * fy).sqrt();
+            data[y * size + x] *= if f > 0.0 { f.powf(-alpha / 2.0) } else { 0.0 };
+        }
+    }
+    fft_2d(&mut data, size, true);
+
+    // the filter is symmetric, so the result is real
+    let values: Vec<f32> = data.iter().map(|c| c.re).collect();
+    let mean = values.iter().sum::<f32>() / values.len() as f32;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / values.len() as f32;
+    let std = variance.sqrt().max(f32::EPSILON);
+    values.into_iter().map(|v| (v - mean) / std).collect()
+}
+
+/// The two-dimensional FFT of a square image with a power of two size, row by row.
+fn fft_2d(data: &mut [Complex<f32>], size: usize, inverse: bool) {
+    for row in data.chunks_exact_mut(size) {
+        fft(row, inverse);
+    }
+
+    let mut column = vec![Complex::new(0.0, 0.0); size];
+    for x in 0..size {
+        for y in 0..size {
+            column[y] = data[y * size + x];
+        }
+        fft(&mut column, inverse);
+        for y in 0..size {
+            data[y * size + x] = column[y];
+        }
+    }
+}
+
+/// An in-place radix-2 FFT. The inverse transform is scaled by `1 / n`.
+fn fft(data: &mut [Complex<f32>], inverse: bool) {
+    let n = data.len();
+
+    // bit reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let angle = sign * 2.0 * std::f64::consts::PI / len as f64;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f64).sin_cos();
+                let w = Complex::new(cos as f32, sin as f32);
+                let a = data[start + k];
+                let b = data[start + k + len / 2] * w;
+                data[start + k] = a + b;
+                data[start + k + len / 2] = a - b;
+            }
+        }
+        len <<= 1;
+    }
+
+    if inverse {
+        for value in data.iter_mut() {
+            *value /= n as f32;
+        }
+    }
+}
+
+/// Perlin gradient noise on a lattice that wraps around, so that the noise tiles seamlessly.
+fn perlin(rng: &mut ChaCha8Rng, size: u32, cell_size: u32) -> Vec<f32> {
+    let cell_size = cell_size.max(1).next_power_of_two().min(size);
+    let cells = (size / cell_size) as usize;
+
+    // a random unit gradient at every lattice point
+    let gradients: Vec<(f32, f32)> = (0..cells * cells)
+        .map(|_| {
+            let angle = uniform(rng) * std::f32::consts::TAU;
+            (angle.cos(), angle.sin())
+        })
+        .collect();
+    let gradient = |x: usize, y: usize| gradients[(y % cells) * cells + x % cells];
+    let fade = |t: f32| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+
+    let mut values = Vec::with_capacity(size as usize * size as usize);
+    for y in 0..size {
+        for x in 0..size {
+            // the position of the centre of the grain in cells
+            let px = (x as f32 + 0.5) / cell_size as f32;
+            let py = (y as f32 + 0.5) / cell_size as f32;
+            let (cx, cy) = (px.floor() as usize, py.floor() as usize);
+            let (fx, fy) = (px.fract(), py.fract());
+
+            let dot = |dx: usize, dy: usize| {
+                let (gx, gy) = gradient(cx + dx, cy + dy);
+                gx * (fx - dx as f32) + gy * (fy - dy as f32)
+            };
+            let top = lerp(dot(0, 0), dot(1, 0), fade(fx));
+            let bottom = lerp(dot(0, 1), dot(1, 1), fade(fx));
+
+            // the range of 2D Perlin noise is [-sqrt(2) / 2, sqrt(2) / 2]
+            values.push(lerp(top, bottom, fade(fy)) * std::f32::consts::SQRT_2);
+        }
+    }
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noise_is_reproducible() {
+        // binary noise does not depend on floating point functions, so its values are fixed
+        let generator = NoiseGenerator::new(NoiseKind::Binary, 4, 1);
+        let values: Vec<f32> = generator.image().pixels().map(|pixel| pixel[0]).collect();
+        assert_eq!(
+            values,
+            [
+                1.0, -1.0, 1.0, -1.0, -1.0, 1.0, -1.0, -1.0, 1.0, -1.0, 1.0, 1.0, -1.0, -1.0, 1.0,
+                -1.0
+            ]
+        );
+
+        // sizes are bounded, so they cannot overflow, and the largest noise can be uploaded
+        let generator = NoiseGenerator::new(NoiseKind::Uniform, u32::MAX, 1);
+        assert_eq!(generator.resolution(), MAX_NOISE_SIZE);
+        let limits = wgpu::Limits::default();
+        assert!(MAX_NOISE_SIZE <= limits.max_texture_dimension_2d);
+        let bytes = (MAX_NOISE_SIZE as u64).pow(2) * std::mem::size_of::<[f32; 4]>() as u64;
+        assert!(bytes <= limits.max_buffer_size);
+    }
+
+    #[test]
+    fn noise_statistics() {
+        // the statistics of the generated noise
+        let values = |generator: &NoiseGenerator| -> Vec<f32> {
+            generator.image().pixels().map(|pixel| pixel[0]).collect()
+        };
+        let mean = |values: &[f32]| values.iter().sum::<f32>() / values.len() as f32;
+        let std = |values: &[f32]| {
+            let mean = mean(values);
+            (values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / values.len() as f32).sqrt()
+        };
+        // the mean absolute difference between horizontally neighbouring values
+        let roughness = |values: &[f32]| {
+            let differences = values
+                .chunks(64)
+                .flat_map(|row| row.windows(2).map(|w| (w[1] - w[0]).abs()));
+            differences.sum::<f32>() / (63.0 * 64.0)
+        };
+
+        let gaussian = values(&NoiseGenerator::new(NoiseKind::Gaussian, 64, 1));
+        assert!(mean(&gaussian).abs() < 0.1 && (std(&gaussian) - 1.0).abs() < 0.1);
+        let uniform = values(&NoiseGenerator::new(NoiseKind::Uniform, 64, 1));
+        assert!(uniform.iter().all(|v| (-1.0..=1.0).contains(v)));
+        assert!(mean(&uniform).abs() < 0.1 && (std(&uniform) - 1.0 / 3f32.sqrt()).abs() < 0.05);
+        let binary = values(&NoiseGenerator::new(NoiseKind::Binary, 64, 1));
+        assert!(binary.iter().all(|v| v.abs() == 1.0) && mean(&binary).abs() < 0.1);
+        let perlin = values(&NoiseGenerator::new(
+            NoiseKind::Perlin { cell_size: 16 },
+            64,
+            1,
+        ));
+        assert!(perlin.iter().all(|v| (-1.0..=1.0).contains(v)));
+
+        // coloured noise is normalised, and gets smoother with increasing alpha
+        let spectral =
+            |alpha: f32| values(&NoiseGenerator::new(NoiseKind::Spectral { alpha }, 64, 1));
+        let (white, pink, brown) = (spectral(0.0), spectral(1.0), spectral(2.0));
+        for values in [&white, &pink, &brown] {
+            assert!(mean(values).abs() < 1e-3 && (std(values) - 1.0).abs() < 1e-3);
+        }
+        assert!(roughness(&white) > roughness(&pink) && roughness(&pink) > roughness(&brown));
+        assert!(roughness(&perlin) < roughness(&brown));
+
+        // the size is rounded up to a power of two
+        assert_eq!(
+            NoiseGenerator::new(NoiseKind::Gaussian, 40, 1)
+                .image()
+                .dimensions(),
+            (64, 64)
+        );
+
+        // luminance noise is the same in all channels, colour noise is not
+        let mut generator = NoiseGenerator::new(NoiseKind::Gaussian, 16, 7);
+        assert!(generator
+            .image()
+            .pixels()
+            .all(|p| p[0] == p[1] && p[1] == p[2]));
+        generator.colour = NoiseColour::Colour;
+        assert!(generator.image().pixels().any(|p| p[0] != p[1]));
+    }
+
+    #[test]
+    fn fft_round_trip() {
+        let data: Vec<Complex<f32>> = (0..16)
+            .map(|i| Complex::new((i as f32 * 0.7).sin(), (i as f32 * 0.3).cos()))
+            .collect();
+
+        // the transform of a constant is a peak at the zero frequency
+        let mut constant = vec![Complex::new(1.0, 0.0); 16];
+        fft(&mut constant, false);
+        assert!((constant[0].re - 16.0).abs() < 1e-4);
+        assert!(constant[1..].iter().all(|c| c.norm_sqr() < 1e-8));
+
+        // the inverse transform restores the data, also in two dimensions
+        let mut transformed = data.clone();
+        fft(&mut transformed, false);
+        fft(&mut transformed, true);
+        for (a, b) in transformed.iter().zip(&data) {
+            assert!((a - b).norm_sqr() < 1e-10, "{a} != {b}");
+        }
+        let mut transformed = data.clone();
+        fft_2d(&mut transformed, 4, false);
+        fft_2d(&mut transformed, 4, true);
+        for (a, b) in transformed.iter().zip(&data) {
+            assert!((a - b).norm_sqr() < 1e-10, "{a} != {b}");
+        }
+    }
+
+    #[test]
+    fn perlin_noise_tiles() {
+        let size = 32;
+        let values = perlin(&mut ChaCha8Rng::seed_from_u64(1), size, 8);
+        let size = size as usize;
+        assert!(values.iter().all(|v| (-1.0..=1.0).contains(v)));
+
+        // wrapping around the edges is as smooth as moving between any other neighbours
+        let value = |x: usize, y: usize| values[(y % size) * size + x % size];
+        let max_step = |columns: std::ops::Range<usize>| {
+            columns
+                .flat_map(|x| (0..size).map(move |y| (x, y)))
+                .map(|(x, y)| {
+                    let horizontal = (value(x + 1, y) - value(x, y)).abs();
+                    let vertical = (value(y, x + 1) - value(y, x)).abs();
+                    horizontal.max(vertical)
+                })
+                .fold(0.0, f32::max)
+        };
+        assert!(max_step(size - 1..size) <= max_step(0..size - 1));
+
+        // the cell size is rounded up to a power of two and is at most the size
+        assert_eq!(perlin(&mut ChaCha8Rng::seed_from_u64(1), 32, 6), values);
+        assert!(perlin(&mut ChaCha8Rng::seed_from_u64(1), 4, 64)
+            .iter()
+            .all(|v| v.is_finite()));
+    }
+
+    #[test]
+    fn seeds_determine_the_noise() {
+        for kind in [
+            NoiseKind::Gaussian,
+            NoiseKind::Uniform,
+            NoiseKind::Binary,
+            NoiseKind::Spectral { alpha: 1.0 },
+            NoiseKind::Perlin { cell_size: 4 },
+        ] {
+            let image = NoiseGenerator::new(kind, 16, 3).image();
+            assert_eq!(image, NoiseGenerator::new(kind, 16, 3).image(), "{kind:?}");
+            assert_ne!(image, NoiseGenerator::new(kind, 16, 4).image(), "{kind:?}");
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use std::path::Path;
+
+use image::{Rgba32FImage, RgbaImage};
+
+use super::error::RendererError;
+use super::geometry::Geom;
+use super::material::Colour;
+use super::{Renderer, TARGET_FORMAT};
+
+/// Number of bytes per pixel of the render target (4 x f16).
+const TARGET_BYTES_PER_PIXEL: u32 = 8;
+
+/// Requests a device and queue that are not tied to any surface. If `force_fallback_adapter` is
+/// true, only a software adapter (e.g. llvmpipe, WARP) is considered. Returns `None` if no
+/// suitable adapter is available.
+pub fn headless_device(force_fallback_adapter: bool) -> Option<(wgpu::Device, wgpu::Queue)> {
+    let instance = wgpu::Instance::default();
+
+    pollster::block_on(async {
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::LowPower,
+                compatible_surface: None,
+                force_fallback_adapter,
+            })
+            .await?;
+
+        // software and downlevel adapters do not necessarily support the default limits
+        let required_limits = wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits());
+
+        // textures that do not repeat need border colours, which not all adapters support
+        let required_features = adapter.features() & wgpu::Features::ADDRESS_MODE_CLAMP_TO_BORDER;
+
+        adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: Some("Headless Device"),
+                    required_features,
+                    required_limits,
+                },
+                None,
+            )
+            .await
+            .ok()
+    })
+}
+
+impl Renderer {
+    /// Renders the given geoms into an offscreen texture of the given size and reads the result
+    /// back. The returned image contains the values of the render target as written by the
+    /// shaders, without any colour space conversion. If a geom cannot be prepared, no image is
+    /// rendered and `RendererError::InvalidGeom` is returned.
+    pub fn render_to_image(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        width: u32,
+        height: u32,
+        geoms: &[Geom],
+        clear: Colour,
+    ) -> Result<Rgba32FImage, RendererError> {
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+
+        // create the render target
+        let target = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen Target"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: TARGET_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        let target_view = target.create_view(&wgpu::TextureViewDescriptor::default());
+
+        // rows in the readback buffer must be aligned to COPY_BYTES_PER_ROW_ALIGNMENT
+        let unpadded_bytes_per_row = width * TARGET_BYTES_PER_PIXEL;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(align) * align;
+
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Offscreen Readback Buffer"),
+            size: (padded_bytes_per_row * height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        // the renderer only reads the size from the surface configuration
+        let surface_desc = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: TARGET_FORMAT,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+            desired_maximum_frame_latency: 1,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            view_formats: vec![],
+        };
+
+        let mut rd = self.prepare(device, queue, &surface_desc, geoms)?;
+
+        // an image without one of the geoms is not what was asked for
+        if let Some((index, error)) = rd.errors.drain(..).next() {
+            return Err(RendererError::InvalidGeom {
+                index,
+                source: Box::new(error),
+            });
+        }
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Offscreen Encoder"),
+        });
+
+        self.render_filter_layers(&mut encoder, &rd)?;
+
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Offscreen Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &target_view,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color {
+                            r: clear.r as f64,
+                            g: clear.g as f64,
+                            b: clear.b as f64,
+                            a: clear.a as f64,
+                        }),
+                        store: wgpu::StoreOp::Store,
+                    },
+                    resolve_target: None,
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            self.render(&mut pass, &rd)?;
+        }
+
+        // copy the render target into the readback buffer
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &target,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &readback_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            size,
+        );
+
+        queue.submit(std::iter::once(encoder.finish()));
+
+        // map the buffer and wait for the GPU to finish
+        let slice = readback_buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            sender.send(result).ok();
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .map_err(|e| RendererError::Gpu(e.to_string()))?
+            .map_err(|e| RendererError::Gpu(e.to_string()))?;
+
+        // convert the padded f16 rows into a f32 image
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        {
+            let data = slice.get_mapped_range();
+            for row in data.chunks_exact(padded_bytes_per_row as usize) {
+                pixels.extend(
+                    row[..unpadded_bytes_per_row as usize]
+                        .chunks_exact(2)
+                        .map(|v| half::f16::from_le_bytes([v[0], v[1]]).to_f32()),
+                );
+            }
+        }
+        readback_buffer.unmap();
+
+        Ok(Rgba32FImage::from_raw(width, height, pixels)
+            .expect("Readback size does not match image size. This should not happen."))
+    }
+
+    /// Like `render_to_image`, but quantises the result to 8 bits per channel. Values are clamped
+    /// to the range [0, 1].
+    pub fn render_to_rgba8_image(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        width: u32,
+        height: u32,
+        geoms: &[Geom],
+        clear: Colour,
+    ) -> Result<RgbaImage, RendererError> {
+        let image = self.render_to_image(device, queue, width, height, geoms, clear)?;
+        Ok(image::DynamicImage::ImageRgba32F(image).into_rgba8())
+    }
+
+    /// Renders the given geoms offscreen and saves the 8-bit result as a PNG file.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_to_png(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        width: u32,
+        height: u32,
+        geoms: &[Geom],
+        clear: Colour,
+        path: impl AsRef<Path>,
+    ) -> Result<(), RendererError> {
+        self.render_to_rgba8_image(device, queue, width, height, geoms, clear)?
+            .save_with_format(path, image::ImageFormat::Png)?;
+        Ok(())
+    }
+}
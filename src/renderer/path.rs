@@ -0,0 +1,428 @@
+use std::hash::{Hash, Hasher};
+
+use lyon::extra::parser::{ParserOptions, PathParser, Source};
+use lyon::path::builder::SvgPathBuilder;
+use lyon::path::ArcFlags;
+
+use super::error::RendererError;
+use super::geometry::{BBox, Point2D, Vector2};
+
+/// A single command of a path.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathCommand {
+    /// Start a new sub-path at the given point.
+    MoveTo(Point2D),
+    /// A straight line to the given point.
+    LineTo(Point2D),
+    /// A quadratic Bézier curve with one control point.
+    QuadraticTo { ctrl: Point2D, to: Point2D },
+    /// A cubic Bézier curve with two control points.
+    CubicTo {
+        ctrl1: Point2D,
+        ctrl2: Point2D,
+        to: Point2D,
+    },
+    /// An elliptical arc, with the same semantics as the SVG `A` command.
+    ArcTo {
+        /// The radii of the ellipse.
+        radii: Vector2,
+        /// The rotation of the ellipse's x-axis in degrees.
+        x_rotation: f32,
+        /// Whether to take the arc that spans more than 180 degrees.
+        large_arc: bool,
+        /// Whether to draw the arc in the positive-angle direction.
+        sweep: bool,
+        /// The end point of the arc.
+        to: Point2D,
+    },
+    /// Close the current sub-path with a straight line to its start.
+    Close,
+}
+
+impl Hash for PathCommand {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            PathCommand::MoveTo(p) | PathCommand::LineTo(p) => {
+                p.x.to_bits().hash(state);
+                p.y.to_bits().hash(state);
+            }
+            PathCommand::QuadraticTo { ctrl, to } => {
+                for p in [ctrl, to] {
+                    p.x.to_bits().hash(state);
+                    p.y.to_bits().hash(state);
+                }
+            }
+            PathCommand::CubicTo { ctrl1, ctrl2, to } => {
+                for p in [ctrl1, ctrl2, to] {
+                    p.x.to_bits().hash(state);
+                    p.y.to_bits().hash(state);
+                }
+            }
+            PathCommand::ArcTo {
+                radii,
+                x_rotation,
+                large_arc,
+                sweep,
+                to,
+            } => {
+                radii.x.to_bits().hash(state);
+                radii.y.to_bits().hash(state);
+                x_rotation.to_bits().hash(state);
+                large_arc.hash(state);
+                sweep.hash(state);
+                to.x.to_bits().hash(state);
+                to.y.to_bits().hash(state);
+            }
+            PathCommand::Close => {}
+        }
+    }
+}
+
+/// A path made of one or more sub-paths of lines, Bézier curves and arcs.
+#[derive(Debug, Clone, PartialEq, Default, Hash)]
+pub struct Path {
+    commands: Vec<PathCommand>,
+}
+
+impl Path {
+    /// Create a new, empty path.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a path from a list of commands.
+    pub fn from_commands(commands: Vec<PathCommand>) -> Self {
+        Self { commands }
+    }
+
+    /// Create a path that connects the given points with straight lines.
+    pub fn polyline(points: &[Point2D], closed: bool) -> Self {
+        let mut path = Self::new();
+        for (i, point) in points.iter().enumerate() {
+            if i == 0 {
+                path.move_to(*point);
+            } else {
+                path.line_to(*point);
+            }
+        }
+        if closed && !points.is_empty() {
+            path.close();
+        }
+        path
+    }
+
+    /// Parse SVG path data (the `d` attribute of a `<path>` element). Arcs are converted to
+    /// Bézier curves by the parser.
+    pub fn from_svg(d: &str) -> Result<Self, RendererError> {
+        let mut builder = lyon::path::Path::builder();
+        PathParser::new()
+            .parse(
+                &ParserOptions::DEFAULT,
+                &mut Source::new(d.chars()),
+                &mut builder,
+            )
+            .map_err(|error| RendererError::InvalidPath(error.to_string()))?;
+
+        let mut path = Self::new();
+        for event in builder.build().iter() {
+            match event {
+                lyon::path::Event::Begin { at } => path.move_to(Point2D::new(at.x, at.y)),
+                lyon::path::Event::Line { to, .. } => path.line_to(Point2D::new(to.x, to.y)),
+                lyon::path::Event::Quadratic { ctrl, to, .. } => {
+                    path.quadratic_bezier_to(Point2D::new(ctrl.x, ctrl.y), Point2D::new(to.x, to.y))
+                }
+                lyon::path::Event::Cubic {
+                    ctrl1, ctrl2, to, ..
+                } => path.cubic_bezier_to(
+                    Point2D::new(ctrl1.x, ctrl1.y),
+                    Point2D::new(ctrl2.x, ctrl2.y),
+                    Point2D::new(to.x, to.y),
+                ),
+                lyon::path::Event::End { close: true, .. } => path.close(),
+                lyon::path::Event::End { close: false, .. } => {}
+            }
+        }
+        Ok(path)
+    }
+
+    /// Create a circular arc around `center`, from `start_angle` to `end_angle` (in degrees). The
+    /// path is left open.
+    pub fn arc(center: Point2D, radius: f32, start_angle: f32, end_angle: f32) -> Self {
+        let mut path = Self::new();
+        path.move_to(point_on_circle(center, radius, start_angle));
+        path.append_arc(center, radius, start_angle, end_angle);
+        path
+    }
+
+    /// Create a circular sector (a pie wedge) around `center`, from `start_angle` to `end_angle`
+    /// (in degrees).
+    pub fn sector(center: Point2D, radius: f32, start_angle: f32, end_angle: f32) -> Self {
+        let mut path = Self::new();
+        if (end_angle - start_angle).abs() >= 360.0 {
+            // a full sector is a circle
+            path.move_to(point_on_circle(center, radius, start_angle));
+        } else {
+            path.move_to(center);
+            path.line_to(point_on_circle(center, radius, start_angle));
+        }
+        path.append_arc(center, radius, start_angle, end_angle);
+        path.close();
+        path
+    }
+
+    /// Create an annulus (ring) segment around `center`, between `inner_radius` and
+    /// `outer_radius` and from `start_angle` to `end_angle` (in degrees).
+    pub fn annulus(
+        center: Point2D,
+        inner_radius: f32,
+        outer_radius: f32,
+        start_angle: f32,
+        end_angle: f32,
+    ) -> Self {
+        let mut path = Self::new();
+        path.move_to(point_on_circle(center, outer_radius, start_angle));
+        path.append_arc(center, outer_radius, start_angle, end_angle);
+        if (end_angle - start_angle).abs() >= 360.0 {
+            // a full ring consists of two circles with opposite winding
+            path.close();
+            path.move_to(point_on_circle(center, inner_radius, end_angle));
+        } else {
+            path.line_to(point_on_circle(center, inner_radius, end_angle));
+        }
+        path.append_arc(center, inner_radius, end_angle, start_angle);
+        path.close();
+        path
+    }
+
+    /// Add a circular arc from `start_angle` to `end_angle` (in degrees). The current point must
+    /// be the start of the arc. The arc is split into pieces of at most 90 degrees so that it can
+    /// be expressed with SVG arc commands.
+    fn append_arc(&mut self, center: Point2D, radius: f32, start_angle: f32, end_angle: f32) {
+        let sweep = (end_angle - start_angle).clamp(-360.0, 360.0);
+        let pieces = (sweep.abs() / 90.0).ceil().max(1.0) as u32;
+        for i in 1..=pieces {
+            let angle = start_angle + sweep * i as f32 / pieces as f32;
+            self.arc_to(
+                Vector2::new(radius, radius),
+                0.0,
+                false,
+                sweep > 0.0,
+                point_on_circle(center, radius, angle),
+            );
+        }
+    }
+
+    /// Start a new sub-path at the given point.
+    pub fn move_to(&mut self, to: Point2D) {
+        self.commands.push(PathCommand::MoveTo(to));
+    }
+
+    /// Add a straight line to the given point.
+    pub fn line_to(&mut self, to: Point2D) {
+        self.commands.push(PathCommand::LineTo(to));
+    }
+
+    /// Add a quadratic Bézier curve to the given point.
+    pub fn quadratic_bezier_to(&mut self, ctrl: Point2D, to: Point2D) {
+        self.commands.push(PathCommand::QuadraticTo { ctrl, to });
+    }
+
+    /// Add a cubic Bézier curve to the given point.
+    pub fn cubic_bezier_to(&mut self, ctrl1: Point2D, ctrl2: Point2D, to: Point2D) {
+        self.commands
+            .push(PathCommand::CubicTo { ctrl1, ctrl2, to });
+    }
+
+    /// Add an elliptical arc to the given point (see `PathCommand::ArcTo`).
+    pub fn arc_to(
+        &mut self,
+        radii: Vector2,
+        x_rotation: f32,
+        large_arc: bool,
+        sweep: bool,
+        to: Point2D,
+    ) {
+        self.commands.push(PathCommand::ArcTo {
+            radii,
+            x_rotation,
+            large_arc,
+            sweep,
+            to,
+        });
+    }
+
+    /// Close the current sub-path.
+    pub fn close(&mut self) {
+        self.commands.push(PathCommand::Close);
+    }
+
+    /// Returns the commands of the path.
+    pub fn commands(&self) -> &[PathCommand] {
+        &self.commands
+    }
+
+    /// Returns true if the path does not contain any commands.
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Returns true if all coordinates and radii of the path are finite.
+    pub fn is_finite(&self) -> bool {
+        self.commands.iter().all(|command| match command {
+            PathCommand::MoveTo(p) | PathCommand::LineTo(p) => p.is_finite(),
+            PathCommand::QuadraticTo { ctrl, to } => ctrl.is_finite() && to.is_finite(),
+            PathCommand::CubicTo { ctrl1, ctrl2, to } => {
+                ctrl1.is_finite() && ctrl2.is_finite() && to.is_finite()
+            }
+            PathCommand::ArcTo {
+                radii,
+                x_rotation,
+                to,
+                ..
+            } => {
+                radii.x.is_finite()
+                    && radii.y.is_finite()
+                    && x_rotation.is_finite()
+                    && to.is_finite()
+            }
+            PathCommand::Close => true,
+        })
+    }
+
+    /// Build the equivalent lyon path.
+    pub fn to_lyon(&self) -> lyon::path::Path {
+        let mut builder = lyon::path::Path::svg_builder();
+        for command in &self.commands {
+            match command {
+                PathCommand::MoveTo(to) => {
+                    builder.move_to(lyon::math::Point::new(to.x, to.y));
+                }
+                PathCommand::LineTo(to) => {
+                    builder.line_to(lyon::math::Point::new(to.x, to.y));
+                }
+                PathCommand::QuadraticTo { ctrl, to } => {
+                    builder.quadratic_bezier_to(
+                        lyon::math::Point::new(ctrl.x, ctrl.y),
+                        lyon::math::Point::new(to.x, to.y),
+                    );
+                }
+                PathCommand::CubicTo { ctrl1, ctrl2, to } => {
+                    builder.cubic_bezier_to(
+                        lyon::math::Point::new(ctrl1.x, ctrl1.y),
+                        lyon::math::Point::new(ctrl2.x, ctrl2.y),
+                        lyon::math::Point::new(to.x, to.y),
+                    );
+                }
+                PathCommand::ArcTo {
+                    radii,
+                    x_rotation,
+                    large_arc,
+                    sweep,
+                    to,
+                } => {
+                    builder.arc_to(
+                        lyon::math::Vector::new(radii.x, radii.y),
+                        lyon::math::Angle::degrees(*x_rotation),
+                        ArcFlags {
+                            large_arc: *large_arc,
+                            sweep: *sweep,
+                        },
+                        lyon::math::Point::new(to.x, to.y),
+                    );
+                }
+                PathCommand::Close => builder.close(),
+            }
+        }
+        builder.build()
+    }
+
+    /// Get the bounding box of the path, including the extent of curves. An empty path has an
+    /// empty bounding box at the origin.
+    pub fn bbox(&self) -> BBox {
+        let bbox = lyon::algorithms::aabb::bounding_box(self.to_lyon().iter());
+        // lyon returns an inverted box if there are no points
+        if bbox.min.x > bbox.max.x || bbox.min.y > bbox.max.y {
+            return BBox {
+                aa: Point2D::new(0.0, 0.0),
+                bb: Point2D::new(0.0, 0.0),
+            };
+        }
+        BBox {
+            aa: Point2D::new(bbox.min.x, bbox.min.y),
+            bb: Point2D::new(bbox.max.x, bbox.max.y),
+        }
+    }
+}
+
+/// Returns the point at `angle` degrees on the circle around `center`.
+fn point_on_circle(center: Point2D, radius: f32, angle: f32) -> Point2D {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    Point2D::new(center.x + radius * cos, center.y + radius * sin)
+}
+
+impl From<Vec<PathCommand>> for Path {
+    fn from(commands: Vec<PathCommand>) -> Self {
+        Self::from_commands(commands)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn svg_path_data() {
+        let path = Path::from_svg("M 0 0 L 10 0 Q 20 0 20 10 C 20 20 10 20 0 20 Z").unwrap();
+        assert_eq!(
+            path.commands(),
+            &[
+                PathCommand::MoveTo(Point2D::new(0.0, 0.0)),
+                PathCommand::LineTo(Point2D::new(10.0, 0.0)),
+                PathCommand::QuadraticTo {
+                    ctrl: Point2D::new(20.0, 0.0),
+                    to: Point2D::new(20.0, 10.0),
+                },
+                PathCommand::CubicTo {
+                    ctrl1: Point2D::new(20.0, 20.0),
+                    ctrl2: Point2D::new(10.0, 20.0),
+                    to: Point2D::new(0.0, 20.0),
+                },
+                PathCommand::Close,
+            ]
+        );
+
+        // relative commands are resolved, and arcs become curves
+        let path = Path::from_svg("m 5 5 h 10 v 10 a 5 5 0 0 1 -10 0 z").unwrap();
+        assert_eq!(
+            path.commands()[1],
+            PathCommand::LineTo(Point2D::new(15.0, 5.0))
+        );
+        assert_eq!(
+            path.commands()[2],
+            PathCommand::LineTo(Point2D::new(15.0, 15.0))
+        );
+        assert!(path
+            .commands()
+            .iter()
+            .all(|c| !matches!(c, PathCommand::ArcTo { .. })));
+
+        assert!(matches!(
+            Path::from_svg("M 0 0 L 10 x"),
+            Err(RendererError::InvalidPath(_))
+        ));
+    }
+
+    #[test]
+    fn empty_paths_have_an_empty_bbox() {
+        for path in [Path::new(), Path::from_commands(vec![PathCommand::Close])] {
+            let bbox = path.bbox();
+            assert_eq!(bbox.aa, Point2D::new(0.0, 0.0));
+            assert_eq!(bbox.bb, Point2D::new(0.0, 0.0));
+        }
+
+        let path = Path::polyline(&[Point2D::new(1.0, 2.0), Point2D::new(-3.0, 4.0)], false);
+        assert_eq!(path.bbox().aa, Point2D::new(-3.0, 2.0));
+        assert_eq!(path.bbox().bb, Point2D::new(1.0, 4.0));
+    }
+}
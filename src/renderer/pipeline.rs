@@ -0,0 +1,189 @@
+use super::geometry::BlendMode;
+use super::material::MaterialType;
+
+/// The state that determines a render pipeline. The renderer caches pipelines by this key, so
+/// all geoms with the same key share a pipeline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+    /// The material type, which determines the shader.
+    pub material_type: MaterialType,
+    /// How the material binds its texture, if it has one.
+    pub texture_binding: Option<TextureBinding>,
+    /// How the output of the shader is blended with the render target.
+    pub blend: wgpu::BlendState,
+    /// The format of the render target.
+    pub target_format: wgpu::TextureFormat,
+    /// The number of samples per pixel of the render target.
+    pub sample_count: u32,
+}
+
+/// The state that determines a pipeline of the passes that apply spatial filters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FilterPipelineKey {
+    /// The kind of pass.
+    pub pass: FilterPassKind,
+    /// How the output of the pass is blended with its target.
+    pub blend: wgpu::BlendState,
+    /// The format of the target of the pass.
+    pub target_format: wgpu::TextureFormat,
+    /// The number of samples per pixel of the target of the pass.
+    pub sample_count: u32,
+}
+
+/// The kinds of passes that apply spatial filters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FilterPassKind {
+    /// Convolves an intermediate texture with a kernel and applies the pixel filters that follow.
+    Convolve,
+    /// Blends the filtered intermediate texture with the render target.
+    Composite,
+}
+
+impl FilterPassKind {
+    /// Returns the entry point of the fragment shader of the pass.
+    pub fn entry_point(&self) -> &'static str {
+        match self {
+            Self::Convolve => "fs_convolve",
+            Self::Composite => "fs_composite",
+        }
+    }
+}
+
+/// How a material binds its texture. This is part of the pipeline layout, as textures that are
+/// sampled with a filtering sampler must be filterable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TextureBinding {
+    /// The texture is sampled with a filtering (linear) sampler.
+    Filtering,
+    /// The texture is sampled with a non-filtering (nearest) sampler.
+    NonFiltering,
+}
+
+impl TextureBinding {
+    /// Creates the bind group layout for a texture and its sampler.
+    pub fn create_bind_group_layout(&self, device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let (filterable, sampler_binding_type) = match self {
+            Self::Filtering => (true, wgpu::SamplerBindingType::Filtering),
+            Self::NonFiltering => (false, wgpu::SamplerBindingType::NonFiltering),
+        };
+
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Texture Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(sampler_binding_type),
+                    count: None,
+                },
+            ],
+        })
+    }
+}
+
+/// The state that determines a sampler. The renderer caches samplers by this key, independently
+/// of the pipelines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SamplerKey {
+    /// The address mode in the horizontal direction.
+    pub address_mode_u: wgpu::AddressMode,
+    /// The address mode in the vertical direction.
+    pub address_mode_v: wgpu::AddressMode,
+    /// The filter used for magnification and minification.
+    pub filter: wgpu::FilterMode,
+}
+
+impl SamplerKey {
+    /// Returns the texture binding that is compatible with this sampler.
+    pub fn texture_binding(&self) -> TextureBinding {
+        match self.filter {
+            wgpu::FilterMode::Linear => TextureBinding::Filtering,
+            wgpu::FilterMode::Nearest => TextureBinding::NonFiltering,
+        }
+    }
+
+    /// Returns the features the device needs to create the sampler.
+    pub fn required_features(&self) -> wgpu::Features {
+        let address_modes = [self.address_mode_u, self.address_mode_v];
+        if address_modes.contains(&wgpu::AddressMode::ClampToBorder) {
+            wgpu::Features::ADDRESS_MODE_CLAMP_TO_BORDER
+        } else {
+            wgpu::Features::empty()
+        }
+    }
+
+    /// Creates the sampler.
+    pub fn create_sampler(&self, device: &wgpu::Device) -> wgpu::Sampler {
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Texture Sampler"),
+            address_mode_u: self.address_mode_u,
+            address_mode_v: self.address_mode_v,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: self.filter,
+            min_filter: self.filter,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            lod_min_clamp: 0.0,
+            lod_max_clamp: 100.0,
+            compare: None,
+            anisotropy_clamp: 1,
+            border_color: Some(wgpu::SamplerBorderColor::TransparentBlack),
+        })
+    }
+}
+
+impl BlendMode {
+    /// Returns the blend state of the pipeline.
+    pub fn blend_state(&self) -> wgpu::BlendState {
+        use wgpu::{BlendComponent, BlendFactor, BlendOperation, BlendState};
+
+        let component = |src_factor, dst_factor, operation| BlendComponent {
+            src_factor,
+            dst_factor,
+            operation,
+        };
+
+        // the alpha of the target is composited as usual for all modes but `Replace`
+        let alpha = BlendComponent::OVER;
+
+        match self {
+            Self::Alpha => BlendState::ALPHA_BLENDING,
+            Self::PremultipliedAlpha => BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+            Self::Additive => BlendState {
+                color: component(BlendFactor::SrcAlpha, BlendFactor::One, BlendOperation::Add),
+                alpha,
+            },
+            Self::Subtractive => BlendState {
+                color: component(
+                    BlendFactor::SrcAlpha,
+                    BlendFactor::One,
+                    BlendOperation::ReverseSubtract,
+                ),
+                alpha,
+            },
+            Self::Multiply => BlendState {
+                color: component(BlendFactor::Dst, BlendFactor::Zero, BlendOperation::Add),
+                alpha,
+            },
+            // min and max ignore the blend factors, but wgpu requires them to be one
+            Self::Min => BlendState {
+                color: component(BlendFactor::One, BlendFactor::One, BlendOperation::Min),
+                alpha,
+            },
+            Self::Max => BlendState {
+                color: component(BlendFactor::One, BlendFactor::One, BlendOperation::Max),
+                alpha,
+            },
+            Self::Replace => BlendState::REPLACE,
+        }
+    }
+}
@@ -0,0 +1,39 @@
+// Gratings that are shared by the grating and the Gabor materials.
+
+const PI: f32 = 3.14159265358979323846264338327950288;
+
+struct Grating {
+    mean: vec4<f32>,
+    frequency: f32,
+    frequency_mode: u32, // 0: cycles per pixel, 1: cycles per unit
+    phase: f32, // in radians
+    orientation: f32, // in radians
+    contrast: f32,
+    waveform: u32, // 0: sine, 1: square, 2: sawtooth, 3: triangle
+    _padding: vec2<u32>,
+};
+
+// evaluates a waveform at `t` cycles, with values between -1 and 1
+fn wave(t: f32, waveform: u32) -> f32 {
+    if (waveform == 1u) {
+        // square
+        return select(-1.0, 1.0, fract(t) < 0.5);
+    } else if (waveform == 2u) {
+        // sawtooth
+        return 2.0 * fract(t + 0.5) - 1.0;
+    } else if (waveform == 3u) {
+        // triangle
+        return 4.0 * abs(fract(t - 0.25) - 0.5) - 1.0;
+    }
+    return sin(2.0 * PI * t);
+}
+
+// returns the frequency of the grating in cycles per unit
+fn grating_frequency(grating: Grating, pixel_size: f32) -> f32 {
+    return select(grating.frequency / pixel_size, grating.frequency, grating.frequency_mode == 1u);
+}
+
+// returns the direction in which the grating varies
+fn grating_direction(grating: Grating) -> vec2<f32> {
+    return vec2<f32>(cos(grating.orientation), sin(grating.orientation));
+}
@@ -0,0 +1,29 @@
+struct Uniforms {
+    transform: mat4x4<f32>,
+    bbox: BBox,
+    filters: FilterRange,
+    colours: array<vec4<f32>, 2>,
+    check_size: vec2<f32>,
+    phase: vec2<f32>, // in checks
+    rotation: f32, // in radians
+};
+
+@group(0) @binding(1)
+var<storage, read> geoms: array<Uniforms>;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let uniforms = geoms[in.geom_index];
+
+    // the position in checks, relative to the centre of the bounding box (which is a corner of
+    // four checks)
+    let centre = (uniforms.bbox.min + uniforms.bbox.max) / 2.0;
+    let d = in.position_org - centre;
+    let c = cos(uniforms.rotation);
+    let s = sin(uniforms.rotation);
+    let p = vec2<f32>(c * d.x + s * d.y, -s * d.x + c * d.y);
+    let check = vec2<i32>(floor(p / uniforms.check_size + uniforms.phase));
+
+    let colour = select(uniforms.colours[0], uniforms.colours[1], ((check.x + check.y) & 1) == 1);
+    return apply_filters(colour * in.tint, in);
+}
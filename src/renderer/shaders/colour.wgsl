@@ -1,32 +1,15 @@
-struct VertexOutput {
-    @builtin(position) position_px: vec4<f32>,
-    @location(0) position_org: vec2<f32>,
-    @location(1) tex_coords: vec2<f32>,
-};
-
-struct ScreenUniforms {
-    width: u32,
-    height: u32,
-};
 
-struct BBox {
-    min: vec2<f32>,
-    max: vec2<f32>,
-};
-
-struct ColourUniforms {
-    _transform: mat4x4<f32>,
-    _bbox: BBox,
+struct Uniforms {
+    transform: mat4x4<f32>,
+    bbox: BBox,
+    filters: FilterRange,
     color: vec4<f32>,
 };
 
-@group(0) @binding(0)
-var<uniform> screen_uniforms: ScreenUniforms;
-
 @group(0) @binding(1)
-var<uniform> uniforms: ColourUniforms;
+var<storage, read> geoms: array<Uniforms>;
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4f {
-    return uniforms.color;
+    return apply_filters(geoms[in.geom_index].color * in.tint, in);
 }
@@ -0,0 +1,80 @@
+// Declarations shared by all shaders, including the pixel filters.
+
+struct ScreenUniforms {
+    width: u32,
+    height: u32,
+};
+
+struct BBox {
+    min: vec2<f32>,
+    max: vec2<f32>,
+};
+
+// the range of the filters of a geom in the filter buffer
+struct FilterRange {
+    start: u32,
+    count: u32,
+    _padding: vec2<u32>,
+};
+
+struct Filter {
+    kind: u32, // 0: grayscale, 1: invert, 2: threshold, 3: gaussian envelope, 4: colour matrix, 5: adjust
+    params_0: vec4<f32>,
+    params_1: vec4<f32>,
+    params_2: vec4<f32>,
+    params_3: vec4<f32>,
+    params_4: vec4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> screen_uniforms: ScreenUniforms;
+
+@group(0) @binding(2)
+var<storage, read> filters: array<Filter>;
+
+// applies a range of pixel filters to a colour, in order. `position` is the position of the
+// fragment in the coordinates of the primitive, whose bounding box is `bbox`.
+fn apply_filter_range(colour: vec4<f32>, range: FilterRange, position: vec2<f32>, bbox: BBox) -> vec4<f32> {
+    var out = colour;
+    for (var i = 0u; i < range.count; i++) {
+        let pixel_filter = filters[range.start + i];
+
+        if (pixel_filter.kind == 0u) {
+            // grayscale
+            let grey = (out.r + out.g + out.b) / 3.0;
+            out = vec4<f32>(grey, grey, grey, out.a);
+        } else if (pixel_filter.kind == 1u) {
+            // invert
+            out = vec4<f32>(1.0 - out.rgb, out.a);
+        } else if (pixel_filter.kind == 2u) {
+            // threshold
+            out = vec4<f32>(step(pixel_filter.params_0.rgb, out.rgb), out.a);
+        } else if (pixel_filter.kind == 3u) {
+            // gaussian envelope around the centre of the bounding box
+            let centre = (bbox.min + bbox.max) / 2.0 + pixel_filter.params_0.xy;
+            let sigma = pixel_filter.params_0.zw;
+            let rotation = pixel_filter.params_1.x;
+
+            // rotate the position into the frame of the envelope
+            let d = position - centre;
+            let c = cos(rotation);
+            let s = sin(rotation);
+            let p = vec2<f32>(c * d.x + s * d.y, -s * d.x + c * d.y) / sigma;
+
+            out = vec4<f32>(out.rgb, out.a * exp(-0.5 * dot(p, p)));
+        } else if (pixel_filter.kind == 4u) {
+            // colour matrix, given as its columns and the offset
+            let matrix = mat4x4<f32>(pixel_filter.params_0, pixel_filter.params_1, pixel_filter.params_2, pixel_filter.params_3);
+            out = matrix * out + pixel_filter.params_4;
+        } else if (pixel_filter.kind == 5u) {
+            // brightness, contrast and gamma
+            let brightness = pixel_filter.params_0.x;
+            let contrast = pixel_filter.params_0.y;
+            let gamma = pixel_filter.params_0.z;
+            let adjusted = max((out.rgb - 0.5) * contrast + 0.5 + brightness, vec3<f32>(0.0));
+            out = vec4<f32>(pow(adjusted, vec3<f32>(1.0 / gamma)), out.a);
+        }
+    }
+
+    return out;
+}
@@ -0,0 +1,17 @@
+// The bindings and the entry point of custom materials. The per-geom `Uniforms` struct (with the
+// `Params` of the material, if it has any) is declared before the source of the material, which
+// must define `fn material(in: VertexOutput) -> vec4<f32>`.
+
+@group(0) @binding(1)
+var<storage, read> geoms: array<Uniforms>;
+
+@group(1) @binding(0)
+var texture: texture_2d<f32>;
+
+@group(1) @binding(1)
+var texture_sampler: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return apply_filters(material(in) * in.tint, in);
+}
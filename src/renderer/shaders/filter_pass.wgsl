@@ -0,0 +1,112 @@
+// Passes that apply spatial filters (convolutions) to a geom that has been rendered into an
+// intermediate texture, and that composite the result onto the render target. The intermediate
+// textures contain premultiplied colours, and cover the part of the render target that starts at
+// the origin of the pass.
+
+// the part of the per-geom uniforms that is the same for all materials
+struct GeomUniforms {
+    transform: mat4x4<f32>,
+    bbox: BBox,
+    filters: FilterRange,
+};
+
+struct PassUniforms {
+    // the pixel filters that are applied after the convolution
+    filters: FilterRange,
+    kernel_width: u32,
+    kernel_height: u32,
+    // the position of the intermediate textures on the render target, in pixels
+    origin: vec2<i32>,
+};
+
+@group(0) @binding(1)
+var<storage, read> geoms: array<GeomUniforms>;
+
+@group(1) @binding(0)
+var source: texture_2d<f32>;
+
+@group(1) @binding(1)
+var<uniform> pass_uniforms: PassUniforms;
+
+@group(1) @binding(2)
+var<storage, read> kernel: array<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    // a triangle that covers the whole target
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// loads a pixel of the source, which is transparent outside of the texture and outside of the
+// render target
+fn load(position: vec2<i32>) -> vec4<f32> {
+    let size = vec2<i32>(textureDimensions(source));
+    let screen = vec2<i32>(i32(screen_uniforms.width), i32(screen_uniforms.height));
+    let target_position = position + pass_uniforms.origin;
+    if (any(position < vec2<i32>(0)) || any(position >= size)
+        || any(target_position < vec2<i32>(0)) || any(target_position >= screen)) {
+        return vec4<f32>(0.0);
+    }
+    return textureLoad(source, position, 0);
+}
+
+fn unpremultiply(colour: vec4<f32>) -> vec4<f32> {
+    if (colour.a <= 0.0) {
+        return vec4<f32>(0.0);
+    }
+    return vec4<f32>(colour.rgb / colour.a, colour.a);
+}
+
+// maps a position on the target (in pixels) back to the coordinates of the primitive, i.e. the
+// inverse of the mapping in the vertex shader (instance transforms are not taken into account)
+fn primitive_position(position_px: vec2<f32>) -> vec2<f32> {
+    let size = vec2<f32>(f32(screen_uniforms.width), f32(screen_uniforms.height));
+    let clip = vec2<f32>(position_px.x / size.x * 2.0 - 1.0, 1.0 - position_px.y / size.y * 2.0);
+
+    let transform = geoms[0].transform;
+    let linear = mat2x2<f32>(transform[0].xy, transform[1].xy);
+    let det = determinant(linear);
+    let inverse = mat2x2<f32>(
+        vec2<f32>(linear[1].y, -linear[0].y),
+        vec2<f32>(-linear[1].x, linear[0].x),
+    ) * (1.0 / det);
+
+    return inverse * (clip - transform[2].xy) * size / -2.0;
+}
+
+@fragment
+fn fs_convolve(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let centre = vec2<i32>(position.xy);
+    let width = i32(pass_uniforms.kernel_width);
+    let height = i32(pass_uniforms.kernel_height);
+
+    // the kernel is centred on the pixel, and applied without flipping it
+    var sum = vec4<f32>(0.0);
+    for (var y = 0; y < height; y++) {
+        for (var x = 0; x < width; x++) {
+            let offset = vec2<i32>(x - width / 2, y - height / 2);
+            sum += kernel[y * width + x] * load(centre + offset);
+        }
+    }
+
+    // the pixel filters expect straight colours
+    let colour = apply_filter_range(
+        unpremultiply(sum),
+        pass_uniforms.filters,
+        primitive_position(position.xy + vec2<f32>(pass_uniforms.origin)),
+        geoms[0].bbox,
+    );
+    return vec4<f32>(colour.rgb * colour.a, colour.a);
+}
+
+@fragment
+fn fs_composite(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    // the output is blended with the target like the output of a material, but only where the
+    // layer is covered (which matters for blend modes that ignore the alpha of the source)
+    let colour = load(vec2<i32>(position.xy) - pass_uniforms.origin);
+    if (colour.a <= 0.0) {
+        discard;
+    }
+    return unpremultiply(colour);
+}
@@ -0,0 +1,37 @@
+struct Uniforms {
+    transform: mat4x4<f32>,
+    bbox: BBox,
+    filters: FilterRange,
+    carrier: Grating,
+    sigma: vec2<f32>,
+    envelope_orientation: f32, // in radians
+    amplitude_scale: f32, // normalises the peak (see `GaborMaterial::uniforms`)
+};
+
+@group(0) @binding(1)
+var<storage, read> geoms: array<Uniforms>;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let uniforms = geoms[in.geom_index];
+    let carrier = uniforms.carrier;
+    let frequency = grating_frequency(carrier, pixel_size(in.position_org));
+
+    let centre = (uniforms.bbox.min + uniforms.bbox.max) / 2.0;
+    let d = in.position_org - centre;
+
+    // the carrier, relative to the centre of the bounding box
+    let direction = grating_direction(carrier);
+    let t = dot(d, direction) * frequency + carrier.phase / (2.0 * PI);
+
+    // the envelope, rotated independently of the carrier
+    let c = cos(uniforms.envelope_orientation);
+    let s = sin(uniforms.envelope_orientation);
+    let p = vec2<f32>(c * d.x + s * d.y, -s * d.x + c * d.y) / uniforms.sigma;
+    let envelope = exp(-0.5 * dot(p, p));
+
+    let amplitude = envelope * wave(t, carrier.waveform) * uniforms.amplitude_scale;
+
+    let colour = vec4<f32>(carrier.mean.rgb * (1.0 + carrier.contrast * amplitude), carrier.mean.a);
+    return apply_filters(colour * in.tint, in);
+}
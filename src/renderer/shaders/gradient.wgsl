@@ -1,37 +1,101 @@
-struct VertexOutput {
-    @location(0) position: vec2<f32>,
-    @location(1) tex_coords: vec2<f32>,
-};
+const PI: f32 = 3.14159265358979323846264338327950288;
 
 struct Uniforms {
-    // the direction of the linear gradient
-    rotation: f32,
+    transform: mat4x4<f32>,
+    bbox: BBox,
+    filters: FilterRange,
+    centre: vec2<f32>, // relative to the centre of the bounding box
+    rotation: f32, // in radians
+    gradient_type: u32, // 0: linear, 1: radial, 2: conic
+    extent_mode: u32, // 0: absolute, 1: relative, 2: fill
+    extent_value: f32,
+    repeat: u32, // 0: clamp, 1: repeat
+    _padding: u32,
 };
 
-@group(0) @binding(0)
-var<uniform> uniforms: Uniforms;
+@group(0) @binding(1)
+var<storage, read> geoms: array<Uniforms>;
 
-// this is a 1x512 ramp texture
-@group(0) @binding(2)
+// the colour ramp, which is a single row or column of pixels
+@group(1) @binding(0)
 var texture: texture_2d<f32>;
 
-@group(0) @binding(3)
+@group(1) @binding(1)
 var texture_sampler: sampler;
 
+// computes the extent of the gradient, i.e. its length (linear), radius (radial) or angle in
+// radians (conic), given the size of a pixel in the coordinates of the primitive
+fn extent(uniforms: Uniforms, centre: vec2<f32>, direction: vec2<f32>, pixel: f32) -> f32 {
+    let size = uniforms.bbox.max - uniforms.bbox.min;
+
+    // the full extent of the bounding box, as seen from the centre of the bounding box
+    var full: f32;
+    if (uniforms.gradient_type == 0u) {
+        full = abs(size.x * direction.x) + abs(size.y * direction.y);
+    } else if (uniforms.gradient_type == 1u) {
+        full = length(size) / 2.0;
+    } else {
+        full = 2.0 * PI;
+    }
+
+    if (uniforms.extent_mode == 0u) {
+        // absolute extent in pixels (or degrees)
+        return select(uniforms.extent_value * pixel, radians(uniforms.extent_value), uniforms.gradient_type == 2u);
+    } else if (uniforms.extent_mode == 1u) {
+        // relative extent
+        return uniforms.extent_value * full;
+    }
+
+    // fill the bounding box, as seen from the centre of the gradient
+    if (uniforms.gradient_type == 0u) {
+        // twice the largest distance of a corner along the gradient, as the gradient is centred
+        var largest = 0.0;
+        for (var i = 0u; i < 4u; i++) {
+            let corner = select(uniforms.bbox.min, uniforms.bbox.max, vec2<bool>((i & 1u) == 1u, (i & 2u) == 2u));
+            largest = max(largest, abs(dot(corner - centre, direction)));
+        }
+        return 2.0 * largest;
+    } else if (uniforms.gradient_type == 1u) {
+        // the distance to the farthest corner
+        return length(max(abs(uniforms.bbox.min - centre), abs(uniforms.bbox.max - centre)));
+    }
+    return full;
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    // rotate the gradient
-    let rotation = uniforms.rotation;
-    let cos_r = cos(rotation);
-    let sin_r = sin(rotation);
-
-    // compute the texture coordinate
-    let tex_coords = in.tex_coords;
-    let x = tex_coords.x * cos_r - tex_coords.y * sin_r;
-    let y = tex_coords.x * sin_r + tex_coords.y * cos_r;
-
-    // sample the texture
-    return textureSample(texture, texture_sampler, vec2<f32>(x, y));
-}
+    let uniforms = geoms[in.geom_index];
+    let pixel = pixel_size(in.position_org);
+
+    let centre = (uniforms.bbox.min + uniforms.bbox.max) / 2.0 + uniforms.centre;
+    let direction = vec2<f32>(cos(uniforms.rotation), sin(uniforms.rotation));
+
+    // the position relative to the centre, rotated so that the gradient runs along the x-axis
+    let d = in.position_org - centre;
+    let p = vec2<f32>(dot(d, direction), dot(d, vec2<f32>(-direction.y, direction.x)));
 
+    // the position along the gradient, where 0 is the first and 1 the last colour
+    var t: f32;
+    if (uniforms.gradient_type == 0u) {
+        t = p.x / extent(uniforms, centre, direction, pixel) + 0.5;
+    } else if (uniforms.gradient_type == 1u) {
+        t = length(p) / extent(uniforms, centre, direction, pixel);
+    } else {
+        let angle = atan2(p.y, p.x);
+        t = select(angle, angle + 2.0 * PI, angle < 0.0) / extent(uniforms, centre, direction, pixel);
+    }
 
+    if (uniforms.repeat == 1u) {
+        t = fract(t);
+    } else {
+        t = clamp(t, 0.0, 1.0);
+    }
+
+    // sample the ramp (the coordinate is the same along both axes, so that horizontal and
+    // vertical ramps work alike)
+    let colour = textureSample(texture, texture_sampler, vec2<f32>(t, t));
+
+    // return red if a mode is invalid
+    let invalid = uniforms.gradient_type > 2u || uniforms.extent_mode > 2u || uniforms.repeat > 1u;
+    return apply_filters(select(colour, vec4<f32>(1.0, 0.0, 0.0, 1.0), invalid) * in.tint, in);
+}
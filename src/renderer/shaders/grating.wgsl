@@ -0,0 +1,25 @@
+struct Uniforms {
+    transform: mat4x4<f32>,
+    bbox: BBox,
+    filters: FilterRange,
+    grating: Grating,
+};
+
+@group(0) @binding(1)
+var<storage, read> geoms: array<Uniforms>;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let grating = geoms[in.geom_index].grating;
+    let bbox = geoms[in.geom_index].bbox;
+    let frequency = grating_frequency(grating, pixel_size(in.position_org));
+
+    // the position along the grating, relative to the centre of the bounding box
+    let centre = (bbox.min + bbox.max) / 2.0;
+    let x = dot(in.position_org - centre, grating_direction(grating));
+
+    let t = x * frequency + grating.phase / (2.0 * PI);
+    let colour = vec4<f32>(grating.mean.rgb * (1.0 + grating.contrast * wave(t, grating.waveform)), grating.mean.a);
+
+    return apply_filters(colour * in.tint, in);
+}
@@ -0,0 +1,39 @@
+struct Uniforms {
+    transform: mat4x4<f32>,
+    bbox: BBox,
+    filters: FilterRange,
+    colour: vec4<f32>,
+    background: vec4<f32>,
+    spacing: f32,
+    width: f32,
+    angle: f32, // in radians
+    crossed: u32,
+};
+
+@group(0) @binding(1)
+var<storage, read> geoms: array<Uniforms>;
+
+// returns true if a position at `x` across the lines is on a line, where the lines are centred on
+// multiples of the spacing
+fn on_line(x: f32, spacing: f32, width: f32) -> bool {
+    return abs(fract(x / spacing + 0.5) - 0.5) * spacing < width / 2.0;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let uniforms = geoms[in.geom_index];
+
+    // one line passes through the centre of the bounding box
+    let centre = (uniforms.bbox.min + uniforms.bbox.max) / 2.0;
+    let d = in.position_org - centre;
+    let along = vec2<f32>(cos(uniforms.angle), sin(uniforms.angle));
+    let across = vec2<f32>(-along.y, along.x);
+
+    var line = on_line(dot(d, across), uniforms.spacing, uniforms.width);
+    if (uniforms.crossed == 1u) {
+        line = line || on_line(dot(d, along), uniforms.spacing, uniforms.width);
+    }
+
+    let colour = select(uniforms.background, uniforms.colour, line);
+    return apply_filters(colour * in.tint, in);
+}
@@ -0,0 +1,31 @@
+struct Uniforms {
+    transform: mat4x4<f32>,
+    bbox: BBox,
+    filters: FilterRange,
+    mean: vec4<f32>,
+    grain_size: f32, // in pixels
+    contrast: f32,
+    _padding: vec2<u32>,
+};
+
+@group(0) @binding(1)
+var<storage, read> geoms: array<Uniforms>;
+
+@group(1) @binding(0)
+var noise: texture_2d<f32>;
+
+@group(1) @binding(1)
+var noise_sampler: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let uniforms = geoms[in.geom_index];
+
+    // one texel of the noise per grain, starting at the corner of the bounding box (the sampler
+    // repeats the noise, which tiles seamlessly)
+    let grain = (in.position_org - uniforms.bbox.min) / (uniforms.grain_size * pixel_size(in.position_org));
+    let value = textureSample(noise, noise_sampler, grain / vec2<f32>(textureDimensions(noise))).rgb;
+
+    let colour = vec4<f32>(uniforms.mean.rgb * (1.0 + uniforms.contrast * value), uniforms.mean.a);
+    return apply_filters(colour * in.tint, in);
+}
@@ -0,0 +1,29 @@
+struct Uniforms {
+    transform: mat4x4<f32>,
+    bbox: BBox,
+    filters: FilterRange,
+    components: array<Grating, 2>,
+};
+
+@group(0) @binding(1)
+var<storage, read> geoms: array<Uniforms>;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let uniforms = geoms[in.geom_index];
+    let size = pixel_size(in.position_org);
+
+    // both gratings are relative to the centre of the bounding box
+    let centre = (uniforms.bbox.min + uniforms.bbox.max) / 2.0;
+
+    // the sum of the two gratings around the average of their means
+    var colour = (uniforms.components[0].mean + uniforms.components[1].mean) / 2.0;
+    for (var i = 0; i < 2; i++) {
+        let grating = geoms[in.geom_index].components[i];
+        let x = dot(in.position_org - centre, grating_direction(grating));
+        let t = x * grating_frequency(grating, size) + grating.phase / (2.0 * PI);
+        colour += vec4<f32>(grating.mean.rgb * grating.contrast * wave(t, grating.waveform), 0.0);
+    }
+
+    return apply_filters(colour * in.tint, in);
+}
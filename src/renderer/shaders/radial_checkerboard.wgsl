@@ -0,0 +1,48 @@
+const PI: f32 = 3.14159265358979323846264338327950288;
+
+struct Uniforms {
+    transform: mat4x4<f32>,
+    bbox: BBox,
+    filters: FilterRange,
+    colours: array<vec4<f32>, 2>,
+    inner_radius: f32,
+    outer_radius: f32,
+    rings: u32,
+    wedges: u32,
+    ring_phase: f32, // in checks
+    wedge_phase: f32, // in checks
+    ring_spacing: u32, // 0: linear, 1: logarithmic
+};
+
+@group(0) @binding(1)
+var<storage, read> geoms: array<Uniforms>;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let uniforms = geoms[in.geom_index];
+
+    // polar coordinates around the centre of the bounding box
+    let centre = (uniforms.bbox.min + uniforms.bbox.max) / 2.0;
+    let d = in.position_org - centre;
+    let r = length(d);
+    if (r < uniforms.inner_radius || r > uniforms.outer_radius) {
+        discard;
+    }
+
+    // the position in rings, from the inner to the outer radius
+    var ring = 0.0;
+    if (uniforms.ring_spacing == 1u) {
+        ring = log(r / uniforms.inner_radius) / log(uniforms.outer_radius / uniforms.inner_radius);
+    } else {
+        ring = (r - uniforms.inner_radius) / (uniforms.outer_radius - uniforms.inner_radius);
+    }
+    // the outer radius belongs to the last ring
+    ring = min(ring * f32(uniforms.rings), f32(uniforms.rings) - 0.5) + uniforms.ring_phase;
+
+    // the position in wedges, counter-clockwise from the positive x axis
+    let wedge = fract(atan2(d.y, d.x) / (2.0 * PI)) * f32(uniforms.wedges) + uniforms.wedge_phase;
+
+    let check = vec2<i32>(floor(vec2<f32>(ring, wedge)));
+    let colour = select(uniforms.colours[0], uniforms.colours[1], ((check.x + check.y) & 1) == 1);
+    return apply_filters(colour * in.tint, in);
+}
@@ -1,35 +1,45 @@
+// The vertex shader is shared by all materials. Each material appends its fragment shader, which
+// declares the per-geom `Uniforms` struct (starting with `transform`, `bbox` and `filters`) and
+// the `geoms` array of the current draw call, and passes its output through `apply_filters`.
+
 struct VertexInput {
     @location(0) position: vec2<f32>,
     @location(1) normal: vec2<f32>,
     @location(2) tex_coords: vec2<f32>,
+    @location(3) geom_index: u32,
+};
+
+struct InstanceInput {
+    @location(4) transform_0: vec4<f32>,
+    @location(5) transform_1: vec4<f32>,
+    @location(6) transform_2: vec4<f32>,
+    @location(7) tint: vec4<f32>,
 };
 
 struct VertexOutput {
     @builtin(position) position_px: vec4<f32>,
     @location(0) position_org: vec2<f32>,
     @location(1) tex_coords: vec2<f32>,
+    @location(2) @interpolate(flat) geom_index: u32,
+    // the output of the fragment shader has to be multiplied with the tint of the instance
+    @location(3) tint: vec4<f32>,
 };
 
-struct ScreenUniforms {
-    width: u32,
-    height: u32,
-};
-
-@group(0) @binding(0)
-var<uniform> screen_uniforms: ScreenUniforms;
-
-@group(0) @binding(1)
-var<uniform> transform: mat4x4<f32>;
-
 @vertex
 fn vs_main(
     in: VertexInput,
+    instance: InstanceInput,
 ) -> VertexOutput {
+    let transform = geoms[in.geom_index].transform;
+
+    // transform the vertex position by the instance transform
+    let instance_transform = mat3x3<f32>(instance.transform_0.xyz, instance.transform_1.xyz, instance.transform_2.xyz);
+    let position = (instance_transform * vec3(in.position, 1.0)).xy;
 
     // transform the vertex position to clip space (from pixel space), using the screen size
     var new_position = vec2<f32>(
-        (in.position.x / f32(screen_uniforms.width)) * -2.0,
-        (in.position.y / f32(screen_uniforms.height)) * -2.0
+        (position.x / f32(screen_uniforms.width)) * -2.0,
+        (position.y / f32(screen_uniforms.height)) * -2.0
     );
 
     // transform the vertex position
@@ -40,5 +50,19 @@ fn vs_main(
         vec4<f32>(new_position.xy, 0.0, 1.0),
         vec2<f32>(in.position.xy),
         vec2<f32>(new_position.xy),
+        in.geom_index,
+        instance.tint,
     );
 }
+
+// applies the pixel filters of the geom to the output of its material
+fn apply_filters(colour: vec4<f32>, in: VertexOutput) -> vec4<f32> {
+    let uniforms = geoms[in.geom_index];
+    return apply_filter_range(colour, uniforms.filters, in.position_org, uniforms.bbox);
+}
+
+// returns the size of a pixel in the coordinates of the primitive (assuming uniform scaling). This
+// must be called in uniform control flow.
+fn pixel_size(position: vec2<f32>) -> f32 {
+    return sqrt(abs(determinant(mat2x2<f32>(dpdx(position), dpdy(position)))));
+}
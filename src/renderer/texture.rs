@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use super::error::RendererError;
 use super::helpers::{CacheEntry, Cacheable, Fingerprint};
 use image::{DynamicImage, Rgba32FImage, RgbaImage};
 
@@ -94,7 +95,9 @@ impl Texture {
         }
     }
 
-    pub fn update_image(&mut self, image: DynamicImage) {
+    /// Replaces the image of a texture backed by an 8-bit image. The new image must have the same
+    /// size as the old one.
+    pub fn update_image(&mut self, image: DynamicImage) -> Result<(), RendererError> {
         match self {
             Self::RgbaImageTexture {
                 image: old_image,
@@ -102,14 +105,17 @@ impl Texture {
                 ..
             } => {
                 let new_image = image.into_rgba8();
-                if old_image.dimensions() == new_image.dimensions() {
-                    *fingerprint = rand::random();
-                    *old_image = Arc::new(new_image);
-                } else {
-                    panic!("Image dimensions do not match");
+                if old_image.dimensions() != new_image.dimensions() {
+                    return Err(RendererError::TextureSizeMismatch {
+                        expected: old_image.dimensions(),
+                        actual: new_image.dimensions(),
+                    });
                 }
+                *fingerprint = rand::random();
+                *old_image = Arc::new(new_image);
+                Ok(())
             }
-            _ => panic!("Texture is not backed by an image"),
+            _ => Err(RendererError::NotAnImageTexture),
         }
     }
 
@@ -141,8 +147,8 @@ impl Texture {
     /// Returns the image data as a byte slice.
     pub fn data(&self) -> &[u8] {
         match self {
-            Self::RgbaImageTexture { image, .. } => &image,
-            Self::Rgba32FImageTexture { image, .. } => bytemuck::cast_slice(&image),
+            Self::RgbaImageTexture { image, .. } => image,
+            Self::Rgba32FImageTexture { image, .. } => bytemuck::cast_slice(image),
             Self::RawTexture { buffer, .. } => buffer,
         }
     }
@@ -13,3 +13,129 @@ pub struct TextureUniforms {
     pub size_value_x: f32,
     pub size_value_y: f32,
 }
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GradientUniforms {
+    pub centre: [f32; 2],
+    /// The rotation of the gradient in radians.
+    pub rotation: f32,
+    pub gradient_type: u32,
+    pub extent_mode: u32,
+    pub extent_value: f32,
+    pub repeat: u32,
+    pub _padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GratingUniforms {
+    pub mean: [f32; 4],
+    pub frequency: f32,
+    pub frequency_mode: u32,
+    /// The phase in radians.
+    pub phase: f32,
+    /// The orientation in radians.
+    pub orientation: f32,
+    pub contrast: f32,
+    pub waveform: u32,
+    pub _padding: [u32; 2],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GaborUniforms {
+    pub carrier: GratingUniforms,
+    pub sigma: [f32; 2],
+    /// The orientation of the envelope in radians.
+    pub envelope_orientation: f32,
+    /// The factor by which the amplitude is scaled, which normalises the peak if requested.
+    pub amplitude_scale: f32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct NoiseUniforms {
+    pub mean: [f32; 4],
+    /// The size of a grain in pixels.
+    pub grain_size: f32,
+    pub contrast: f32,
+    pub _padding: [u32; 2],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CheckerboardUniforms {
+    pub colours: [[f32; 4]; 2],
+    pub check_size: [f32; 2],
+    /// The phase in checks.
+    pub phase: [f32; 2],
+    /// The rotation in radians.
+    pub rotation: f32,
+    pub _padding: [u32; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct RadialCheckerboardUniforms {
+    pub colours: [[f32; 4]; 2],
+    pub inner_radius: f32,
+    pub outer_radius: f32,
+    pub rings: u32,
+    pub wedges: u32,
+    /// The radial phase in rings.
+    pub ring_phase: f32,
+    /// The angular phase in wedges.
+    pub wedge_phase: f32,
+    pub ring_spacing: u32,
+    pub _padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PlaidUniforms {
+    pub components: [GratingUniforms; 2],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct HatchUniforms {
+    pub colour: [f32; 4],
+    pub background: [f32; 4],
+    pub spacing: f32,
+    pub width: f32,
+    /// The angle in radians.
+    pub angle: f32,
+    pub crossed: u32,
+}
+
+/// The range of the filters of a geom in the filter buffer.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct FilterRange {
+    pub start: u32,
+    pub count: u32,
+    pub _padding: [u32; 2],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct FilterUniforms {
+    /// The kind of filter, see `PixelFilter::uniforms`.
+    pub kind: u32,
+    pub _padding: [u32; 3],
+    /// The parameters of the filter. Their meaning depends on the kind of filter.
+    pub params: [[f32; 4]; 5],
+}
+
+/// The uniforms of a pass that applies a spatial filter.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct FilterPassUniforms {
+    /// The pixel filters that are applied after the convolution.
+    pub filters: FilterRange,
+    pub kernel_width: u32,
+    pub kernel_height: u32,
+    /// The position of the layer texture on the render target, in pixels.
+    pub origin: [i32; 2],
+}
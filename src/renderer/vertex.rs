@@ -1,6 +1,10 @@
 use lyon::{geom::Angle, path::Winding};
 
-use super::geometry::{LineCap, LineJoin, Point2D, Primitive, TessellationOptions};
+use super::error::RendererError;
+use super::geometry::{
+    rectangle_corners, FillRule, Instance, LineCap, LineJoin, Point2D, Primitive,
+    TessellationOptions, Transformation,
+};
 
 /// A vertex with position, color, and texture coordinates.
 #[repr(C)]
@@ -9,6 +13,8 @@ pub struct GPUVertex {
     pub position: Point2D,
     pub normal: [f32; 2],
     pub tex_coords: [f32; 2],
+    /// The index of the geom within its draw call, used to look up its uniforms.
+    pub geom_index: u32,
 }
 
 impl GPUVertex {
@@ -29,15 +35,75 @@ impl GPUVertex {
                 format: wgpu::VertexFormat::Float32x2,
                 shader_location: 2,
             },
+            wgpu::VertexAttribute {
+                offset: 24,
+                format: wgpu::VertexFormat::Uint32,
+                shader_location: 3,
+            },
         ]
     }
 }
 
+/// The per-instance data of a draw call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GPUInstance {
+    pub transform: Transformation,
+    /// The tint, with the opacity multiplied into the alpha channel.
+    pub tint: [f32; 4],
+}
+
+impl GPUInstance {
+    /// An instance that does not change the geom, used for geoms that are not instanced.
+    pub const IDENTITY: Self = Self {
+        transform: Transformation::IDENTITY,
+        tint: [1.0; 4],
+    };
+
+    pub fn desc() -> &'static [wgpu::VertexAttribute] {
+        &[
+            wgpu::VertexAttribute {
+                offset: 0,
+                format: wgpu::VertexFormat::Float32x4,
+                shader_location: 4,
+            },
+            wgpu::VertexAttribute {
+                offset: 16,
+                format: wgpu::VertexFormat::Float32x4,
+                shader_location: 5,
+            },
+            wgpu::VertexAttribute {
+                offset: 32,
+                format: wgpu::VertexFormat::Float32x4,
+                shader_location: 6,
+            },
+            wgpu::VertexAttribute {
+                offset: 64,
+                format: wgpu::VertexFormat::Float32x4,
+                shader_location: 7,
+            },
+        ]
+    }
+}
+
+impl From<&Instance> for GPUInstance {
+    fn from(instance: &Instance) -> Self {
+        let tint = instance.tint;
+        Self {
+            transform: instance.transform,
+            tint: [tint.r, tint.g, tint.b, tint.a * instance.opacity],
+        }
+    }
+}
+
 pub struct GPUGeometryBuffer {
     pub vertices: Vec<GPUVertex>,
     pub indices: Vec<u32>,
     pub indices_offsets: Vec<u32>,
     pub indices_sizes: Vec<u32>,
+    /// The number of vertices and indices before the current geometry, used to roll back
+    /// aborted geometries.
+    geometry_start: (usize, usize),
 }
 
 impl lyon::tessellation::geometry_builder::GeometryBuilder for GPUGeometryBuffer {
@@ -48,14 +114,20 @@ impl lyon::tessellation::geometry_builder::GeometryBuilder for GPUGeometryBuffer
         c: lyon::tessellation::VertexId,
     ) {
         // Add the three vertices to the current geometry
-        self.indices.push(a.0 as u32);
-        self.indices.push(b.0 as u32);
-        self.indices.push(c.0 as u32);
+        self.indices.push(a.0);
+        self.indices.push(b.0);
+        self.indices.push(c.0);
+    }
+
+    fn begin_geometry(&mut self) {
+        self.geometry_start = (self.vertices.len(), self.indices.len());
     }
 
     fn abort_geometry(&mut self) {
-        // we cannot abort the geometry and need to panic
-        panic!("Something went wrong while tessellating a geometry, cannot proceed.");
+        // remove everything that was added for the failed geometry
+        let (vertices_len, indices_len) = self.geometry_start;
+        self.vertices.truncate(vertices_len);
+        self.indices.truncate(indices_len);
     }
 }
 
@@ -72,6 +144,7 @@ impl lyon::tessellation::geometry_builder::FillGeometryBuilder for GPUGeometryBu
             },
             normal: [0.0, 0.0],
             tex_coords: [vertex.position().x, vertex.position().y],
+            geom_index: 0,
         });
 
         Ok(lyon::tessellation::VertexId(
@@ -96,6 +169,7 @@ impl lyon::tessellation::geometry_builder::StrokeGeometryBuilder for GPUGeometry
             },
             normal: [vertex.normal().x, vertex.normal().y],
             tex_coords: [vertex.position().x, vertex.position().y],
+            geom_index: 0,
         });
 
         Ok(lyon::tessellation::VertexId(
@@ -104,6 +178,12 @@ impl lyon::tessellation::geometry_builder::StrokeGeometryBuilder for GPUGeometry
     }
 }
 
+impl Default for GPUGeometryBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl GPUGeometryBuffer {
     pub fn new() -> Self {
         Self {
@@ -111,35 +191,76 @@ impl GPUGeometryBuffer {
             indices: Vec::new(),
             indices_offsets: Vec::new(),
             indices_sizes: Vec::new(),
+            geometry_start: (0, 0),
         }
     }
 
-    pub fn tesselate(&mut self, primitive: &Primitive, options: &TessellationOptions) {
+    /// Appends previously tessellated vertices and indices. The indices are relative to the first
+    /// of the given vertices.
+    pub fn append(&mut self, vertices: &[GPUVertex], indices: &[u32], geom_index: u32) {
+        let vertices_offset = self.vertices.len() as u32;
+
+        self.indices_offsets.push(self.indices.len() as u32);
+        self.indices_sizes.push(indices.len() as u32);
+
+        self.vertices
+            .extend(vertices.iter().map(|vertex| GPUVertex {
+                geom_index,
+                ..*vertex
+            }));
+        self.indices
+            .extend(indices.iter().map(|i| i + vertices_offset));
+    }
+
+    /// Tessellates a primitive. `pixel_scale` is the number of pixels per unit of the primitive on
+    /// screen and is used to resolve `Tolerance::Auto`.
+    pub fn tesselate(
+        &mut self,
+        primitive: &Primitive,
+        options: &TessellationOptions,
+        pixel_scale: f32,
+    ) -> Result<(), RendererError> {
+        // lyon asserts that all coordinates are finite
+        if !primitive.is_finite() {
+            return Err(RendererError::NonFinitePrimitive);
+        }
+
         match options {
-            TessellationOptions::Fill => {
-                self.tesselate_fill(primitive, options);
+            TessellationOptions::Fill { .. } => {
+                self.tesselate_fill(primitive, options, pixel_scale)
             }
             TessellationOptions::Stroke { .. } => {
-                self.tesselate_stroke(primitive, options);
+                self.tesselate_stroke(primitive, options, pixel_scale)
             }
         }
     }
 
-    pub fn tesselate_stroke(&mut self, primitive: &Primitive, options: &TessellationOptions) {
+    pub fn tesselate_stroke(
+        &mut self,
+        primitive: &Primitive,
+        options: &TessellationOptions,
+        pixel_scale: f32,
+    ) -> Result<(), RendererError> {
         // create the tessellator
         let mut tessellator = lyon::tessellation::StrokeTessellator::new();
 
         // create the tessellation options
-        let lyon_options = match options {
+        let (lyon_options, dash_pattern, dash_offset) = match options {
             TessellationOptions::Stroke {
                 line_width,
                 line_join,
                 start_cap,
                 end_cap,
-                ..
+                miter_limit,
+                dash_pattern,
+                dash_offset,
+                tolerance,
             } => {
                 let mut o = lyon::tessellation::StrokeOptions::default();
+                o.tolerance = tolerance.resolve(pixel_scale);
                 o.line_width = *line_width;
+                o.miter_limit =
+                    miter_limit.max(lyon::tessellation::StrokeOptions::MINIMUM_MITER_LIMIT);
                 o.end_cap = match end_cap {
                     LineCap::Butt => lyon::tessellation::LineCap::Butt,
                     LineCap::Round => lyon::tessellation::LineCap::Round,
@@ -154,164 +275,592 @@ impl GPUGeometryBuffer {
                     LineJoin::Miter => lyon::tessellation::LineJoin::Miter,
                     LineJoin::Round => lyon::tessellation::LineJoin::Round,
                     LineJoin::Bevel => lyon::tessellation::LineJoin::Bevel,
-                    LineJoin::MiterClip => todo!(),
+                    LineJoin::MiterClip => lyon::tessellation::LineJoin::MiterClip,
                 };
-                o
+                (o, dash_pattern, *dash_offset)
+            }
+            _ => {
+                return Err(RendererError::InvalidTessellationOptions { expected: "stroke" });
             }
-            _ => panic!("Invalid tessellation options"),
         };
 
-        // add the offset
+        if dash_pattern.iter().any(|l| *l < 0.0 || !l.is_finite()) {
+            return Err(RendererError::InvalidDashPattern {
+                reason: "it has a negative or non-finite entry",
+            });
+        }
+
         let indices_offset = self.indices.len() as u32;
 
+        // dashed and solid strokes follow the same outline
+        let mut path = primitive_path(primitive);
+        if is_dashed(dash_pattern) {
+            // split the outline into dashes, each of which is stroked as an open sub-path
+            path = dash_path(&path, dash_pattern, dash_offset, lyon_options.tolerance)?;
+        }
+        tessellator.tessellate_path(&path, &lyon_options, self)?;
+
+        // add the offset and size (only once tessellation succeeded, failed geometries are
+        // rolled back by `abort_geometry`)
         self.indices_offsets.push(indices_offset);
+        self.indices_sizes
+            .push((self.indices.len() - indices_offset as usize) as u32);
+
+        Ok(())
+    }
+
+    pub fn tesselate_fill(
+        &mut self,
+        primitive: &Primitive,
+        options: &TessellationOptions,
+        pixel_scale: f32,
+    ) -> Result<(), RendererError> {
+        // create the tessellator
+        let mut tessellator = lyon::tessellation::FillTessellator::new();
+
+        // create the tessellation options
+        let lyon_options = match options {
+            TessellationOptions::Fill {
+                fill_rule,
+                tolerance,
+            } => lyon::tessellation::FillOptions::tolerance(tolerance.resolve(pixel_scale))
+                .with_fill_rule(match fill_rule {
+                    FillRule::EvenOdd => lyon::tessellation::FillRule::EvenOdd,
+                    FillRule::NonZero => lyon::tessellation::FillRule::NonZero,
+                }),
+            _ => return Err(RendererError::InvalidTessellationOptions { expected: "fill" }),
+        };
+
+        let indices_offset = self.indices.len() as u32;
+        let vertices_offset = self.vertices.len();
 
         match primitive {
             Primitive::Circle { center, radius } => {
-                tessellator
-                    .tessellate_circle(
-                        lyon::math::Point::new(center.x, center.y),
-                        *radius,
-                        &lyon_options,
-                        self,
-                    )
-                    .unwrap();
+                tessellator.tessellate_circle(
+                    lyon::math::Point::new(center.x, center.y),
+                    *radius,
+                    &lyon_options,
+                    self,
+                )?;
+            }
+            Primitive::Rectangle { a, b, rotation } if *rotation == 0.0 => {
+                tessellator.tessellate_rectangle(
+                    &lyon::math::Box2D::new(
+                        lyon::math::Point::new(a.x, a.y),
+                        lyon::math::Point::new(b.x, b.y),
+                    ),
+                    &lyon_options,
+                    self,
+                )?;
             }
-            Primitive::Rectangle { a, b } => {
-                tessellator
-                    .tessellate_rectangle(
-                        &lyon::math::Box2D::new(
-                            lyon::math::Point::new(a.x, a.y),
-                            lyon::math::Point::new(b.x, b.y),
-                        ),
-                        &lyon_options,
-                        self,
-                    )
-                    .unwrap();
+            Primitive::Rectangle { a, b, rotation } => {
+                let points = to_lyon_points(&rectangle_corners(a, b, *rotation));
+                tessellator.tessellate_polygon(
+                    lyon::path::Polygon {
+                        points: &points,
+                        closed: true,
+                    },
+                    &lyon_options,
+                    self,
+                )?;
             }
-            Primitive::Ellipse { center, radii } => {
-                let rot_rad = Angle::degrees(0.0);
-                tessellator
-                    .tessellate_ellipse(
-                        lyon::math::Point::new(center.x, center.y),
-                        lyon::math::Vector::new(radii.x, radii.y),
-                        rot_rad,
-                        Winding::Positive,
-                        &lyon_options,
-                        self,
-                    )
-                    .unwrap();
+            Primitive::RoundedRectangle {
+                a,
+                b,
+                radius,
+                rotation,
+            } => {
+                let path = rounded_rectangle_path(a, b, *radius, *rotation);
+                tessellator.tessellate_path(&path, &lyon_options, self)?;
             }
-            Primitive::Line { a, b } => {
-                tessellator
-                    .tessellate_polygon(
-                        lyon::path::Polygon {
-                            points: &[
-                                lyon::math::Point::new(a.x, a.y),
-                                lyon::math::Point::new(b.x, b.y),
-                            ],
-                            closed: false,
-                        },
-                        &lyon_options,
-                        self,
-                    )
-                    .unwrap();
+            Primitive::Ellipse {
+                center,
+                radii,
+                rotation,
+            } => {
+                let rot_rad = Angle::degrees(*rotation);
+                tessellator.tessellate_ellipse(
+                    lyon::math::Point::new(center.x, center.y),
+                    lyon::math::Vector::new(radii.x, radii.y),
+                    rot_rad,
+                    Winding::Positive,
+                    &lyon_options,
+                    self,
+                )?;
             }
-            _ => {
-                todo!();
+            Primitive::Triangle { a, b, c } => {
+                let points = to_lyon_points(&[*a, *b, *c]);
+                tessellator.tessellate_polygon(
+                    lyon::path::Polygon {
+                        points: &points,
+                        closed: true,
+                    },
+                    &lyon_options,
+                    self,
+                )?;
+            }
+            Primitive::Polygon { points } => {
+                let points = to_lyon_points(points);
+                tessellator.tessellate_polygon(
+                    lyon::path::Polygon {
+                        points: &points,
+                        closed: true,
+                    },
+                    &lyon_options,
+                    self,
+                )?;
+            }
+            Primitive::Path { path } => {
+                // open sub-paths are filled as if they were closed
+                tessellator.tessellate_path(&path.to_lyon(), &lyon_options, self)?;
+            }
+            Primitive::Arc { .. } | Primitive::Sector { .. } | Primitive::Annulus { .. } => {
+                let path = primitive
+                    .outline()
+                    .expect("Arc-based primitives always have an outline.");
+                tessellator.tessellate_path(&path.to_lyon(), &lyon_options, self)?;
+            }
+            Primitive::Compound { .. } => {
+                // all contours are filled together so that the fill rule can cut out holes
+                tessellator.tessellate_path(&primitive_path(primitive), &lyon_options, self)?;
+            }
+            Primitive::Line { .. } => {
+                // a line has no interior, so filling it would not produce any triangles
+                return Err(RendererError::UnsupportedTessellation {
+                    primitive: "line",
+                    kind: "fill",
+                });
+            }
+        }
+
+        // update the texture coordinates by scaling them to the range [0, 1]
+        let new_vertices = &mut self.vertices[vertices_offset..];
+        if !new_vertices.is_empty() {
+            let min_x = new_vertices
+                .iter()
+                .map(|v| v.position.x)
+                .reduce(f32::min)
+                .unwrap();
+            let max_x = new_vertices
+                .iter()
+                .map(|v| v.position.x)
+                .reduce(f32::max)
+                .unwrap();
+
+            let min_y = new_vertices
+                .iter()
+                .map(|v| v.position.y)
+                .reduce(f32::min)
+                .unwrap();
+            let max_y = new_vertices
+                .iter()
+                .map(|v| v.position.y)
+                .reduce(f32::max)
+                .unwrap();
+
+            // degenerate fills (e.g. a rectangle without height) have no extent along an axis,
+            // which would give NaN coordinates
+            let normalise = |value: f32, min: f32, max: f32| {
+                if max > min {
+                    (value - min) / (max - min)
+                } else {
+                    0.0
+                }
+            };
+            for vertex in new_vertices {
+                vertex.tex_coords[0] = normalise(vertex.position.x, min_x, max_x);
+                vertex.tex_coords[1] = normalise(vertex.position.y, min_y, max_y);
             }
         }
 
-        // add the size
+        // add the offset and size (only once tessellation succeeded, failed geometries are
+        // rolled back by `abort_geometry`)
+        self.indices_offsets.push(indices_offset);
         self.indices_sizes
             .push((self.indices.len() - indices_offset as usize) as u32);
+
+        Ok(())
     }
+}
 
-    pub fn tesselate_fill(&mut self, primitive: &Primitive, _options: &TessellationOptions) {
-        // create the tessellator
-        let mut tessellator = lyon::tessellation::FillTessellator::new();
+/// Builds the outline of a primitive as a lyon path.
+fn primitive_path(primitive: &Primitive) -> lyon::path::Path {
+    let mut builder = lyon::path::Path::builder();
+    match primitive {
+        Primitive::Circle { center, radius } => {
+            builder.add_circle(
+                lyon::math::Point::new(center.x, center.y),
+                *radius,
+                Winding::Positive,
+            );
+        }
+        Primitive::Rectangle { a, b, rotation } => {
+            builder.add_polygon(lyon::path::Polygon {
+                points: &to_lyon_points(&rectangle_corners(a, b, *rotation)),
+                closed: true,
+            });
+        }
+        Primitive::RoundedRectangle {
+            a,
+            b,
+            radius,
+            rotation,
+        } => return rounded_rectangle_path(a, b, *radius, *rotation),
+        Primitive::Ellipse {
+            center,
+            radii,
+            rotation,
+        } => {
+            builder.add_ellipse(
+                lyon::math::Point::new(center.x, center.y),
+                lyon::math::Vector::new(radii.x, radii.y),
+                Angle::degrees(*rotation),
+                Winding::Positive,
+            );
+        }
+        Primitive::Triangle { a, b, c } => {
+            builder.add_polygon(lyon::path::Polygon {
+                points: &to_lyon_points(&[*a, *b, *c]),
+                closed: true,
+            });
+        }
+        Primitive::Polygon { points } => {
+            builder.add_polygon(lyon::path::Polygon {
+                points: &to_lyon_points(points),
+                closed: true,
+            });
+        }
+        Primitive::Line { a, b } => {
+            builder.add_polygon(lyon::path::Polygon {
+                points: &to_lyon_points(&[*a, *b]),
+                closed: false,
+            });
+        }
+        Primitive::Path { path } => return path.to_lyon(),
+        Primitive::Arc { .. } | Primitive::Sector { .. } | Primitive::Annulus { .. } => {
+            return primitive
+                .outline()
+                .expect("Arc-based primitives always have an outline.")
+                .to_lyon();
+        }
+        Primitive::Compound { contours } => {
+            let paths: Vec<lyon::path::Path> = contours.iter().map(primitive_path).collect();
+            let slices: Vec<lyon::path::PathSlice> = paths.iter().map(|p| p.as_slice()).collect();
+            builder.extend_from_paths(&slices);
+        }
+    }
+    builder.build()
+}
 
-        // create the tessellation options
-        let lyon_options = lyon::tessellation::FillOptions::tolerance(0.01);
+/// Returns true if the dash pattern produces any gaps. The entries must be finite and
+/// non-negative, which `tesselate_stroke` checks.
+fn is_dashed(dash_pattern: &[f32]) -> bool {
+    if dash_pattern.len() % 2 == 1 {
+        // odd patterns are repeated, so every entry is used as a gap once
+        dash_pattern.iter().any(|l| *l > 0.0)
+    } else {
+        dash_pattern.iter().skip(1).step_by(2).any(|l| *l > 0.0)
+    }
+}
 
-        // add the offset
-        let indices_offset = self.indices.len() as u32;
+/// The maximum number of dashes and gaps that a dashed stroke may consist of.
+const MAX_DASH_SEGMENTS: usize = 100_000;
 
-        self.indices_offsets.push(indices_offset);
+/// Splits every sub-path of `path` into dashes. As in SVG, the dash pattern restarts at the
+/// beginning of every sub-path. Patterns whose period is below the tolerance, or that would
+/// produce more than `MAX_DASH_SEGMENTS` dashes and gaps, are rejected.
+fn dash_path(
+    path: &lyon::path::Path,
+    dash_pattern: &[f32],
+    dash_offset: f32,
+    tolerance: f32,
+) -> Result<lyon::path::Path, RendererError> {
+    // a pattern with an odd number of entries is repeated to get an even number
+    let pattern: Vec<f32> = if dash_pattern.len() % 2 == 1 {
+        dash_pattern.repeat(2)
+    } else {
+        dash_pattern.to_vec()
+    };
+    let period: f32 = pattern.iter().sum();
+    if period < tolerance {
+        return Err(RendererError::InvalidDashPattern {
+            reason: "its period is below the tolerance",
+        });
+    }
 
-        match primitive {
-            Primitive::Circle { center, radius } => {
-                tessellator
-                    .tessellate_circle(
-                        lyon::math::Point::new(center.x, center.y),
-                        *radius,
-                        &lyon_options,
-                        self,
-                    )
-                    .unwrap();
+    let too_many_dashes = RendererError::InvalidDashPattern {
+        reason: "it produces too many dashes",
+    };
+    let mut segments = 0;
+
+    let mut output = lyon::path::Path::builder();
+
+    for sub_path in sub_paths(path) {
+        let measurements =
+            lyon::algorithms::measure::PathMeasurements::from_path(&sub_path, tolerance);
+        let mut sampler =
+            measurements.create_sampler(&sub_path, lyon::algorithms::measure::SampleType::Distance);
+        let length = sampler.length();
+        if ((length / period) as usize).saturating_mul(pattern.len()) > MAX_DASH_SEGMENTS {
+            return Err(too_many_dashes);
+        }
+
+        // find the position in the pattern at the start of the sub-path
+        let mut index = 0;
+        let mut remaining = pattern[0] - dash_offset.rem_euclid(period);
+        while remaining < 0.0 {
+            index = (index + 1) % pattern.len();
+            remaining += pattern[index];
+        }
+
+        let mut distance = 0.0;
+        while distance < length {
+            // entries below the precision of `distance` do not advance it, so the number of
+            // iterations is bounded as well
+            segments += 1;
+            if segments > MAX_DASH_SEGMENTS {
+                return Err(too_many_dashes);
             }
-            Primitive::Rectangle { a, b } => {
-                tessellator
-                    .tessellate_rectangle(
-                        &lyon::math::Box2D::new(
-                            lyon::math::Point::new(a.x, a.y),
-                            lyon::math::Point::new(b.x, b.y),
-                        ),
-                        &lyon_options,
-                        self,
-                    )
-                    .unwrap();
+
+            let end = (distance + remaining).min(length);
+            if index % 2 == 0 {
+                if end > distance {
+                    sampler.split_range(distance..end, &mut output);
+                } else {
+                    // zero-length dashes only consist of their caps, which the stroker only
+                    // emits for a degenerate segment
+                    let position = sampler.sample(distance).position();
+                    if position.x.is_finite() && position.y.is_finite() {
+                        output.begin(position);
+                        output.line_to(position);
+                        output.end(false);
+                    }
+                }
             }
-            Primitive::Ellipse { center, radii, .. } => {
-                let rot_rad = Angle::degrees(0.0);
-                tessellator
-                    .tessellate_ellipse(
-                        lyon::math::Point::new(center.x, center.y),
-                        lyon::math::Vector::new(radii.x, radii.y),
-                        rot_rad,
-                        Winding::Positive,
-                        &lyon_options,
-                        self,
-                    )
-                    .unwrap();
+            distance = end;
+            index = (index + 1) % pattern.len();
+            remaining = pattern[index];
+        }
+    }
+
+    Ok(output.build())
+}
+
+/// Splits a path into its sub-paths. Degenerate (zero-length) segments are dropped because
+/// they cannot be measured.
+fn sub_paths(path: &lyon::path::Path) -> Vec<lyon::path::Path> {
+    let mut sub_paths = vec![];
+    let mut builder = lyon::path::Path::builder();
+    for event in path.iter() {
+        match event {
+            lyon::path::Event::Begin { at } => {
+                builder.begin(at);
             }
-            _ => {
-                panic!("Unsupported primitive type for tesselation");
+            lyon::path::Event::Line { from, to } => {
+                if from != to {
+                    builder.line_to(to);
+                }
+            }
+            lyon::path::Event::Quadratic { from, ctrl, to } => {
+                if from != to || from != ctrl {
+                    builder.quadratic_bezier_to(ctrl, to);
+                }
+            }
+            lyon::path::Event::Cubic {
+                from,
+                ctrl1,
+                ctrl2,
+                to,
+            } => {
+                if from != to || from != ctrl1 || from != ctrl2 {
+                    builder.cubic_bezier_to(ctrl1, ctrl2, to);
+                }
+            }
+            lyon::path::Event::End { last, first, close } => {
+                builder.end(close && last != first);
+                sub_paths
+                    .push(std::mem::replace(&mut builder, lyon::path::Path::builder()).build());
             }
         }
-        // update the texture coordinates by scaling them to the range [0, 1]
-        let min_x = self
-            .vertices
-            .iter()
-            .map(|v| v.position.x)
-            .reduce(f32::min)
-            .unwrap();
-        let max_x = self
-            .vertices
-            .iter()
-            .map(|v| v.position.x)
-            .reduce(f32::max)
-            .unwrap();
-
-        let min_y = self
-            .vertices
-            .iter()
-            .map(|v| v.position.y)
-            .reduce(f32::min)
-            .unwrap();
-        let max_y = self
-            .vertices
-            .iter()
-            .map(|v| v.position.y)
-            .reduce(f32::max)
-            .unwrap();
-
-        for vertex in &mut self.vertices {
-            vertex.tex_coords[0] = (vertex.position.x - min_x) / (max_x - min_x);
-            vertex.tex_coords[1] = (vertex.position.y - min_y) / (max_y - min_y);
+    }
+    sub_paths
+}
+
+/// Converts a list of points into lyon points.
+fn to_lyon_points(points: &[Point2D]) -> Vec<lyon::math::Point> {
+    points
+        .iter()
+        .map(|p| lyon::math::Point::new(p.x, p.y))
+        .collect()
+}
+
+/// Builds a closed path for a rectangle with rounded corners, rotated by `rotation` degrees
+/// around its centre.
+fn rounded_rectangle_path(
+    a: &Point2D,
+    b: &Point2D,
+    radius: f32,
+    rotation: f32,
+) -> lyon::path::Path {
+    let mut builder = lyon::path::Path::builder();
+    builder.add_rounded_rectangle(
+        &lyon::math::Box2D::new(
+            lyon::math::Point::new(a.x.min(b.x), a.y.min(b.y)),
+            lyon::math::Point::new(a.x.max(b.x), a.y.max(b.y)),
+        ),
+        &lyon::path::builder::BorderRadii::new(radius),
+        Winding::Positive,
+    );
+    let path = builder.build();
+
+    if rotation == 0.0 {
+        return path;
+    }
+
+    let center = lyon::math::Vector::new((a.x + b.x) / 2.0, (a.y + b.y) / 2.0);
+    let transform = lyon::math::Transform::translation(-center.x, -center.y)
+        .then_rotate(Angle::degrees(rotation))
+        .then_translate(center);
+    path.transformed(&transform)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn degenerate_fills_have_finite_tex_coords() {
+        let primitives = [
+            Primitive::Rectangle {
+                a: Point2D::new(0.0, 5.0),
+                b: Point2D::new(10.0, 5.0),
+                rotation: 0.0,
+            },
+            Primitive::Polygon {
+                points: vec![
+                    Point2D::new(0.0, 0.0),
+                    Point2D::new(5.0, 5.0),
+                    Point2D::new(10.0, 10.0),
+                ],
+            },
+        ];
+        for primitive in &primitives {
+            let mut buffer = GPUGeometryBuffer::new();
+            buffer
+                .tesselate(primitive, &TessellationOptions::simple_fill(), 1.0)
+                .unwrap();
+            assert!(buffer
+                .vertices
+                .iter()
+                .all(|v| v.tex_coords.iter().all(|t| t.is_finite())));
         }
-        // add the size
-        self.indices_sizes
-            .push((self.indices.len() - indices_offset as usize) as u32);
+    }
+
+    #[test]
+    fn empty_compounds_are_rejected() {
+        let primitive = Primitive::Compound { contours: vec![] };
+        let mut buffer = GPUGeometryBuffer::new();
+        let result = buffer.tesselate(&primitive, &TessellationOptions::simple_fill(), 1.0);
+        assert!(matches!(result, Err(RendererError::NonFinitePrimitive)));
+    }
+
+    #[test]
+    fn lines_cannot_be_filled() {
+        let line = Primitive::Line {
+            a: Point2D::new(0.0, 0.0),
+            b: Point2D::new(20.0, 0.0),
+        };
+        let mut buffer = GPUGeometryBuffer::new();
+        let result = buffer.tesselate(&line, &TessellationOptions::simple_fill(), 1.0);
+        assert!(matches!(
+            result,
+            Err(RendererError::UnsupportedTessellation { .. })
+        ));
+        assert!(buffer
+            .tesselate(&line, &TessellationOptions::simple_line(1.0), 1.0)
+            .is_ok());
+    }
+
+    #[test]
+    fn invalid_dash_patterns_are_rejected() {
+        let line = Primitive::Line {
+            a: Point2D::new(0.0, 0.0),
+            b: Point2D::new(20.0, 0.0),
+        };
+        let mut buffer = GPUGeometryBuffer::new();
+        for length in [-2.0, f32::NAN, f32::INFINITY] {
+            let options = TessellationOptions::dashed_line(1.0, vec![4.0, length]);
+            let result = buffer.tesselate(&line, &options, 1.0);
+            assert!(matches!(
+                result,
+                Err(RendererError::InvalidDashPattern { .. })
+            ));
+        }
+        assert!(buffer.indices.is_empty());
+    }
+
+    #[test]
+    fn pathological_dash_patterns_terminate() {
+        let line = Primitive::Line {
+            a: Point2D::new(0.0, 0.0),
+            b: Point2D::new(1e4, 0.0),
+        };
+        let path = primitive_path(&line);
+
+        // dashes below the precision of the distance along the path
+        let result = dash_path(&path, &[1e-4, 1e-4], 0.0, 0.1);
+        assert!(matches!(
+            result,
+            Err(RendererError::InvalidDashPattern { .. })
+        ));
+
+        // representable, but far too many dashes
+        let result = dash_path(&path, &[0.05, 0.05], 0.0, 0.01);
+        assert!(matches!(
+            result,
+            Err(RendererError::InvalidDashPattern { .. })
+        ));
+
+        // a single tiny entry does not stall the pattern
+        assert!(dash_path(&path, &[1e-9, 100.0], 0.0, 0.1).is_ok());
+        assert!(dash_path(&path, &[100.0, 1e-9], 0.0, 0.1).is_ok());
+    }
+
+    #[test]
+    fn dash_patterns() {
+        // patterns without a gap are solid lines
+        assert!(is_dashed(&[4.0, 2.0]));
+        assert!(is_dashed(&[0.0, 2.0]));
+        assert!(!is_dashed(&[]));
+        assert!(!is_dashed(&[4.0, 0.0]));
+        // odd patterns are repeated, so every entry is also a gap
+        assert!(is_dashed(&[4.0]));
+        assert!(is_dashed(&[0.0, 0.0, 2.0]));
+        assert!(!is_dashed(&[0.0]));
+
+        // the dashes of a line, shifted by the offset
+        let line = Primitive::Line {
+            a: Point2D::new(0.0, 0.0),
+            b: Point2D::new(20.0, 0.0),
+        };
+        let path = primitive_path(&line);
+        let dashes = |dash_pattern: &[f32], dash_offset: f32| -> Vec<(f32, f32)> {
+            dash_path(&path, dash_pattern, dash_offset, 0.01)
+                .unwrap()
+                .iter()
+                .filter_map(|event| match event {
+                    lyon::path::Event::End { first, last, .. } => {
+                        Some((first.x.round(), last.x.round()))
+                    }
+                    _ => None,
+                })
+                .collect()
+        };
+        assert_eq!(dashes(&[6.0, 4.0], 0.0), [(0.0, 6.0), (10.0, 16.0)]);
+        assert_eq!(
+            dashes(&[6.0, 4.0], 2.0),
+            [(0.0, 4.0), (8.0, 14.0), (18.0, 20.0)]
+        );
+        assert_eq!(dashes(&[5.0], 0.0), [(0.0, 5.0), (10.0, 15.0)]);
     }
 }